@@ -5,7 +5,7 @@ use structopt::{clap::AppSettings, StructOpt};
 
 use bstree_file_readonly::{
   cliargs::{colargs::*, coltypeargs::*, csvargs::*, memsize::*, mkargs::*},
-  mk::MkIndex,
+  mk::{MkIndex, StrByteSizeScan, TypeInference},
 };
 
 #[derive(Debug, StructOpt)]
@@ -14,6 +14,8 @@ use bstree_file_readonly::{
 ///
 /// Examples:
 ///     ./mkbst -h --input resources/tests/vals.csv test --id-type u4 --val-type u4
+/// Example: types inferred from a pre-scan of the input file:
+///     ./mkbst -h --input resources/tests/vals.csv test --infer-types
 /// Example: cat 2mass.csv | ./mkbst -hnx 2mass.jmag -i oid --id-type u4 -v Jmag --val-type f4
 /// Example: single column containing e.g. a magnitude:
 ///   cat Jmag.txt | ./mkbstree 2mass.jmag --id-type u4 --val-type f4
@@ -25,8 +27,46 @@ struct Args {
 }
 
 impl Args {
-  fn exec(self) -> Result<<SubArgs as FnUsingReader>::Output, std::io::Error> {
+  fn exec(mut self) -> Result<<SubArgs as FnUsingReader>::Output, std::io::Error> {
     self.sub_args.check()?;
+    if self.sub_args.coltype_args.infer_types() {
+      if !self.csv_args.has_input_file() {
+        return Err(Error::new(
+          ErrorKind::Other,
+          "Option --infer-types requires an input file (not stdin) to pre-scan it",
+        ));
+      }
+      let inference = TypeInference::new(
+        &self.sub_args.col_args,
+        self.sub_args.mkalgo_args.normalize,
+        self.sub_args.coltype_args.id_type().cloned(),
+        self.sub_args.coltype_args.val_type().cloned(),
+      );
+      let types = self.csv_args.call_once(inference)?;
+      eprintln!(
+        " - inferred types: --id-type {} --val-type {}",
+        types.id_type(),
+        types.val_type()
+      );
+      self.sub_args.coltype_args.set_types(types);
+    }
+    if self.sub_args.mkalgo_args.truncation == Truncation::Auto {
+      if !self.csv_args.has_input_file() {
+        return Err(Error::new(
+          ErrorKind::Other,
+          "The 'auto' truncation policy requires an input file (not stdin) to pre-scan it",
+        ));
+      }
+      let scan = StrByteSizeScan::new(
+        &self.sub_args.col_args,
+        self.sub_args.mkalgo_args.normalize,
+      );
+      let (id_byte_size, val_byte_size) = self.csv_args.call_once(scan)?;
+      self
+        .sub_args
+        .coltype_args
+        .set_str_byte_sizes(id_byte_size, val_byte_size);
+    }
     self.csv_args.call_once(self.sub_args)
   }
 }
@@ -77,7 +117,8 @@ impl FnUsingReader for SubArgs {
       self.mkalgo_args,
       self.mem_args,
     );
-    self.coltype_args.to_idval().exec(process)
+    // Types not supported by the compiled code (e.g. inferred ones) are handled in memory
+    self.coltype_args.to_idval()?.exec_any(process)
   }
 }
 
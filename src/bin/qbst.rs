@@ -1,19 +1,23 @@
 use std::{
   fs::File,
-  io::{BufRead, BufReader, Cursor, Error, ErrorKind},
-  path::PathBuf,
+  io::{self, BufRead, BufReader, BufWriter, Cursor, Error, ErrorKind, Write},
+  path::{Path, PathBuf},
 };
 
-#[cfg(not(target_arch = "wasm32"))]
-use memmap::{Mmap, MmapOptions};
 use structopt::{clap::AppSettings, StructOpt};
 
 use bstree_file_readonly::{
-  bstree::{read_meta, BSTreeMeta, SubTreeR},
-  cliargs::mode::*,
+  bstree::BSTreeFile,
+  cliargs::{
+    mode::*,
+    output::{Coded, Distance, OutputFormat, ResultWriter},
+  },
+  dist::Dist,
+  norm::StrNorm,
   rw::ReadWrite,
-  visitors::*,
-  Entry, Id, IdVal, Process, Val,
+  source::{BlockSource, RawBlock, DEFAULT_CACHE_BYTE_SIZE},
+  visitors::Neigbhour,
+  Entry, Id, IdVal, PageLayout, Process, Val,
 };
 
 #[derive(Debug, StructOpt)]
@@ -25,6 +29,15 @@ use bstree_file_readonly::{
 pub struct Args {
   /// File storing the binary search tree
   input: PathBuf,
+  #[structopt(long)]
+  /// Read the file with positioned reads (pread) instead of memory mapping it
+  pread: bool,
+  #[structopt(long, default_value = "1024")]
+  /// Size of the cache of the last read pages, in KB (used with 'pread' only)
+  cache: usize,
+  #[structopt(long, default_value = "csv", possible_values = &OutputFormat::NAMES)]
+  /// Format of the results: csv, tsv, json, ndjson or bin (raw little-endian rows, see the doc)
+  format: OutputFormat,
   #[structopt(subcommand)]
   mode: Mode,
 }
@@ -38,36 +51,36 @@ impl Args {
 
   #[cfg(not(target_arch = "wasm32"))]
   fn exec(self) -> Result<(), std::io::Error> {
-    // Get the size of the file
-    //let metadata = fs::metadata(&self.input)?;
-    //let byte_size = metadata.len();
-    // Open the file and read the metadata part
-    let file = File::open(&self.input)?;
-    let mmap = unsafe { MmapOptions::new().map(&file)? };
-    let (_version, data_starting_byte, meta) = read_meta(&mmap)?;
-    let idval = &meta.types;
-    idval.exec(Query {
-      mode: self.mode,
-      meta: &meta,
-      mmap: &mmap,
-      data_starting_byte,
-    })
+    if self.pread {
+      let bstree = BSTreeFile::open_pread(&self.input, self.cache * 1024)?;
+      bstree.types().exec_any(Query {
+        mode: self.mode,
+        format: self.format,
+        bstree: &bstree,
+      })
+    } else {
+      let bstree = BSTreeFile::open(&self.input)?;
+      bstree.types().exec_any(Query {
+        mode: self.mode,
+        format: self.format,
+        bstree: &bstree,
+      })
+    }
   }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-struct Query<'a> {
+struct Query<'a, S: BlockSource> {
   mode: Mode,
-  meta: &'a BSTreeMeta,
-  mmap: &'a Mmap,
-  data_starting_byte: usize,
+  format: OutputFormat,
+  bstree: &'a BSTreeFile<S>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-impl<'a> Process for Query<'a> {
+impl<'a, S: BlockSource> Process for Query<'a, S> {
   type Output = ();
 
-  fn exec<I, V, D, IRW, VRW>(
+  fn exec<I, V, U, D, IRW, VRW>(
     self,
     _types: IdVal,
     id_rw: IRW,
@@ -77,101 +90,172 @@ impl<'a> Process for Query<'a> {
   where
     I: Id,
     V: Val,
-    D: Fn(&V, &V) -> V,
+    U: Dist,
+    D: Fn(&V, &V) -> U + Sync,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let mut out = ResultWriter::new(BufWriter::new(io::stdout().lock()), self.format);
+    let res = self.write_results(&mut out, id_rw, val_rw, dist);
+    // Always end the output, even in case of error (e.g. to close a JSON array)
+    out.finish()?;
+    res
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'a, S: BlockSource> Query<'a, S> {
+  fn write_results<I, V, U, D, IRW, VRW, W>(
+    self,
+    out: &mut ResultWriter<W>,
+    id_rw: IRW,
+    val_rw: VRW,
+    dist: D,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    U: Dist,
+    D: Fn(&V, &V) -> U + Sync,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    W: Write,
   {
-    let root = self.meta.get_root();
+    let norm = *self.bstree.norm();
     match self.mode {
       Mode::Info => {
-        println!("{}", serde_json::to_string_pretty(&self.meta)?);
+        println!("{}", serde_json::to_string_pretty(self.bstree.meta())?);
         Ok(())
       }
       Mode::Data { limit } => {
+        if self.bstree.page_layout() != PageLayout::Interleaved {
+          return Err(Error::new(
+            ErrorKind::Other,
+            "The data mode is only supported for files having interleaved pages",
+          ));
+        }
         let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
-        println!("id,val");
-        match limit {
-          Some(limit) => {
-            for kv in self.mmap[self.data_starting_byte..]
-              .chunks_exact(entry_byte_size)
-              .take(limit)
-            {
-              let mut cursor = Cursor::new(kv);
-              let id = id_rw.read(&mut cursor)?;
-              let val = val_rw.read(&mut cursor)?;
-              println!("{},{}", id, val);
-            }
-          }
-          None => {
-            for kv in self.mmap[self.data_starting_byte..].chunks_exact(entry_byte_size) {
-              let mut cursor = Cursor::new(kv);
-              let id = id_rw.read(&mut cursor)?;
-              let val = val_rw.read(&mut cursor)?;
-              println!("{},{}", id, val);
-            }
+        let data = self.bstree.data_block();
+        let n_entries = limit.unwrap_or(usize::MAX).min(self.bstree.n_entries());
+        let data_byte_size = n_entries * entry_byte_size;
+        // Read the data by chunks of (at most) the cache size
+        let chunk_byte_size = entry_byte_size * (DEFAULT_CACHE_BYTE_SIZE / entry_byte_size);
+        out.header(&["id", "val"])?;
+        for from in (0..data_byte_size).step_by(chunk_byte_size) {
+          let chunk = data.slice(from..data_byte_size.min(from + chunk_byte_size)).bytes()?;
+          for kv in chunk.chunks_exact(entry_byte_size) {
+            let mut cursor = Cursor::new(kv);
+            let id = id_rw.read(&mut cursor)?;
+            let val = val_rw.read(&mut cursor)?;
+            out.entry(&Entry { id, val }, &id_rw, &val_rw)?;
           }
         }
         Ok(())
       }
       Mode::GetFirst { val_or_file } => match val_or_file {
         ValOrFile::Value { value } => {
-          let v = value
-            .parse::<V>()
-            .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
-          let visitor = VisitorExact::new(v);
-          let visitor = root.visit(
-            visitor,
-            &self.mmap[self.data_starting_byte..],
+          let v = norm.parse::<V>(&value)?;
+          out.header(&["id", "val"])?;
+          if let Some(entry) = self.bstree.get(v, &id_rw, &val_rw)? {
+            out.entry(&entry, &id_rw, &val_rw)?;
+          }
+          Ok(())
+        }
+        ValOrFile::List { ref file, echo, .. } if val_or_file.batch_order().is_some() => {
+          let order = val_or_file.batch_order().unwrap();
+          let values = read_values::<V>(file, &norm)?;
+          let res = self.bstree.get_batch(
+            values.clone(),
+            order,
+            val_or_file.n_threads(),
             &id_rw,
             &val_rw,
           )?;
-          println!("id,val");
-          if let Some(Entry { id, val }) = visitor.entry {
-            println!("{},{}", id, val)
+          out.header(get_columns(echo))?;
+          for (i, entry) in res {
+            write_get(out, echo, i, &values[i], entry.as_ref(), &id_rw, &val_rw)?;
           }
           Ok(())
         }
-        ValOrFile::List { file } => {
+        ValOrFile::List {
+          file,
+          threads,
+          echo,
+          ..
+        } if threads > 1 => {
+          out.header(get_columns(echo))?;
+          for_each_block::<V, _>(&file, &norm, threads, |offset, values| {
+            let res = self
+              .bstree
+              .get_list(values.clone(), threads, &id_rw, &val_rw)?;
+            for (i, (v, entry)) in values.iter().zip(res).enumerate() {
+              write_get(out, echo, offset + i, v, entry.as_ref(), &id_rw, &val_rw)?;
+            }
+            Ok(())
+          })
+        }
+        ValOrFile::List { file, echo, .. } => {
           let file = File::open(file)?;
-          println!("id,val");
-          for line in BufReader::new(file).lines() {
+          out.header(get_columns(echo))?;
+          for (i, line) in BufReader::new(file).lines().enumerate() {
             let value = line?;
-            let v = value
-              .parse::<V>()
-              .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
-            let visitor = VisitorExact::new(v);
-            let visitor = root.visit(
-              visitor,
-              &self.mmap[self.data_starting_byte..],
-              &id_rw,
-              &val_rw,
-            )?;
-            if let Some(Entry { id, val }) = visitor.entry {
-              println!("{},{}", id, val)
-            }
+            let v = norm.parse::<V>(&value)?;
+            let entry = self.bstree.get(v.clone(), &id_rw, &val_rw)?;
+            write_get(out, echo, i, &v, entry.as_ref(), &id_rw, &val_rw)?;
           }
           Ok(())
         }
       },
+      Mode::All {
+        value: _,
+        list: Some(file),
+        limit,
+        count,
+      } => {
+        let limit = limit.unwrap_or(usize::MAX);
+        out.header(if count {
+          &["index", "count"]
+        } else {
+          &["index", "id", "val"]
+        })?;
+        for (i, line) in BufReader::new(File::open(file)?).lines().enumerate() {
+          let v = parse_required::<V>(Some(&line?), "value", &norm)?;
+          let index = i as u64;
+          if count {
+            let n_entries = self.bstree.count_all(v, limit, &id_rw, &val_rw)?;
+            out.row(&[&index, &(n_entries as u64)])?;
+          } else {
+            for e in self.bstree.all(v, limit, &id_rw, &val_rw)? {
+              out.row(&[
+                &index,
+                &Coded::new(&e.id, &id_rw),
+                &Coded::new(&e.val, &val_rw),
+              ])?;
+            }
+          }
+        }
+        Ok(())
+      }
       Mode::All {
         value,
+        list: None,
         limit,
         count,
       } => {
-        let v = value
-          .parse::<V>()
-          .map_err(|_| Error::new(ErrorKind::Other, "Wrong valie type"))?;
+        let v = parse_required::<V>(value.as_deref(), "value", &norm)?;
         if count {
-          let v = VisitorAllCount::new(v, limit.unwrap_or(std::usize::MAX));
-          let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-          println!("count");
-          println!("{}", v.n_entries);
+          let n_entries = self
+            .bstree
+            .count_all(v, limit.unwrap_or(usize::MAX), &id_rw, &val_rw)?;
+          out.header(&["count"])?;
+          out.row(&[&(n_entries as u64)])?;
         } else {
-          let v = VisitorAll::new(v, limit.unwrap_or(std::usize::MAX));
-          let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-          println!("id,val");
-          for Entry { id, val } in v.entries {
-            println!("{},{}", id, val);
+          let entries = self
+            .bstree
+            .all(v, limit.unwrap_or(usize::MAX), &id_rw, &val_rw)?;
+          out.header(&["id", "val"])?;
+          for entry in entries {
+            out.entry(&entry, &id_rw, &val_rw)?;
           }
         }
         Ok(())
@@ -179,106 +263,385 @@ impl<'a> Process for Query<'a> {
       Mode::Nn { val_or_file, d_max } => {
         let d_max = d_max
           .map(|d| {
-            d.parse::<V>()
+            d.parse::<U>()
               .map_err(|_| Error::new(ErrorKind::Other, "Wrong distance type"))
           })
           .transpose()?;
         match val_or_file {
           ValOrFile::Value { value } => {
-            let v = value
-              .parse::<V>()
-              .map_err(|_| Error::new(ErrorKind::Other, ""))?;
-            let v = VisitorNn::new(v, &dist, d_max);
-            let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-            println!("distance,id,val");
-            if let Some(Neigbhour {
-              distance: d,
-              neighbour: Entry { id, val },
-            }) = v.nn
-            {
-              println!("{},{},{}", d, id, val);
+            let v = norm.parse::<V>(&value)?;
+            out.header(&["distance", "id", "val"])?;
+            if let Some(nn) = self.bstree.nn(v, &dist, d_max, &id_rw, &val_rw)? {
+              out.neighbour(&nn, &id_rw, &val_rw)?;
+            }
+            Ok(())
+          }
+          ValOrFile::List { ref file, echo, .. } if val_or_file.batch_order().is_some() => {
+            let order = val_or_file.batch_order().unwrap();
+            let values = read_values::<V>(file, &norm)?;
+            let res = self.bstree.nn_batch(
+              values.clone(),
+              order,
+              val_or_file.n_threads(),
+              &dist,
+              d_max,
+              &id_rw,
+              &val_rw,
+            )?;
+            out.header(nn_columns(echo))?;
+            for (i, nn) in res {
+              write_nn(out, echo, i, &values[i], nn.as_ref(), &id_rw, &val_rw)?;
             }
             Ok(())
           }
-          ValOrFile::List { file } => {
+          ValOrFile::List {
+            file,
+            threads,
+            echo,
+            ..
+          } if threads > 1 => {
+            out.header(nn_columns(echo))?;
+            for_each_block::<V, _>(&file, &norm, threads, |offset, values| {
+              let res = self.bstree.nn_list(
+                values.clone(),
+                threads,
+                &dist,
+                d_max.clone(),
+                &id_rw,
+                &val_rw,
+              )?;
+              for (i, (v, nn)) in values.iter().zip(res).enumerate() {
+                write_nn(out, echo, offset + i, v, nn.as_ref(), &id_rw, &val_rw)?;
+              }
+              Ok(())
+            })
+          }
+          ValOrFile::List { file, echo, .. } => {
             let file = File::open(file)?;
-            println!("distance,id,val");
-            for line in BufReader::new(file).lines() {
+            out.header(nn_columns(echo))?;
+            for (i, line) in BufReader::new(file).lines().enumerate() {
               let value = line?;
-              let v = value
-                .parse::<V>()
-                .map_err(|_e| Error::new(ErrorKind::Other, ""))?;
-              let v = VisitorNn::new(v, &dist, d_max.clone());
-              let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-              if let Some(Neigbhour {
-                distance: d,
-                neighbour: Entry { id, val },
-              }) = v.nn
-              {
-                println!("{},{},{}", d, id, val);
-              }
+              let v = norm.parse::<V>(&value)?;
+              let nn = self.bstree.nn(v.clone(), &dist, d_max.clone(), &id_rw, &val_rw)?;
+              write_nn(out, echo, i, &v, nn.as_ref(), &id_rw, &val_rw)?;
             }
             Ok(())
           }
         }
       }
-      Mode::Knn { value, k, d_max } => {
-        let v = value
-          .parse::<V>()
-          .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
-        let v: VisitorKnn<I, V, V, _> = VisitorKnn::new(
-          v,
-          dist,
-          k as usize,
-          d_max
-            .map(|d| {
-              d.parse::<V>()
-                .map_err(|_| Error::new(ErrorKind::Other, "Wrong distance type"))
-            })
-            .transpose()?,
-        );
-        let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-        println!("distance,id,val");
-        for Neigbhour {
-          distance: d,
-          neighbour: Entry { id, val },
-        } in v.knn.into_sorted_vec().drain(..)
-        {
-          println!("{},{},{}", d, id, val);
+      Mode::Knn {
+        value,
+        list,
+        k,
+        d_max,
+      } => {
+        let d_max = d_max
+          .map(|d| {
+            d.parse::<U>()
+              .map_err(|_| Error::new(ErrorKind::Other, "Wrong distance type"))
+          })
+          .transpose()?;
+        match list {
+          Some(file) => {
+            out.header(&["index", "distance", "id", "val"])?;
+            for (i, line) in BufReader::new(File::open(file)?).lines().enumerate() {
+              let v = parse_required::<V>(Some(&line?), "value", &norm)?;
+              let knn = self
+                .bstree
+                .knn(v, &dist, k as usize, d_max.clone(), &id_rw, &val_rw)?;
+              for nn in knn {
+                out.row(&[
+                  &(i as u64),
+                  &Distance(Some(&nn.distance)),
+                  &Coded::new(&nn.neighbour.id, &id_rw),
+                  &Coded::new(&nn.neighbour.val, &val_rw),
+                ])?;
+              }
+            }
+          }
+          None => {
+            let v = parse_required::<V>(value.as_deref(), "value", &norm)?;
+            let knn = self
+              .bstree
+              .knn(v, dist, k as usize, d_max, &id_rw, &val_rw)?;
+            out.header(&["distance", "id", "val"])?;
+            for nn in knn {
+              out.neighbour(&nn, &id_rw, &val_rw)?;
+            }
+          }
+        }
+        Ok(())
+      }
+      Mode::Range {
+        list: Some(file),
+        limit,
+        count,
+        ..
+      } => {
+        let limit = limit.unwrap_or(usize::MAX);
+        out.header(if count {
+          &["index", "count"]
+        } else {
+          &["index", "id", "val"]
+        })?;
+        for (i, line) in BufReader::new(File::open(file)?).lines().enumerate() {
+          let (lo, hi) = parse_range::<V>(&line?, &norm)?;
+          let index = i as u64;
+          if count {
+            let n_entries = self.bstree.count_range(lo, hi, limit, &id_rw, &val_rw)?;
+            out.row(&[&index, &(n_entries as u64)])?;
+          } else {
+            let entries = self.bstree.range_iter(lo, hi, id_rw.clone(), val_rw.clone())?;
+            for entry in entries.take(limit) {
+              let e = entry?;
+              out.row(&[
+                &index,
+                &Coded::new(&e.id, &id_rw),
+                &Coded::new(&e.val, &val_rw),
+              ])?;
+            }
+          }
         }
         Ok(())
       }
       Mode::Range {
         lo,
         hi,
+        list: None,
+        limit,
+        count,
+      } => {
+        let lo = parse_required::<V>(lo.as_deref(), "lower bound", &norm)?;
+        let hi = parse_required::<V>(hi.as_deref(), "upper bound", &norm)?;
+        if count {
+          let n_entries = self
+            .bstree
+            .count_range(lo, hi, limit.unwrap_or(usize::MAX), &id_rw, &val_rw)?;
+          out.header(&["count"])?;
+          out.row(&[&(n_entries as u64)])?;
+        } else {
+          let entries = self.bstree.range_iter(lo, hi, id_rw.clone(), val_rw.clone())?;
+          out.header(&["id", "val"])?;
+          for entry in entries.take(limit.unwrap_or(usize::MAX)) {
+            out.entry(&entry?, &id_rw, &val_rw)?;
+          }
+        }
+        Ok(())
+      }
+      Mode::Prefix {
+        prefix,
         limit,
         count,
       } => {
-        let lo = lo
-          .parse::<V>()
-          .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
-        let hi = hi
-          .parse::<V>()
-          .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
+        let prefix = parse_required::<V>(Some(&prefix), "prefix", &norm)?;
+        let limit = limit.unwrap_or(usize::MAX);
+        if count {
+          let n_entries = self.bstree.count_prefix(prefix, limit, &id_rw, &val_rw)?;
+          out.header(&["count"])?;
+          out.row(&[&(n_entries as u64)])?;
+        } else {
+          let entries = self.bstree.prefix_iter(prefix, id_rw.clone(), val_rw.clone())?;
+          out.header(&["id", "val"])?;
+          for entry in entries.take(limit) {
+            out.entry(&entry?, &id_rw, &val_rw)?;
+          }
+        }
+        Ok(())
+      }
+      Mode::Nulls { limit, count } => {
         if count {
-          let v = VisitorRangeCount::new(lo, hi, limit.unwrap_or(std::usize::MAX));
-          let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-          println!("count");
-          println!("{}", v.n_entries);
+          out.header(&["count"])?;
+          out.row(&[&(self.bstree.n_nulls() as u64)])?;
         } else {
-          let v = VisitorRange::new(lo, hi, limit.unwrap_or(std::usize::MAX));
-          let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-          println!("id,val");
-          for Entry { id, val } in v.entries {
-            println!("{},{}", id, val);
+          out.header(&["id"])?;
+          for id in self.bstree.nulls(&id_rw)?.take(limit.unwrap_or(usize::MAX)) {
+            out.row(&[&Coded::new(&id?, &id_rw)])?;
           }
         }
         Ok(())
       }
+      Mode::Verify => {
+        let bstree = self.bstree;
+        let mut ok = true;
+        out.header(&["check", "result"])?;
+        // - file size
+        if bstree.byte_size() == bstree.expected_byte_size() {
+          out.row(&[&"file_size", &"ok"])?;
+        } else {
+          ok = false;
+          let msg = format!(
+            "error: {} bytes instead of {}",
+            bstree.byte_size(),
+            bstree.expected_byte_size()
+          );
+          out.row(&[&"file_size", &msg.as_str()])?;
+        }
+        // - checksums
+        if bstree.has_checksums() {
+          let corrupted = bstree.corrupted_blocks()?;
+          if corrupted.is_empty() {
+            out.row(&[&"block_checksums", &"ok"])?;
+          } else {
+            ok = false;
+            let msg = format!(
+              "error: corrupted blocks {}",
+              corrupted.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(" ")
+            );
+            out.row(&[&"block_checksums", &msg.as_str()])?;
+          }
+          if bstree.check_file_checksum()? {
+            out.row(&[&"file_checksum", &"ok"])?;
+          } else {
+            ok = false;
+            out.row(&[&"file_checksum", &"error"])?;
+          }
+        } else {
+          out.row(&[&"block_checksums", &"absent"])?;
+          out.row(&[&"file_checksum", &"absent"])?;
+        }
+        // - in-order traversal
+        let mut n_entries = 0_usize;
+        let mut n_unsorted = 0_usize;
+        let mut prev: Option<V> = None;
+        for entry in bstree.iter(id_rw, val_rw)? {
+          let Entry { val, .. } = entry?;
+          if let Some(prev) = &prev {
+            if prev > &val {
+              n_unsorted += 1;
+            }
+          }
+          prev = Some(val);
+          n_entries += 1;
+        }
+        if n_unsorted == 0 {
+          out.row(&[&"sorted", &"ok"])?;
+        } else {
+          ok = false;
+          let msg = format!("error: {} entries lower than their predecessor", n_unsorted);
+          out.row(&[&"sorted", &msg.as_str()])?;
+        }
+        if n_entries == bstree.n_entries() {
+          out.row(&[&"n_entries", &"ok"])?;
+        } else {
+          ok = false;
+          let msg = format!(
+            "error: {} entries instead of {}",
+            n_entries,
+            bstree.n_entries()
+          );
+          out.row(&[&"n_entries", &msg.as_str()])?;
+        }
+        if ok {
+          Ok(())
+        } else {
+          Err(Error::new(ErrorKind::Other, "File verification failed"))
+        }
+      }
     }
   }
 }
 
+/// Columns of the results of `get` on a list of values.
+fn get_columns(echo: bool) -> &'static [&'static str] {
+  if echo {
+    &["index", "query", "found", "id", "val"]
+  } else {
+    &["id", "val"]
+  }
+}
+
+/// Columns of the results of `nn` on a list of values.
+fn nn_columns(echo: bool) -> &'static [&'static str] {
+  if echo {
+    &["index", "query", "found", "distance", "id", "val"]
+  } else {
+    &["distance", "id", "val"]
+  }
+}
+
+/// Writes the result of `get` for the given query value, read from the `index`-th line of the
+/// input: without `echo`, nothing is written if no entry has been found.
+#[allow(clippy::too_many_arguments)]
+fn write_get<I, V, IRW, VRW, W>(
+  out: &mut ResultWriter<W>,
+  echo: bool,
+  index: usize,
+  query: &V,
+  entry: Option<&Entry<I, V>>,
+  id_rw: &IRW,
+  val_rw: &VRW,
+) -> Result<(), Error>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+  W: Write,
+{
+  match entry {
+    _ if echo => out.query_entry(index, query, entry, id_rw, val_rw),
+    Some(entry) => out.entry(entry, id_rw, val_rw),
+    None => Ok(()),
+  }
+}
+
+/// Writes the result of `nn` for the given query value, read from the `index`-th line of the
+/// input: without `echo`, nothing is written if no neighbour has been found.
+#[allow(clippy::too_many_arguments)]
+fn write_nn<I, V, U, IRW, VRW, W>(
+  out: &mut ResultWriter<W>,
+  echo: bool,
+  index: usize,
+  query: &V,
+  nn: Option<&Neigbhour<I, V, U>>,
+  id_rw: &IRW,
+  val_rw: &VRW,
+) -> Result<(), Error>
+where
+  I: Id,
+  V: Val,
+  U: Dist,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+  W: Write,
+{
+  match nn {
+    _ if echo => out.query_neighbour(index, query, nn, id_rw, val_rw),
+    Some(nn) => out.neighbour(nn, id_rw, val_rw),
+    None => Ok(()),
+  }
+}
+
+/// Number of values queried by each thread at once when querying a list with several threads.
+const N_VALUES_PER_THREAD: usize = 4096;
+
+/// Reads the values of the given file by blocks of `n_threads * N_VALUES_PER_THREAD` values,
+/// calling `f` on each block (together with the index of the line of its first value), so that
+/// the results can be output while the file is read.
+fn for_each_block<V, F>(file: &Path, norm: &StrNorm, n_threads: usize, mut f: F) -> Result<(), Error>
+where
+  V: Val,
+  F: FnMut(usize, Vec<V>) -> Result<(), Error>,
+{
+  let block_size = n_threads * N_VALUES_PER_THREAD;
+  let mut values = Vec::with_capacity(block_size);
+  let mut offset = 0;
+  for line in BufReader::new(File::open(file)?).lines() {
+    let value = line?;
+    values.push(
+      norm.parse::<V>(&value)?,
+    );
+    if values.len() == block_size {
+      f(offset, std::mem::replace(&mut values, Vec::with_capacity(block_size)))?;
+      offset += block_size;
+    }
+  }
+  if !values.is_empty() {
+    f(offset, values)?;
+  }
+  Ok(())
+}
+
 fn main() -> Result<(), Error> {
   // Parse command line arguments
   let args = Args::from_args();
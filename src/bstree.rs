@@ -1,23 +1,50 @@
 //! See the tree terminology here: https://en.wikipedia.org/wiki/Tree_(data_structure)
+use bincode::Options;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 #[cfg(not(target_arch = "wasm32"))]
-use memmap::{Mmap, MmapMut};
+use memmap::MmapMut;
 use serde::{self, Deserialize, Serialize};
 
 use std::{
+  borrow::Borrow,
+  fmt::{self, Display, Formatter},
   fs::OpenOptions,
   io::{Error, ErrorKind, Read, Write},
+  marker::PhantomData,
   num::ParseIntError,
-  path::PathBuf,
+  path::{Path, PathBuf},
+  thread,
 };
 
+#[cfg(all(not(target_arch = "wasm32"), any(unix, windows)))]
+use crate::source::PreadSource;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::source::MmapSource;
 use crate::{
-  cliargs::memsize::MemSizeArgs, rw::ReadWrite, visitors::*, Entry, Id, IdVal, Process, RawEntries,
-  Val,
+  cliargs::memsize::MemSizeArgs,
+  error::BSTreeError,
+  norm::StrNorm,
+  rw::{EncodedRW, ReadWrite, ValEncoding},
+  source::{BlockSource, RawBlock},
+  visitors::*,
+  Entry, Id, IdVal, PageLayout, RawEntries, RawEntriesWriter, Val,
 };
 
 const FILE_TYPE: &[u8; 10] = b"BSTreeFile";
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Byte following `FILE_TYPE` in files having an explicit format version.
+/// In legacy files, this byte is the patch number of the code version (written in reverse order).
+const FORMAT_MARKER: u8 = 0xFF;
+/// Header size of legacy files: `FILE_TYPE`, reversed code version, metadata size.
+const LEGACY_HEADER_BYTE_SIZE: usize = FILE_TYPE.len() + 3 + 2;
+/// Header size: `FILE_TYPE`, `FORMAT_MARKER`, format version, code version, metadata size.
+const HEADER_BYTE_SIZE: usize = FILE_TYPE.len() + 1 + 2 + 3 + 2;
+/// Version of the file format written by this code.
+pub const FORMAT_VERSION: FormatVersion = FormatVersion { major: 1, minor: 0 };
+/// Version of the file format of legacy files, i.e. of files without `FORMAT_MARKER`.
+const LEGACY_FORMAT_VERSION: FormatVersion = FormatVersion { major: 0, minor: 0 };
+/// Number of bytes used to store a single CRC32 checksum.
+const CHECKSUM_BYTE_SIZE: usize = 4;
 
 pub trait HasByteSize {
   /// Returns the total size of the sub-tree, in bytes.
@@ -45,11 +72,90 @@ trait SubTreeW: HasByteSize {
     T: Iterator<Item = Entry<I, V>>;
 }
 
+/// Result of the lookup of a single value in a batch walk of the tree.
+pub trait BatchHit<I: Id, V: Val>: Clone {
+  /// Tells whether the walk has to provide the entries surrounding the values not found.
+  const SURROUNDING: bool;
+  /// The value has been found in the given entry.
+  fn found(entry: Entry<I, V>) -> Self;
+  /// The value has not been found so far, it is located between the given entries (if any).
+  /// Called at each level of the walk, with entries closer and closer to the value.
+  fn between(&mut self, _lo: Option<Entry<I, V>>, _hi: Option<Entry<I, V>>) {}
+}
+
+/// A batch `get` only needs the entries having the looked-for values.
+impl<I: Id, V: Val> BatchHit<I, V> for Option<Entry<I, V>> {
+  const SURROUNDING: bool = false;
+
+  fn found(entry: Entry<I, V>) -> Self {
+    Some(entry)
+  }
+}
+
+/// Entries surrounding a value, i.e. the entries having the closest lower and higher values.
+/// Used by batch nearest neighbour queries.
+#[derive(Debug, Clone)]
+pub enum Surrounding<I: Id, V: Val> {
+  /// The value has been found
+  Found(Entry<I, V>),
+  /// Entries right below and right above the value (if any)
+  Between(Option<Entry<I, V>>, Option<Entry<I, V>>),
+}
+
+impl<I: Id, V: Val> Default for Surrounding<I, V> {
+  fn default() -> Self {
+    Surrounding::Between(None, None)
+  }
+}
+
+impl<I: Id, V: Val> BatchHit<I, V> for Surrounding<I, V> {
+  const SURROUNDING: bool = true;
+
+  fn found(entry: Entry<I, V>) -> Self {
+    Surrounding::Found(entry)
+  }
+
+  fn between(&mut self, lo: Option<Entry<I, V>>, hi: Option<Entry<I, V>>) {
+    if let Surrounding::Between(prev_lo, prev_hi) = self {
+      // Entries of a sub-tree are in between the entries surrounding the sub-tree
+      if lo.is_some() {
+        *prev_lo = lo;
+      }
+      if hi.is_some() {
+        *prev_hi = hi;
+      }
+    }
+  }
+}
+
+impl<I: Id, V: Val> Surrounding<I, V> {
+  /// Returns the nearest of the surrounding entries from the given value, provided that its
+  /// distance is not larger than `d_max`.
+  pub fn nearest<U, D>(self, value: &V, dist: &D, d_max: Option<&U>) -> Option<Neigbhour<I, V, U>>
+  where
+    U: Ord,
+    D: Fn(&V, &V) -> U,
+  {
+    let to_neighbour = |entry: Entry<I, V>| Neigbhour {
+      distance: dist(value, &entry.val),
+      neighbour: entry,
+    };
+    let nn = match self {
+      Surrounding::Found(entry) => Some(to_neighbour(entry)),
+      Surrounding::Between(lo, hi) => match (lo.map(to_neighbour), hi.map(to_neighbour)) {
+        (Some(lo), Some(hi)) => Some(if hi.distance < lo.distance { hi } else { lo }),
+        (lo, hi) => lo.or(hi),
+      },
+    };
+    nn.filter(|nn| d_max.is_none_or(|d_max| nn.distance <= *d_max))
+  }
+}
+
 pub trait SubTreeR: HasByteSize {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     value: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -57,13 +163,33 @@ pub trait SubTreeR: HasByteSize {
     I: Id,
     V: Val,
     IRW: ReadWrite<Type = I>,
-    VRW: ReadWrite<Type = V>;
+    VRW: ReadWrite<Type = V>,
+    B: RawBlock;
+
+  /// Same as `get` for each of the given values, sorted in increasing order: the tree is walked
+  /// only once, each node being read once for all the values it may contain.
+  /// The result for `values[i]` is put in `res[i]` (see `BatchHit`).
+  fn get_batch<I, V, IRW, VRW, R, B>(
+    &self,
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock;
 
   /// Visit from the largest to the smallest value
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn visit_desc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -72,13 +198,14 @@ pub trait SubTreeR: HasByteSize {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
-    T: Visitor<I = I, V = V>;
+    T: Visitor<I = I, V = V>,
+    B: RawBlock;
 
   /// Visit starting with a binary search of the visitor central value
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -87,13 +214,14 @@ pub trait SubTreeR: HasByteSize {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
-    T: Visitor<I = I, V = V>;
+    T: Visitor<I = I, V = V>,
+    B: RawBlock;
 
   /// Visit from the smallest to the largest value
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -102,7 +230,74 @@ pub trait SubTreeR: HasByteSize {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
-    T: Visitor<I = I, V = V>;
+    T: Visitor<I = I, V = V>,
+    B: RawBlock;
+}
+
+/// Location of an entry in a sub-tree, all indices being in number of entries from the start
+/// of the sub-tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryLocation {
+  /// Index of the entry
+  pub index: usize,
+  /// Number of entries stored contiguously from `index` in increasing rank order (including
+  /// the entry itself)
+  pub run: usize,
+  /// Index of the first entry of the page containing the entry
+  pub page_from: usize,
+  /// Index following the one of the last entry of the page containing the entry
+  pub page_to: usize,
+}
+
+impl EntryLocation {
+  fn new(index: usize, run: usize, page_from: usize, page_to: usize) -> EntryLocation {
+    EntryLocation {
+      index,
+      run,
+      page_from,
+      page_to,
+    }
+  }
+
+  /// Returns the same location, in a sub-tree starting `offset` entries before this one.
+  fn shift(self, offset: usize) -> EntryLocation {
+    EntryLocation::new(
+      offset + self.index,
+      self.run,
+      offset + self.page_from,
+      offset + self.page_to,
+    )
+  }
+}
+
+/// Rank based access to the entries of a sub-tree, the rank of an entry being its position
+/// in the ordered sequence of all the entries of the sub-tree.
+pub trait SubTreeRank: HasByteSize {
+  /// Number of entries in the sub-tree.
+  fn n_entries(&self) -> usize {
+    self.byte_size(1)
+  }
+
+  /// Returns the location of the entry having the given rank.
+  fn locate(&self, rank: usize) -> EntryLocation;
+
+  /// Returns the rank of the first entry for which the predicate is `false`, assuming
+  /// that the predicate is `true` for all values lower than a given value and `false` for all
+  /// others (e.g. `|v| v < &lo` or `|v| v <= &hi`).
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock;
 }
 
 #[derive(Debug)]
@@ -157,10 +352,10 @@ impl SubTreeW for Root {
 }
 
 impl SubTreeR for Root {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     value: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -169,6 +364,7 @@ impl SubTreeR for Root {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -180,10 +376,36 @@ impl SubTreeR for Root {
     }
   }
 
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn get_batch<I, V, IRW, VRW, R, B>(
+    &self,
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock,
+  {
+    // Simple delegation
+    match &self {
+      Root::L1Leaf(leaf) => leaf.get_batch(values, res, raw_entries, id_rw, val_rw),
+      Root::L1Node(node) => node.get_batch(values, res, raw_entries, id_rw, val_rw),
+      Root::LDNode(node) => node.get_batch(values, res, raw_entries, id_rw, val_rw),
+      Root::RootL1Node(node) => node.get_batch(values, res, raw_entries, id_rw, val_rw),
+      Root::RootLDNode(node) => node.get_batch(values, res, raw_entries, id_rw, val_rw),
+    }
+  }
+
+  fn visit_desc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -193,6 +415,7 @@ impl SubTreeR for Root {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -204,10 +427,10 @@ impl SubTreeR for Root {
     }
   }
 
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -217,6 +440,7 @@ impl SubTreeR for Root {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -228,10 +452,10 @@ impl SubTreeR for Root {
     }
   }
 
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -241,6 +465,7 @@ impl SubTreeR for Root {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -253,6 +478,42 @@ impl SubTreeR for Root {
   }
 }
 
+impl SubTreeRank for Root {
+  fn locate(&self, rank: usize) -> EntryLocation {
+    match &self {
+      Root::L1Leaf(node) => node.locate(rank),
+      Root::L1Node(node) => node.locate(rank),
+      Root::LDNode(node) => node.locate(rank),
+      Root::RootL1Node(node) => node.locate(rank),
+      Root::RootLDNode(node) => node.locate(rank),
+    }
+  }
+
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock,
+  {
+    match &self {
+      Root::L1Leaf(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+      Root::L1Node(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+      Root::LDNode(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+      Root::RootL1Node(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+      Root::RootLDNode(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub enum SubTree {
   L1Leaf(L1Leaf),
@@ -295,10 +556,10 @@ impl SubTreeW for SubTree {
 }
 
 impl SubTreeR for SubTree {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     value: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -307,6 +568,7 @@ impl SubTreeR for SubTree {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -316,10 +578,34 @@ impl SubTreeR for SubTree {
     }
   }
 
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn get_batch<I, V, IRW, VRW, R, B>(
+    &self,
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock,
+  {
+    // Simple delegation
+    match &self {
+      SubTree::L1Leaf(leaf) => leaf.get_batch(values, res, raw_entries, id_rw, val_rw),
+      SubTree::L1Node(node) => node.get_batch(values, res, raw_entries, id_rw, val_rw),
+      SubTree::LDNode(node) => node.get_batch(values, res, raw_entries, id_rw, val_rw),
+    }
+  }
+
+  fn visit_desc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -329,6 +615,7 @@ impl SubTreeR for SubTree {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -338,10 +625,10 @@ impl SubTreeR for SubTree {
     }
   }
 
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -351,6 +638,7 @@ impl SubTreeR for SubTree {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -360,10 +648,10 @@ impl SubTreeR for SubTree {
     }
   }
 
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -373,6 +661,7 @@ impl SubTreeR for SubTree {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -383,6 +672,38 @@ impl SubTreeR for SubTree {
   }
 }
 
+impl SubTreeRank for SubTree {
+  fn locate(&self, rank: usize) -> EntryLocation {
+    match &self {
+      SubTree::L1Leaf(node) => node.locate(rank),
+      SubTree::L1Node(node) => node.locate(rank),
+      SubTree::LDNode(node) => node.locate(rank),
+    }
+  }
+
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock,
+  {
+    match &self {
+      SubTree::L1Leaf(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+      SubTree::L1Node(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+      SubTree::LDNode(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub enum LDSubTree {
   L1Node(L1Node), // LDLeaf = L1Node with L1Leaf as sub-tree
@@ -421,10 +742,10 @@ impl SubTreeW for LDSubTree {
 }
 
 impl SubTreeR for LDSubTree {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     value: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -433,6 +754,7 @@ impl SubTreeR for LDSubTree {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    B: RawBlock,
   {
     match &self {
       LDSubTree::L1Node(node) => node.get(value, raw_entries, id_rw, val_rw),
@@ -440,10 +762,32 @@ impl SubTreeR for LDSubTree {
     }
   }
 
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn get_batch<I, V, IRW, VRW, R, B>(
+    &self,
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock,
+  {
+    match &self {
+      LDSubTree::L1Node(node) => node.get_batch(values, res, raw_entries, id_rw, val_rw),
+      LDSubTree::LDNode(node) => node.get_batch(values, res, raw_entries, id_rw, val_rw),
+    }
+  }
+
+  fn visit_desc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -453,6 +797,7 @@ impl SubTreeR for LDSubTree {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -460,10 +805,10 @@ impl SubTreeR for LDSubTree {
       LDSubTree::LDNode(node) => node.visit_desc(visitor, raw_entries, id_rw, val_rw),
     }
   }
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -473,6 +818,7 @@ impl SubTreeR for LDSubTree {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -481,10 +827,10 @@ impl SubTreeR for LDSubTree {
     }
   }
 
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -494,6 +840,7 @@ impl SubTreeR for LDSubTree {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     // Simple delegation
     match &self {
@@ -503,20 +850,57 @@ impl SubTreeR for LDSubTree {
   }
 }
 
+impl SubTreeRank for LDSubTree {
+  fn locate(&self, rank: usize) -> EntryLocation {
+    match &self {
+      LDSubTree::L1Node(node) => node.locate(rank),
+      LDSubTree::LDNode(node) => node.locate(rank),
+    }
+  }
+
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock,
+  {
+    match &self {
+      LDSubTree::L1Node(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+      LDSubTree::LDNode(node) => node.partition_point(pred, raw_entries, id_rw, val_rw),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct RootL1Node {
   // Same as LDLeaf with sub-tree instead of Leaf!!
   n_elems: usize,
   sub_tree: SubTree,
   rightmost_subtree: Box<Root>,
+  page_layout: PageLayout,
 }
 
 impl RootL1Node {
-  fn new(n_elems: usize, sub_tree: SubTree, rightmost_subtree: Root) -> RootL1Node {
+  fn new(
+    n_elems: usize,
+    sub_tree: SubTree,
+    rightmost_subtree: Root,
+    page_layout: PageLayout,
+  ) -> RootL1Node {
     RootL1Node {
       n_elems,
       sub_tree,
       rightmost_subtree: Box::new(rightmost_subtree),
+      page_layout,
     }
   }
 }
@@ -554,29 +938,32 @@ impl SubTreeW for RootL1Node {
     );
     // Same algo as L1Node except that the last element is the righmost-subtree
     let subtree_byte_size = self.sub_tree.byte_size(entry_byte_size);
-    let (mut l1_buff, r_buff) = dest.split_at_mut(self.n_elems * entry_byte_size);
+    let (l1_buff, r_buff) = dest.split_at_mut(self.n_elems * entry_byte_size);
     let (mut st_buff, r_buff) = r_buff.split_at_mut(self.n_elems * subtree_byte_size);
+    let mut l1_entries = RawEntriesWriter::new(l1_buff, id_rw, val_rw, self.page_layout);
     for _ in 0..self.n_elems {
       let (curr_buff, subtree_buff) = st_buff.split_at_mut(subtree_byte_size);
       it = self.sub_tree.write(it, id_rw, val_rw, curr_buff)?;
       st_buff = subtree_buff;
       // Write the current entry
-      it.next()
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?
-        .write(&mut l1_buff, id_rw, val_rw)?;
+      let entry = it
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?;
+      l1_entries.push(entry)?;
     }
     // Plus the rightmost subtree
     it = self.rightmost_subtree.write(it, id_rw, val_rw, r_buff)?;
+    assert!(l1_entries.is_full());
     assert_eq!(st_buff.len(), 0);
     Ok(it)
   }
 }
 
 impl SubTreeR for RootL1Node {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     value: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -585,6 +972,7 @@ impl SubTreeR for RootL1Node {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     assert_eq!(
@@ -597,27 +985,70 @@ impl SubTreeR for RootL1Node {
     // Same algo as L1Node except that the last element is the righmost-subtree
     let subtree_byte_size = self.sub_tree.byte_size(entry_byte_size);
     let (l1_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
-    let mut l1_entries = RawEntries::new(l1_buff, id_rw, val_rw);
+    let l1_bytes = l1_buff.bytes()?;
+    let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, self.page_layout);
     match l1_entries.binary_search(&value)? {
       Ok(i) => Ok(Some(l1_entries.get_entry(i)?)),
       Err(i) => {
         if i == self.n_elems {
           self
             .rightmost_subtree
-            .get(value, &r_buff[i * subtree_byte_size..], id_rw, val_rw)
+            .get(value, r_buff.slice(i * subtree_byte_size..), id_rw, val_rw)
         } else {
           let from = i * subtree_byte_size;
           let to = from + subtree_byte_size;
-          self.sub_tree.get(value, &r_buff[from..to], id_rw, val_rw)
+          self.sub_tree.get(value, r_buff.slice(from..to), id_rw, val_rw)
         }
       }
     }
   }
 
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn get_batch<I, V, IRW, VRW, R, B>(
+    &self,
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock,
+  {
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
+    // Same algo as L1Node except that the last element is the righmost-subtree
+    let subtree_byte_size = self.sub_tree.byte_size(entry_byte_size);
+    let (l1_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let l1_bytes = l1_buff.bytes()?;
+    let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, self.page_layout);
+    get_batch_entries(values, res, &l1_entries, |i, values, res| {
+      if i == self.n_elems {
+        self.rightmost_subtree.get_batch(
+          values,
+          res,
+          r_buff.slice(i * subtree_byte_size..),
+          id_rw,
+          val_rw,
+        )
+      } else {
+        let from = i * subtree_byte_size;
+        let to = from + subtree_byte_size;
+        self
+          .sub_tree
+          .get_batch(values, res, r_buff.slice(from..to), id_rw, val_rw)
+      }
+    })
+  }
+
+  fn visit_desc<I, V, IRW, VRW, T, B>(
     &self,
     mut _visitor: T,
-    _raw_entries: &[u8],
+    _raw_entries: B,
     _id_rw: &IRW,
     _val_rw: &VRW,
   ) -> Result<T, Error>
@@ -627,14 +1058,15 @@ impl SubTreeR for RootL1Node {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     unreachable!() // not supposed to be called at the root level
   }
 
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     mut visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -644,6 +1076,7 @@ impl SubTreeR for RootL1Node {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     debug_assert!(!raw_entries.is_empty());
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
@@ -657,7 +1090,8 @@ impl SubTreeR for RootL1Node {
     // Same algo as L1Node except that the last element is the righmost-subtree
     let subtree_byte_size = self.sub_tree.byte_size(entry_byte_size);
     let (l1_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
-    let mut l1_entries = RawEntries::new(l1_buff, id_rw, val_rw);
+    let l1_bytes = l1_buff.bytes()?;
+    let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, self.page_layout);
     let (mut l, mut r) = match l1_entries.binary_search(visitor.center())? {
       Ok(i) => {
         visitor.visit_center(l1_entries.get_entry(i)?);
@@ -666,19 +1100,19 @@ impl SubTreeR for RootL1Node {
           let to = from + subtree_byte_size;
           visitor = self
             .sub_tree
-            .visit_desc(visitor, &r_buff[from..to], id_rw, val_rw)?;
+            .visit_desc(visitor, r_buff.slice(from..to), id_rw, val_rw)?;
         }
         if visitor.visit_asc() {
-          if i < self.n_elems {
+          if i + 1 < self.n_elems {
             let from = (i + 1) * subtree_byte_size;
             let to = from + subtree_byte_size;
             visitor = self
               .sub_tree
-              .visit_asc(visitor, &r_buff[from..to], id_rw, val_rw)?;
+              .visit_asc(visitor, r_buff.slice(from..to), id_rw, val_rw)?;
           } else {
             visitor = self.rightmost_subtree.visit_asc(
               visitor,
-              &r_buff[i * subtree_byte_size..],
+              r_buff.slice((i + 1) * subtree_byte_size..),
               id_rw,
               val_rw,
             )?;
@@ -692,12 +1126,12 @@ impl SubTreeR for RootL1Node {
           let to = from + subtree_byte_size;
           visitor = self
             .sub_tree
-            .visit(visitor, &r_buff[from..to], id_rw, val_rw)?;
+            .visit(visitor, r_buff.slice(from..to), id_rw, val_rw)?;
         } else {
           debug_assert_eq!(i, self.n_elems);
           visitor = self.rightmost_subtree.visit(
             visitor,
-            &r_buff[i * subtree_byte_size..],
+            r_buff.slice(i * subtree_byte_size..),
             id_rw,
             val_rw,
           )?;
@@ -717,7 +1151,7 @@ impl SubTreeR for RootL1Node {
       let to = from + subtree_byte_size;
       visitor = self
         .sub_tree
-        .visit_desc(visitor, &r_buff[from..to], id_rw, val_rw)?;
+        .visit_desc(visitor, r_buff.slice(from..to), id_rw, val_rw)?;
       l -= 1;
     }
     while r < self.n_elems {
@@ -730,15 +1164,15 @@ impl SubTreeR for RootL1Node {
       }
       r += 1;
       if r < self.n_elems {
-        let from = (r + 1) * subtree_byte_size;
+        let from = r * subtree_byte_size;
         let to = from + subtree_byte_size;
         visitor = self
           .sub_tree
-          .visit_asc(visitor, &r_buff[from..to], id_rw, val_rw)?;
+          .visit_asc(visitor, r_buff.slice(from..to), id_rw, val_rw)?;
       } else {
         visitor = self.rightmost_subtree.visit_asc(
           visitor,
-          &r_buff[r * subtree_byte_size..],
+          r_buff.slice(r * subtree_byte_size..),
           id_rw,
           val_rw,
         )?;
@@ -747,12 +1181,12 @@ impl SubTreeR for RootL1Node {
     Ok(visitor)
   }
 
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
-    mut _visitor: T,
-    _raw_entries: &[u8],
-    _id_rw: &IRW,
-    _val_rw: &VRW,
+    mut visitor: T,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
   ) -> Result<T, Error>
   where
     I: Id,
@@ -760,8 +1194,97 @@ impl SubTreeR for RootL1Node {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
-    unreachable!() // not supposed to be called at the root level
+    // Called when the node is the rightmost sub-tree of a root node
+    debug_assert!(visitor.visit_asc());
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    debug_assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
+    let subtree_byte_size = self.sub_tree.byte_size(entry_byte_size);
+    let (l1_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let l1_bytes = l1_buff.bytes()?;
+    let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, self.page_layout);
+    let mut i = 0;
+    while i < self.n_elems {
+      let from = i * subtree_byte_size;
+      let to = from + subtree_byte_size;
+      visitor = self
+        .sub_tree
+        .visit_asc(visitor, r_buff.slice(from..to), id_rw, val_rw)?;
+      if !visitor.visit_asc() {
+        break;
+      }
+      visitor.visit_he_center(l1_entries.get_entry(i)?);
+      if !visitor.visit_asc() {
+        break;
+      }
+      i += 1;
+    }
+    if i == self.n_elems {
+      visitor = self.rightmost_subtree.visit_asc(
+        visitor,
+        r_buff.slice(i * subtree_byte_size..),
+        id_rw,
+        val_rw,
+      )?;
+    }
+    Ok(visitor)
+  }
+}
+
+impl SubTreeRank for RootL1Node {
+  fn locate(&self, rank: usize) -> EntryLocation {
+    // Same algo as L1Node except that the last element is the righmost-subtree
+    let n_subtree_entries = self.sub_tree.n_entries();
+    let n_left_entries = self.n_elems * (n_subtree_entries + 1);
+    let (i, r) = (rank / (n_subtree_entries + 1), rank % (n_subtree_entries + 1));
+    if rank >= n_left_entries {
+      self
+        .rightmost_subtree
+        .locate(rank - n_left_entries)
+        .shift(n_left_entries)
+    } else if r < n_subtree_entries {
+      let offset = self.n_elems + i * n_subtree_entries;
+      self.sub_tree.locate(r).shift(offset)
+    } else {
+      EntryLocation::new(i, 1, 0, self.n_elems)
+    }
+  }
+
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock,
+  {
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    let subtree_byte_size = self.sub_tree.byte_size(entry_byte_size);
+    let (l1_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let i = RawEntries::with_layout(&l1_buff.bytes()?, id_rw, val_rw, self.page_layout)
+      .partition_point(pred)?;
+    let from = i * subtree_byte_size;
+    let rank = i * (self.sub_tree.n_entries() + 1);
+    if i < self.n_elems {
+      let to = from + subtree_byte_size;
+      self
+        .sub_tree
+        .partition_point(pred, r_buff.slice(from..to), id_rw, val_rw)
+        .map(|r| rank + r)
+    } else {
+      self
+        .rightmost_subtree
+        .partition_point(pred, r_buff.slice(from..), id_rw, val_rw)
+        .map(|r| rank + r)
+    }
   }
 }
 
@@ -771,6 +1294,7 @@ pub struct RootLDNode {
   n_l1page_elems: usize,
   sub_tree: LDSubTree,
   rightmost_subtree: Box<Root>,
+  page_layout: PageLayout,
 }
 
 impl RootLDNode {
@@ -779,12 +1303,14 @@ impl RootLDNode {
     n_l1page_elems: usize,
     sub_tree: LDSubTree,
     rightmost_subtree: Root,
+    page_layout: PageLayout,
   ) -> RootLDNode {
     RootLDNode {
       n_elems,
       n_l1page_elems,
       sub_tree,
       rightmost_subtree: Box::new(rightmost_subtree),
+      page_layout,
     }
   }
 }
@@ -825,9 +1351,10 @@ impl SubTreeW for RootLDNode {
     let subtree_group_byte_size =
       (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
     // Split the 4 blocs [ld][l1, l1, ..., l1][ST, ST, ..., ST][RootSubTree]
-    let (mut ld_buff, r_buff) = dest.split_at_mut(self.n_elems * entry_byte_size);
+    let (ld_buff, r_buff) = dest.split_at_mut(self.n_elems * entry_byte_size);
     let (mut l1_buff, r_buff) = r_buff.split_at_mut(self.n_elems * l1page_byte_size);
     let (mut st_buff, r_buff) = r_buff.split_at_mut(self.n_elems * subtree_group_byte_size);
+    let mut ld_entries = RawEntriesWriter::new(ld_buff, id_rw, val_rw, self.page_layout);
     assert_eq!(
       r_buff.len(),
       self.rightmost_subtree.byte_size(entry_byte_size)
@@ -836,16 +1363,26 @@ impl SubTreeW for RootLDNode {
       // Sub-split the [l1, l1, ..., l1] and [ST, ST, ..., ST] blocks
       let (cl1_buff, tl1_buff) = l1_buff.split_at_mut(l1page_byte_size);
       let (cst_buff, tst_buff) = st_buff.split_at_mut(subtree_group_byte_size);
-      it = write_l1page(it, id_rw, val_rw, cl1_buff, &self.sub_tree, cst_buff)?;
+      it = write_l1page(
+        it,
+        id_rw,
+        val_rw,
+        self.page_layout,
+        cl1_buff,
+        &self.sub_tree,
+        cst_buff,
+      )?;
       l1_buff = tl1_buff;
       st_buff = tst_buff;
       // Write current entry
-      it.next()
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?
-        .write(&mut ld_buff, id_rw, val_rw)?;
+      let entry = it
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?;
+      ld_entries.push(entry)?;
     }
     // And write the rightmost subtree
     it = self.rightmost_subtree.write(it, id_rw, val_rw, r_buff)?;
+    assert!(ld_entries.is_full());
     assert_eq!(l1_buff.len(), 0, "Wrong L1 buff size: {}", l1_buff.len());
     assert_eq!(st_buff.len(), 0, "Wrong ST buff size: {}", st_buff.len());
     Ok(it)
@@ -853,10 +1390,10 @@ impl SubTreeW for RootLDNode {
 }
 
 impl SubTreeR for RootLDNode {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     value: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -865,6 +1402,7 @@ impl SubTreeR for RootLDNode {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
@@ -874,7 +1412,8 @@ impl SubTreeR for RootLDNode {
       (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
     // Split the 4 blocs [ld][l1, l1, ..., l1][ST, ST, ..., ST][RootSubTree]
     let (ld_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
-    let mut entries = RawEntries::new(ld_buff, id_rw, val_rw);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
     match entries.binary_search(&value)? {
       Ok(i) => Ok(Some(entries.get_entry(i)?)),
       Err(i) => {
@@ -896,36 +1435,90 @@ impl SubTreeR for RootLDNode {
             value,
             id_rw,
             val_rw,
-            &l1_buff[from_l1..to_l1],
+            self.page_layout,
+            l1_buff.slice(from_l1..to_l1),
             &self.sub_tree,
-            &st_buff[from_st..to_st],
+            st_buff.slice(from_st..to_st),
           )
         }
       }
     }
   }
 
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn get_batch<I, V, IRW, VRW, R, B>(
     &self,
-    _visitor: T,
-    _raw_entries: &[u8],
-    _id_rw: &IRW,
-    _val_rw: &VRW,
-  ) -> Result<T, Error>
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
   where
     I: Id,
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
-    T: Visitor<I = I, V = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock,
   {
-    unreachable!() // not supposed to be called at the root level
-  }
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
+    // Same algo as LDNode except that the las element is the rightmost sub-tree
+    let l1page_byte_size = self.n_l1page_elems * entry_byte_size;
+    let subtree_group_byte_size =
+      (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
+    // Split the 4 blocs [ld][l1, l1, ..., l1][ST, ST, ..., ST][RootSubTree]
+    let (ld_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let (l1_buff, r_buff) = r_buff.split_at(self.n_elems * l1page_byte_size);
+    let (st_buff, r_buff) = r_buff.split_at(self.n_elems * subtree_group_byte_size);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
+    get_batch_entries(values, res, &entries, |i, values, res| {
+      if i == self.n_elems {
+        self
+          .rightmost_subtree
+          .get_batch(values, res, r_buff, id_rw, val_rw)
+      } else {
+        let from_l1 = i * l1page_byte_size;
+        let to_l1 = from_l1 + l1page_byte_size;
+        let from_st = i * subtree_group_byte_size;
+        let to_st = from_st + subtree_group_byte_size;
+        get_batch_l1page(
+          values,
+          res,
+          id_rw,
+          val_rw,
+          self.page_layout,
+          l1_buff.slice(from_l1..to_l1),
+          &self.sub_tree,
+          st_buff.slice(from_st..to_st),
+        )
+      }
+    })
+  }
+
+  fn visit_desc<I, V, IRW, VRW, T, B>(
+    &self,
+    _visitor: T,
+    _raw_entries: B,
+    _id_rw: &IRW,
+    _val_rw: &VRW,
+  ) -> Result<T, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    T: Visitor<I = I, V = V>,
+    B: RawBlock,
+  {
+    unreachable!() // not supposed to be called at the root level
+  }
 
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     mut visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -935,6 +1528,7 @@ impl SubTreeR for RootLDNode {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
@@ -946,7 +1540,8 @@ impl SubTreeR for RootLDNode {
     let (ld_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
     let (l1_buff, r_buff) = r_buff.split_at(self.n_elems * l1page_byte_size);
     let (st_buff, r_buff) = r_buff.split_at(self.n_elems * subtree_group_byte_size);
-    let mut entries = RawEntries::new(ld_buff, id_rw, val_rw);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
     let (mut l, mut r) = match entries.binary_search(visitor.center())? {
       Ok(i) => {
         visitor.visit_center(entries.get_entry(i)?);
@@ -959,13 +1554,14 @@ impl SubTreeR for RootLDNode {
             visitor,
             id_rw,
             val_rw,
-            &l1_buff[from_l1..to_l1],
+            self.page_layout,
+            l1_buff.slice(from_l1..to_l1),
             &self.sub_tree,
-            &st_buff[from_st..to_st],
+            st_buff.slice(from_st..to_st),
           )?;
         }
         if visitor.visit_asc() {
-          if i < self.n_elems {
+          if i + 1 < self.n_elems {
             let from_l1 = (i + 1) * l1page_byte_size;
             let to_l1 = from_l1 + l1page_byte_size;
             let from_st = (i + 1) * subtree_group_byte_size;
@@ -974,9 +1570,10 @@ impl SubTreeR for RootLDNode {
               visitor,
               id_rw,
               val_rw,
-              &l1_buff[from_l1..to_l1],
+              self.page_layout,
+              l1_buff.slice(from_l1..to_l1),
               &self.sub_tree,
-              &st_buff[from_st..to_st],
+              st_buff.slice(from_st..to_st),
             )?;
           } else {
             visitor = self
@@ -996,9 +1593,10 @@ impl SubTreeR for RootLDNode {
             visitor,
             id_rw,
             val_rw,
-            &l1_buff[from_l1..to_l1],
+            self.page_layout,
+            l1_buff.slice(from_l1..to_l1),
             &self.sub_tree,
-            &st_buff[from_st..to_st],
+            st_buff.slice(from_st..to_st),
           )?;
         } else {
           visitor = self
@@ -1024,9 +1622,10 @@ impl SubTreeR for RootLDNode {
         visitor,
         id_rw,
         val_rw,
-        &l1_buff[from_l1..to_l1],
+        self.page_layout,
+        l1_buff.slice(from_l1..to_l1),
         &self.sub_tree,
-        &st_buff[from_st..to_st],
+        st_buff.slice(from_st..to_st),
       )?;
       l -= 1;
     }
@@ -1048,9 +1647,10 @@ impl SubTreeR for RootLDNode {
           visitor,
           id_rw,
           val_rw,
-          &l1_buff[from_l1..to_l1],
+          self.page_layout,
+          l1_buff.slice(from_l1..to_l1),
           &self.sub_tree,
-          &st_buff[from_st..to_st],
+          st_buff.slice(from_st..to_st),
         )?;
       } else {
         visitor = self
@@ -1061,12 +1661,12 @@ impl SubTreeR for RootLDNode {
     Ok(visitor)
   }
 
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
-    _visitor: T,
-    _raw_entries: &[u8],
-    _id_rw: &IRW,
-    _val_rw: &VRW,
+    mut visitor: T,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
   ) -> Result<T, Error>
   where
     I: Id,
@@ -1074,19 +1674,143 @@ impl SubTreeR for RootLDNode {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
-    unreachable!() // not supposed to be called at the root level
+    // Called when the node is the rightmost sub-tree of a root node
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
+    let l1page_byte_size = self.n_l1page_elems * entry_byte_size;
+    let subtree_group_byte_size =
+      (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
+    // Split the 4 blocs [ld][l1, l1, ..., l1][ST, ST, ..., ST][RootSubTree]
+    let (ld_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let (l1_buff, r_buff) = r_buff.split_at(self.n_elems * l1page_byte_size);
+    let (st_buff, r_buff) = r_buff.split_at(self.n_elems * subtree_group_byte_size);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
+    let mut i = 0;
+    while i < self.n_elems {
+      let from_l1 = i * l1page_byte_size;
+      let to_l1 = from_l1 + l1page_byte_size;
+      let from_st = i * subtree_group_byte_size;
+      let to_st = from_st + subtree_group_byte_size;
+      visitor = visit_asc_l1page(
+        visitor,
+        id_rw,
+        val_rw,
+        self.page_layout,
+        l1_buff.slice(from_l1..to_l1),
+        &self.sub_tree,
+        st_buff.slice(from_st..to_st),
+      )?;
+      if !visitor.visit_asc() {
+        break;
+      }
+      visitor.visit_he_center(entries.get_entry(i)?);
+      if !visitor.visit_asc() {
+        break;
+      }
+      i += 1;
+    }
+    if i == self.n_elems {
+      visitor = self
+        .rightmost_subtree
+        .visit_asc(visitor, r_buff, id_rw, val_rw)?;
+    }
+    Ok(visitor)
+  }
+}
+
+impl SubTreeRank for RootLDNode {
+  fn locate(&self, rank: usize) -> EntryLocation {
+    // Same algo as LDNode except that the last element is the rightmost sub-tree
+    let n_subtree_entries = self.sub_tree.n_entries();
+    let n_l1page_block_entries = self.n_l1page_elems * (n_subtree_entries + 1) + n_subtree_entries;
+    let n_left_entries = self.n_elems * (n_l1page_block_entries + 1);
+    let (i, r) = (rank / (n_l1page_block_entries + 1), rank % (n_l1page_block_entries + 1));
+    let st_offset = self.n_elems * (1 + self.n_l1page_elems);
+    if rank >= n_left_entries {
+      self
+        .rightmost_subtree
+        .locate(rank - n_left_entries)
+        .shift(n_left_entries)
+    } else if r < n_l1page_block_entries {
+      locate_l1page(
+        r,
+        self.n_l1page_elems,
+        &self.sub_tree,
+        self.n_elems + i * self.n_l1page_elems,
+        st_offset + i * (self.n_l1page_elems + 1) * n_subtree_entries,
+      )
+    } else {
+      EntryLocation::new(i, 1, 0, self.n_elems)
+    }
+  }
+
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock,
+  {
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    let l1page_byte_size = self.n_l1page_elems * entry_byte_size;
+    let subtree_group_byte_size =
+      (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
+    let n_l1page_block_entries =
+      self.n_l1page_elems * (self.sub_tree.n_entries() + 1) + self.sub_tree.n_entries();
+    // Split the 4 blocs [ld][l1, l1, ..., l1][ST, ST, ..., ST][RootSubTree]
+    let (ld_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let (l1_buff, r_buff) = r_buff.split_at(self.n_elems * l1page_byte_size);
+    let (st_buff, r_buff) = r_buff.split_at(self.n_elems * subtree_group_byte_size);
+    let i = RawEntries::with_layout(&ld_buff.bytes()?, id_rw, val_rw, self.page_layout)
+      .partition_point(pred)?;
+    let rank = i * (n_l1page_block_entries + 1);
+    if i < self.n_elems {
+      let from_l1 = i * l1page_byte_size;
+      let to_l1 = from_l1 + l1page_byte_size;
+      let from_st = i * subtree_group_byte_size;
+      let to_st = from_st + subtree_group_byte_size;
+      partition_point_l1page(
+        pred,
+        id_rw,
+        val_rw,
+        self.page_layout,
+        l1_buff.slice(from_l1..to_l1),
+        &self.sub_tree,
+        st_buff.slice(from_st..to_st),
+      )
+      .map(|r| rank + r)
+    } else {
+      self
+        .rightmost_subtree
+        .partition_point(pred, r_buff, id_rw, val_rw)
+        .map(|r| rank + r)
+    }
   }
 }
 
 #[derive(Debug)]
 pub struct L1Leaf {
   n_elems: usize,
+  page_layout: PageLayout,
 }
 
 impl L1Leaf {
-  fn new(n_elems: usize) -> L1Leaf {
-    L1Leaf { n_elems }
+  fn new(n_elems: usize, page_layout: PageLayout) -> L1Leaf {
+    L1Leaf {
+      n_elems,
+      page_layout,
+    }
   }
 }
 
@@ -1102,7 +1826,7 @@ impl SubTreeW for L1Leaf {
     mut it: T,
     id_rw: &IRW,
     val_rw: &VRW,
-    mut dest: &mut [u8],
+    dest: &mut [u8],
   ) -> Result<T, Error>
   where
     I: Id,
@@ -1119,21 +1843,23 @@ impl SubTreeW for L1Leaf {
       self.byte_size(entry_byte_size),
       dest.len()
     );
+    let mut entries = RawEntriesWriter::new(dest, id_rw, val_rw, self.page_layout);
     for _ in 0..self.n_elems {
-      it.next()
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?
-        .write(&mut dest, id_rw, val_rw)?;
+      let entry = it
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?;
+      entries.push(entry)?;
     }
-    assert_eq!(dest.len(), 0);
+    assert!(entries.is_full());
     Ok(it)
   }
 }
 
 impl SubTreeR for L1Leaf {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     val: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -1142,12 +1868,14 @@ impl SubTreeR for L1Leaf {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    B: RawBlock,
   {
     debug_assert_eq!(
       self.byte_size(id_rw.n_bytes() + val_rw.n_bytes()),
       raw_entries.len()
     );
-    let mut entries = RawEntries::new(raw_entries, id_rw, val_rw);
+    let bytes = raw_entries.bytes()?;
+    let entries = RawEntries::with_layout(&bytes, id_rw, val_rw, self.page_layout);
     entries
       .binary_search(&val)?
       .ok()
@@ -1155,10 +1883,45 @@ impl SubTreeR for L1Leaf {
       .transpose()
   }
 
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn get_batch<I, V, IRW, VRW, R, B>(
+    &self,
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock,
+  {
+    debug_assert_eq!(
+      self.byte_size(id_rw.n_bytes() + val_rw.n_bytes()),
+      raw_entries.len()
+    );
+    let bytes = raw_entries.bytes()?;
+    let entries = RawEntries::with_layout(&bytes, id_rw, val_rw, self.page_layout);
+    for (value, res) in values.iter().zip(res.iter_mut()) {
+      match entries.binary_search(value)? {
+        Ok(i) => *res = R::found(entries.get_entry(i)?),
+        Err(i) => {
+          if R::SURROUNDING {
+            res.between(surrounding_entry(&entries, i, 1)?, surrounding_entry(&entries, i, 0)?);
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn visit_desc<I, V, IRW, VRW, T, B>(
     &self,
     mut visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1168,13 +1931,15 @@ impl SubTreeR for L1Leaf {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     debug_assert_eq!(
       self.byte_size(id_rw.n_bytes() + val_rw.n_bytes()),
       raw_entries.len()
     );
     debug_assert!(visitor.visit_desc());
-    let mut entries = RawEntries::new(raw_entries, id_rw, val_rw);
+    let bytes = raw_entries.bytes()?;
+    let entries = RawEntries::with_layout(&bytes, id_rw, val_rw, self.page_layout);
     for i in (0..self.n_elems).rev() {
       visitor.visit_le_center(entries.get_entry(i)?);
       if !visitor.visit_desc() {
@@ -1184,10 +1949,10 @@ impl SubTreeR for L1Leaf {
     Ok(visitor)
   }
 
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     mut visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1197,12 +1962,14 @@ impl SubTreeR for L1Leaf {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     debug_assert_eq!(
       self.byte_size(id_rw.n_bytes() + val_rw.n_bytes()),
       raw_entries.len()
     );
-    let mut entries = RawEntries::new(raw_entries, id_rw, val_rw);
+    let bytes = raw_entries.bytes()?;
+    let entries = RawEntries::with_layout(&bytes, id_rw, val_rw, self.page_layout);
     let (mut l, mut r) = match entries.binary_search(visitor.center())? {
       Ok(i) => {
         visitor.visit_center(entries.get_entry(i)?);
@@ -1223,10 +1990,10 @@ impl SubTreeR for L1Leaf {
     Ok(visitor)
   }
 
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
     mut visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1236,13 +2003,15 @@ impl SubTreeR for L1Leaf {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     debug_assert_eq!(
       self.byte_size(id_rw.n_bytes() + val_rw.n_bytes()),
       raw_entries.len()
     );
     debug_assert!(visitor.visit_asc());
-    let mut entries = RawEntries::new(raw_entries, id_rw, val_rw);
+    let bytes = raw_entries.bytes()?;
+    let entries = RawEntries::with_layout(&bytes, id_rw, val_rw, self.page_layout);
     for i in 0..self.n_elems {
       visitor.visit_he_center(entries.get_entry(i)?);
       if !visitor.visit_asc() {
@@ -1253,18 +2022,49 @@ impl SubTreeR for L1Leaf {
   }
 }
 
+impl SubTreeRank for L1Leaf {
+  fn locate(&self, rank: usize) -> EntryLocation {
+    EntryLocation::new(rank, self.n_elems - rank, 0, self.n_elems)
+  }
+
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock,
+  {
+    if self.n_elems == 0 {
+      Ok(0)
+    } else {
+      RawEntries::with_layout(&raw_entries.bytes()?, id_rw, val_rw, self.page_layout)
+        .partition_point(pred)
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct L1Node {
   // Only the root can be a L1Node
   n_elems: usize,
   sub_tree: Box<SubTree>, // Like LDLeaf with leaf being a sub-tree
+  page_layout: PageLayout,
 }
 
 impl L1Node {
-  fn new(n_elems: usize, sub_tree: SubTree) -> L1Node {
+  fn new(n_elems: usize, sub_tree: SubTree, page_layout: PageLayout) -> L1Node {
     L1Node {
       n_elems,
       sub_tree: Box::new(sub_tree),
+      page_layout,
     }
   }
 }
@@ -1297,16 +2097,24 @@ impl SubTreeW for L1Node {
       "Wrong buffer size"
     );
     let (l1_buff, st_buff) = dest.split_at_mut(self.n_elems * entry_byte_size);
-    it = write_l1page(it, id_rw, val_rw, l1_buff, self.sub_tree.as_ref(), st_buff)?;
+    it = write_l1page(
+      it,
+      id_rw,
+      val_rw,
+      self.page_layout,
+      l1_buff,
+      self.sub_tree.as_ref(),
+      st_buff,
+    )?;
     Ok(it)
   }
 }
 
 impl SubTreeR for L1Node {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     val: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -1315,17 +2123,57 @@ impl SubTreeR for L1Node {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    B: RawBlock,
+  {
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    debug_assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
+    let (l1_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    get_l1page(
+      val,
+      id_rw,
+      val_rw,
+      self.page_layout,
+      l1_buff,
+      self.sub_tree.as_ref(),
+      st_buff,
+    )
+  }
+
+  fn get_batch<I, V, IRW, VRW, R, B>(
+    &self,
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     debug_assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
     let (l1_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
-    get_l1page(val, id_rw, val_rw, l1_buff, self.sub_tree.as_ref(), st_buff)
+    get_batch_l1page(
+      values,
+      res,
+      id_rw,
+      val_rw,
+      self.page_layout,
+      l1_buff,
+      self.sub_tree.as_ref(),
+      st_buff,
+    )
   }
 
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn visit_desc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1335,6 +2183,7 @@ impl SubTreeR for L1Node {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     debug_assert!(visitor.visit_desc());
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
@@ -1344,16 +2193,17 @@ impl SubTreeR for L1Node {
       visitor,
       id_rw,
       val_rw,
+      self.page_layout,
       l1_buff,
       self.sub_tree.as_ref(),
       st_buff,
     )
   }
 
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1363,6 +2213,7 @@ impl SubTreeR for L1Node {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     debug_assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
@@ -1371,16 +2222,17 @@ impl SubTreeR for L1Node {
       visitor,
       id_rw,
       val_rw,
+      self.page_layout,
       l1_buff,
       self.sub_tree.as_ref(),
       st_buff,
     )
   }
 
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
     visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1390,6 +2242,7 @@ impl SubTreeR for L1Node {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     debug_assert!(visitor.visit_asc());
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
@@ -1399,6 +2252,7 @@ impl SubTreeR for L1Node {
       visitor,
       id_rw,
       val_rw,
+      self.page_layout,
       l1_buff,
       self.sub_tree.as_ref(),
       st_buff,
@@ -1406,19 +2260,67 @@ impl SubTreeR for L1Node {
   }
 }
 
+impl SubTreeRank for L1Node {
+  fn locate(&self, rank: usize) -> EntryLocation {
+    let n_subtree_entries = self.sub_tree.n_entries();
+    let (i, r) = (rank / (n_subtree_entries + 1), rank % (n_subtree_entries + 1));
+    if r < n_subtree_entries {
+      let offset = self.n_elems + i * n_subtree_entries;
+      self.sub_tree.locate(r).shift(offset)
+    } else {
+      EntryLocation::new(i, 1, 0, self.n_elems)
+    }
+  }
+
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock,
+  {
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    let (l1_buff, subtree_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    partition_point_l1page(
+      pred,
+      id_rw,
+      val_rw,
+      self.page_layout,
+      l1_buff,
+      &*self.sub_tree,
+      subtree_buff,
+    )
+  }
+}
+
 #[derive(Debug)]
 pub struct LDNode {
   n_elems: usize,
   n_l1page_elems: usize,
   sub_tree: Box<LDSubTree>,
+  page_layout: PageLayout,
 }
 
 impl LDNode {
-  fn new(n_elems: usize, n_l1page_elems: usize, sub_tree: LDSubTree) -> LDNode {
+  fn new(
+    n_elems: usize,
+    n_l1page_elems: usize,
+    sub_tree: LDSubTree,
+    page_layout: PageLayout,
+  ) -> LDNode {
     LDNode {
       n_elems,
       n_l1page_elems,
       sub_tree: Box::new(sub_tree),
+      page_layout,
     }
   }
 }
@@ -1458,9 +2360,10 @@ impl SubTreeW for LDNode {
     let l1page_byte_size = self.n_l1page_elems * entry_byte_size;
     let subtree_group_byte_size =
       (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
-    let (mut ld_buff, st_buff) = dest.split_at_mut(self.n_elems * entry_byte_size);
+    let (ld_buff, st_buff) = dest.split_at_mut(self.n_elems * entry_byte_size);
     let (mut l1_buff, mut st_buff) = st_buff.split_at_mut((self.n_elems + 1) * l1page_byte_size);
     assert_eq!(st_buff.len(), (self.n_elems + 1) * subtree_group_byte_size);
+    let mut ld_entries = RawEntriesWriter::new(ld_buff, id_rw, val_rw, self.page_layout);
     for _ in 0..self.n_elems {
       // Sub-split the [l1, l1, ..., l1] and [ST, ST, ..., ST] blocks
       let (cl1_buff, tl1_buff) = l1_buff.split_at_mut(l1page_byte_size);
@@ -1469,6 +2372,7 @@ impl SubTreeW for LDNode {
         it,
         id_rw,
         val_rw,
+        self.page_layout,
         cl1_buff,
         self.sub_tree.as_ref(),
         cst_buff,
@@ -1476,22 +2380,31 @@ impl SubTreeW for LDNode {
       l1_buff = tl1_buff;
       st_buff = tst_buff;
       // Write the current entry
-      it.next()
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?
-        .write(&mut ld_buff, id_rw, val_rw)?;
+      let entry = it
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?;
+      ld_entries.push(entry)?;
     }
     // Write the last sub-tree
-    it = write_l1page(it, id_rw, val_rw, l1_buff, self.sub_tree.as_ref(), st_buff)?;
-    assert_eq!(ld_buff.len(), 0);
+    it = write_l1page(
+      it,
+      id_rw,
+      val_rw,
+      self.page_layout,
+      l1_buff,
+      self.sub_tree.as_ref(),
+      st_buff,
+    )?;
+    assert!(ld_entries.is_full());
     Ok(it)
   }
 }
 
 impl SubTreeR for LDNode {
-  fn get<I, V, IRW, VRW>(
+  fn get<I, V, IRW, VRW, B>(
     &self,
     val: V,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<Option<Entry<I, V>>, Error>
@@ -1500,6 +2413,7 @@ impl SubTreeR for LDNode {
     V: Val,
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
@@ -1508,7 +2422,8 @@ impl SubTreeR for LDNode {
     let subtree_group_byte_size =
       (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
     let (ld_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
-    let mut entries = RawEntries::new(ld_buff, id_rw, val_rw);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
     match entries.binary_search(&val)? {
       Ok(i) => Ok(Some(entries.get_entry(i)?)),
       Err(i) => {
@@ -1521,18 +2436,63 @@ impl SubTreeR for LDNode {
           val,
           id_rw,
           val_rw,
-          &l1_buff[from_l1..to_l1],
+          self.page_layout,
+          l1_buff.slice(from_l1..to_l1),
           self.sub_tree.as_ref(),
-          &st_buff[from_st..to_st],
+          st_buff.slice(from_st..to_st),
         )
       }
     }
   }
 
-  fn visit_desc<I, V, IRW, VRW, T>(
+  fn get_batch<I, V, IRW, VRW, R, B>(
+    &self,
+    values: &[V],
+    res: &mut [R],
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    R: BatchHit<I, V>,
+    B: RawBlock,
+  {
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
+    // Split the 3 blocs [ld][l1, l1, ..., l1][ST, ST, ..., ST]
+    let l1page_byte_size = self.n_l1page_elems * entry_byte_size;
+    let subtree_group_byte_size =
+      (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
+    let (ld_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let (l1_buff, st_buff) = st_buff.split_at((self.n_elems + 1) * l1page_byte_size);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
+    get_batch_entries(values, res, &entries, |i, values, res| {
+      let from_l1 = i * l1page_byte_size;
+      let to_l1 = from_l1 + l1page_byte_size;
+      let from_st = i * subtree_group_byte_size;
+      let to_st = from_st + subtree_group_byte_size;
+      get_batch_l1page(
+        values,
+        res,
+        id_rw,
+        val_rw,
+        self.page_layout,
+        l1_buff.slice(from_l1..to_l1),
+        self.sub_tree.as_ref(),
+        st_buff.slice(from_st..to_st),
+      )
+    })
+  }
+
+  fn visit_desc<I, V, IRW, VRW, T, B>(
     &self,
     mut visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1542,6 +2502,7 @@ impl SubTreeR for LDNode {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
@@ -1549,9 +2510,10 @@ impl SubTreeR for LDNode {
     let l1page_byte_size = self.n_l1page_elems * entry_byte_size;
     let subtree_group_byte_size =
       (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
-    let (_ld_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let (ld_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
     let (l1_buff, st_buff) = st_buff.split_at((self.n_elems + 1) * l1page_byte_size);
-    // let mut entries = RawEntries::new(ld_buff, id_rw, val_rw);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
 
     let from_l1 = self.n_elems * l1page_byte_size;
     let to_l1 = from_l1 + l1page_byte_size;
@@ -1561,11 +2523,19 @@ impl SubTreeR for LDNode {
       visitor,
       id_rw,
       val_rw,
-      &l1_buff[from_l1..to_l1],
+      self.page_layout,
+      l1_buff.slice(from_l1..to_l1),
       self.sub_tree.as_ref(),
-      &st_buff[from_st..to_st],
+      st_buff.slice(from_st..to_st),
     )?;
     for i in (0..self.n_elems).rev() {
+      if !visitor.visit_desc() {
+        break;
+      }
+      visitor.visit_le_center(entries.get_entry(i)?);
+      if !visitor.visit_desc() {
+        break;
+      }
       let from_l1 = i * l1page_byte_size;
       let to_l1 = from_l1 + l1page_byte_size;
       let from_st = i * subtree_group_byte_size;
@@ -1574,17 +2544,18 @@ impl SubTreeR for LDNode {
         visitor,
         id_rw,
         val_rw,
-        &l1_buff[from_l1..to_l1],
+        self.page_layout,
+        l1_buff.slice(from_l1..to_l1),
         self.sub_tree.as_ref(),
-        &st_buff[from_st..to_st],
+        st_buff.slice(from_st..to_st),
       )?;
     }
     Ok(visitor)
   }
-  fn visit<I, V, IRW, VRW, T>(
+  fn visit<I, V, IRW, VRW, T, B>(
     &self,
     mut visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1594,6 +2565,7 @@ impl SubTreeR for LDNode {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
@@ -1603,7 +2575,8 @@ impl SubTreeR for LDNode {
       (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
     let (ld_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
     let (l1_buff, st_buff) = st_buff.split_at((self.n_elems + 1) * l1page_byte_size);
-    let mut entries = RawEntries::new(ld_buff, id_rw, val_rw);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
     let (mut l, mut r) = match entries.binary_search(visitor.center())? {
       Ok(i) => {
         visitor.visit_center(entries.get_entry(i)?);
@@ -1616,9 +2589,10 @@ impl SubTreeR for LDNode {
             visitor,
             id_rw,
             val_rw,
-            &l1_buff[from_l1..to_l1],
+            self.page_layout,
+            l1_buff.slice(from_l1..to_l1),
             self.sub_tree.as_ref(),
-            &st_buff[from_st..to_st],
+            st_buff.slice(from_st..to_st),
           )?;
         }
         if visitor.visit_asc() {
@@ -1630,9 +2604,10 @@ impl SubTreeR for LDNode {
             visitor,
             id_rw,
             val_rw,
-            &l1_buff[from_l1..to_l1],
+            self.page_layout,
+            l1_buff.slice(from_l1..to_l1),
             self.sub_tree.as_ref(),
-            &st_buff[from_st..to_st],
+            st_buff.slice(from_st..to_st),
           )?;
         }
         (i as i32 - 1, i + 1)
@@ -1646,9 +2621,10 @@ impl SubTreeR for LDNode {
           visitor,
           id_rw,
           val_rw,
-          &l1_buff[from_l1..to_l1],
+          self.page_layout,
+          l1_buff.slice(from_l1..to_l1),
           self.sub_tree.as_ref(),
-          &st_buff[from_st..to_st],
+          st_buff.slice(from_st..to_st),
         )?;
         (i as i32 - 1, i)
       }
@@ -1669,9 +2645,10 @@ impl SubTreeR for LDNode {
         visitor,
         id_rw,
         val_rw,
-        &l1_buff[from_l1..to_l1],
+        self.page_layout,
+        l1_buff.slice(from_l1..to_l1),
         self.sub_tree.as_ref(),
-        &st_buff[from_st..to_st],
+        st_buff.slice(from_st..to_st),
       )?;
       l -= 1;
     }
@@ -1691,18 +2668,19 @@ impl SubTreeR for LDNode {
         visitor,
         id_rw,
         val_rw,
-        &l1_buff[from_l1..to_l1],
+        self.page_layout,
+        l1_buff.slice(from_l1..to_l1),
         self.sub_tree.as_ref(),
-        &st_buff[from_st..to_st],
+        st_buff.slice(from_st..to_st),
       )?;
       r += 1;
     }
     Ok(visitor)
   }
-  fn visit_asc<I, V, IRW, VRW, T>(
+  fn visit_asc<I, V, IRW, VRW, T, B>(
     &self,
     mut visitor: T,
-    raw_entries: &[u8],
+    raw_entries: B,
     id_rw: &IRW,
     val_rw: &VRW,
   ) -> Result<T, Error>
@@ -1712,6 +2690,7 @@ impl SubTreeR for LDNode {
     IRW: ReadWrite<Type = I>,
     VRW: ReadWrite<Type = V>,
     T: Visitor<I = I, V = V>,
+    B: RawBlock,
   {
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     assert_eq!(self.byte_size(entry_byte_size), raw_entries.len());
@@ -1719,19 +2698,28 @@ impl SubTreeR for LDNode {
     let l1page_byte_size = self.n_l1page_elems * entry_byte_size;
     let subtree_group_byte_size =
       (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
-    let (_ld_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let (ld_buff, st_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
     let (l1_buff, st_buff) = st_buff.split_at((self.n_elems + 1) * l1page_byte_size);
-    // let mut entries = RawEntries::new(ld_buff, id_rw, val_rw);
+    let ld_bytes = ld_buff.bytes()?;
+    let entries = RawEntries::with_layout(&ld_bytes, id_rw, val_rw, self.page_layout);
 
     visitor = visit_asc_l1page(
       visitor,
       id_rw,
       val_rw,
-      &l1_buff[0..l1page_byte_size],
+      self.page_layout,
+      l1_buff.slice(0..l1page_byte_size),
       self.sub_tree.as_ref(),
-      &st_buff[0..subtree_group_byte_size],
+      st_buff.slice(0..subtree_group_byte_size),
     )?;
     for i in 1..=self.n_elems {
+      if !visitor.visit_asc() {
+        break;
+      }
+      visitor.visit_he_center(entries.get_entry(i - 1)?);
+      if !visitor.visit_asc() {
+        break;
+      }
       let from_l1 = i * l1page_byte_size;
       let to_l1 = from_l1 + l1page_byte_size;
       let from_st = i * subtree_group_byte_size;
@@ -1740,27 +2728,91 @@ impl SubTreeR for LDNode {
         visitor,
         id_rw,
         val_rw,
-        &l1_buff[from_l1..to_l1],
+        self.page_layout,
+        l1_buff.slice(from_l1..to_l1),
         self.sub_tree.as_ref(),
-        &st_buff[from_st..to_st],
+        st_buff.slice(from_st..to_st),
       )?;
     }
     Ok(visitor)
   }
 }
 
-///
-/// # Remark:
-/// A LD Leaf can be considered as a L1 page (with a small number of entries) having L1 pages
-/// as sub-tree. In this particular case, `offset_to_subtree` = `l1page_byte_size`.
-///
-/// # Args
+impl SubTreeRank for LDNode {
+  fn locate(&self, rank: usize) -> EntryLocation {
+    let n_subtree_entries = self.sub_tree.n_entries();
+    let n_l1page_block_entries = self.n_l1page_elems * (n_subtree_entries + 1) + n_subtree_entries;
+    let (i, r) = (rank / (n_l1page_block_entries + 1), rank % (n_l1page_block_entries + 1));
+    if r < n_l1page_block_entries {
+      let st_offset = self.n_elems + (self.n_elems + 1) * self.n_l1page_elems;
+      locate_l1page(
+        r,
+        self.n_l1page_elems,
+        &*self.sub_tree,
+        self.n_elems + i * self.n_l1page_elems,
+        st_offset + i * (self.n_l1page_elems + 1) * n_subtree_entries,
+      )
+    } else {
+      EntryLocation::new(i, 1, 0, self.n_elems)
+    }
+  }
+
+  fn partition_point<I, V, IRW, VRW, P, B>(
+    &self,
+    pred: &P,
+    raw_entries: B,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    P: Fn(&V) -> bool,
+    B: RawBlock,
+  {
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    let l1page_byte_size = self.n_l1page_elems * entry_byte_size;
+    let subtree_group_byte_size =
+      (self.n_l1page_elems + 1) * self.sub_tree.byte_size(entry_byte_size);
+    let n_l1page_block_entries =
+      self.n_l1page_elems * (self.sub_tree.n_entries() + 1) + self.sub_tree.n_entries();
+    // Split the 3 blocs [ld][l1, l1, ..., l1][ST, ST, ..., ST]
+    let (ld_buff, r_buff) = raw_entries.split_at(self.n_elems * entry_byte_size);
+    let (l1_buff, st_buff) = r_buff.split_at((self.n_elems + 1) * l1page_byte_size);
+    let i = RawEntries::with_layout(&ld_buff.bytes()?, id_rw, val_rw, self.page_layout)
+      .partition_point(pred)?;
+    let from_l1 = i * l1page_byte_size;
+    let to_l1 = from_l1 + l1page_byte_size;
+    let from_st = i * subtree_group_byte_size;
+    let to_st = from_st + subtree_group_byte_size;
+    partition_point_l1page(
+      pred,
+      id_rw,
+      val_rw,
+      self.page_layout,
+      l1_buff.slice(from_l1..to_l1),
+      &*self.sub_tree,
+      st_buff.slice(from_st..to_st),
+    )
+    .map(|r| i * (n_l1page_block_entries + 1) + r)
+  }
+}
+
+///
+/// # Remark:
+/// A LD Leaf can be considered as a L1 page (with a small number of entries) having L1 pages
+/// as sub-tree. In this particular case, `offset_to_subtree` = `l1page_byte_size`.
+///
+/// # Args
 /// * `dest`: slice containing a group of L1 pages (or a single L1 page) followed by sub-trees.
 fn write_l1page<I, V, IRW, VRW, S, T>(
   mut it: T,
   id_rw: &IRW,
   val_rw: &VRW,
-  mut l1_buff: &mut [u8],
+  page_layout: PageLayout,
+  l1_buff: &mut [u8],
   sub_tree: &S,
   mut subtree_buff: &mut [u8],
 ) -> Result<T, Error>
@@ -1790,26 +2842,29 @@ where
     subtree_buff.len(),
     (n_l1 + 1) * subtree_byte_size
   );
+  let mut l1_entries = RawEntriesWriter::new(l1_buff, id_rw, val_rw, page_layout);
   for _ in 0..n_l1 {
     let (curr_buff, st_buff) = subtree_buff.split_at_mut(subtree_byte_size);
     it = sub_tree.write(it, id_rw, val_rw, curr_buff)?;
     subtree_buff = st_buff;
-    it.next()
-      .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?
-      .write(&mut l1_buff, id_rw, val_rw)?;
+    let entry = it
+      .next()
+      .ok_or_else(|| Error::new(ErrorKind::Other, "Iterator depleted!"))?;
+    l1_entries.push(entry)?;
   }
   it = sub_tree.write(it, id_rw, val_rw, subtree_buff)?;
-  assert!(l1_buff.is_empty());
+  assert!(l1_entries.is_full());
   Ok(it)
 }
 
-fn get_l1page<I, V, IRW, VRW, S>(
+fn get_l1page<I, V, IRW, VRW, S, B>(
   val: V,
   id_rw: &IRW,
   val_rw: &VRW,
-  l1_buff: &[u8],
+  page_layout: PageLayout,
+  l1_buff: B,
   sub_tree: &S,
-  subtree_buff: &[u8],
+  subtree_buff: B,
 ) -> Result<Option<Entry<I, V>>, Error>
 where
   I: Id,
@@ -1817,6 +2872,7 @@ where
   IRW: ReadWrite<Type = I>,
   VRW: ReadWrite<Type = V>,
   S: SubTreeR,
+  B: RawBlock,
 {
   assert!(!l1_buff.is_empty());
   let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
@@ -1824,24 +2880,127 @@ where
   let n_l1 = l1_buff.len() / entry_byte_size;
   assert_eq!(l1_buff.len(), n_l1 * entry_byte_size);
   assert_eq!(subtree_buff.len(), (n_l1 + 1) * subtree_byte_size);
-  let mut l1_entries = RawEntries::new(l1_buff, id_rw, val_rw);
+  let l1_bytes = l1_buff.bytes()?;
+  let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, page_layout);
   match l1_entries.binary_search(&val)? {
     Ok(i) => Ok(Some(l1_entries.get_entry(i)?)),
     Err(i) => {
       let from = i * subtree_byte_size;
       let to = from + subtree_byte_size;
-      sub_tree.get(val, &subtree_buff[from..to], id_rw, val_rw)
+      sub_tree.get(val, subtree_buff.slice(from..to), id_rw, val_rw)
+    }
+  }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn get_batch_l1page<I, V, IRW, VRW, R, S, B>(
+  values: &[V],
+  res: &mut [R],
+  id_rw: &IRW,
+  val_rw: &VRW,
+  page_layout: PageLayout,
+  l1_buff: B,
+  sub_tree: &S,
+  subtree_buff: B,
+) -> Result<(), Error>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+  R: BatchHit<I, V>,
+  S: SubTreeR,
+  B: RawBlock,
+{
+  assert!(!l1_buff.is_empty());
+  let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+  let subtree_byte_size = sub_tree.byte_size(entry_byte_size);
+  let n_l1 = l1_buff.len() / entry_byte_size;
+  assert_eq!(l1_buff.len(), n_l1 * entry_byte_size);
+  assert_eq!(subtree_buff.len(), (n_l1 + 1) * subtree_byte_size);
+  let l1_bytes = l1_buff.bytes()?;
+  let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, page_layout);
+  get_batch_entries(values, res, &l1_entries, |i, values, res| {
+    let from = i * subtree_byte_size;
+    let to = from + subtree_byte_size;
+    sub_tree.get_batch(values, res, subtree_buff.slice(from..to), id_rw, val_rw)
+  })
+}
+
+/// Looks for the given sorted values in the given node entries. The values not found are
+/// grouped by sub-tree: `get_in_subtree` is called once per group, with the index of the
+/// sub-tree (i.e. the index of the first entry larger than the values of the group).
+fn get_batch_entries<I, V, IRW, VRW, R, F>(
+  values: &[V],
+  res: &mut [R],
+  entries: &RawEntries<I, V, IRW, VRW>,
+  mut get_in_subtree: F,
+) -> Result<(), Error>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+  R: BatchHit<I, V>,
+  F: FnMut(usize, &[V], &mut [R]) -> Result<(), Error>,
+{
+  debug_assert_eq!(values.len(), res.len());
+  let mut k = 0;
+  while k < values.len() {
+    match entries.binary_search(&values[k])? {
+      Ok(i) => {
+        res[k] = R::found(entries.get_entry(i)?);
+        k += 1;
+      }
+      Err(i) => {
+        // The next values lower than the i-th entry are in the same sub-tree
+        let n = if i < entries.n_entries() {
+          let upper = entries.get_val(i)?;
+          values[k..].partition_point(|v| v < &upper)
+        } else {
+          values.len() - k
+        };
+        if R::SURROUNDING {
+          let lo = surrounding_entry(entries, i, 1)?;
+          let hi = surrounding_entry(entries, i, 0)?;
+          for res in &mut res[k..k + n] {
+            res.between(lo.clone(), hi.clone());
+          }
+        }
+        get_in_subtree(i, &values[k..k + n], &mut res[k..k + n])?;
+        k += n;
+      }
     }
   }
+  Ok(())
+}
+
+/// Returns the entry located `shift` positions before the given insertion point, if any.
+fn surrounding_entry<I, V, IRW, VRW>(
+  entries: &RawEntries<I, V, IRW, VRW>,
+  insertion_point: usize,
+  shift: usize,
+) -> Result<Option<Entry<I, V>>, Error>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  match insertion_point.checked_sub(shift) {
+    Some(i) if i < entries.n_entries() => entries.get_entry(i).map(Some),
+    _ => Ok(None),
+  }
 }
 
-fn visit_l1page<I, V, IRW, VRW, S, T>(
+fn visit_l1page<I, V, IRW, VRW, S, T, B>(
   mut visitor: T,
   id_rw: &IRW,
   val_rw: &VRW,
-  l1_buff: &[u8],
+  page_layout: PageLayout,
+  l1_buff: B,
   sub_tree: &S,
-  subtree_buff: &[u8],
+  subtree_buff: B,
 ) -> Result<T, Error>
 where
   I: Id,
@@ -1850,6 +3009,7 @@ where
   VRW: ReadWrite<Type = V>,
   S: SubTreeR,
   T: Visitor<I = I, V = V>,
+  B: RawBlock,
 {
   assert!(!l1_buff.is_empty());
   let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
@@ -1857,26 +3017,27 @@ where
   let n_l1 = l1_buff.len() / entry_byte_size;
   assert_eq!(l1_buff.len(), n_l1 * entry_byte_size);
   assert_eq!(subtree_buff.len(), (n_l1 + 1) * subtree_byte_size);
-  let mut l1_entries = RawEntries::new(l1_buff, id_rw, val_rw);
+  let l1_bytes = l1_buff.bytes()?;
+  let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, page_layout);
   let (mut l, mut r) = match l1_entries.binary_search(visitor.center())? {
     Ok(i) => {
       visitor.visit_center(l1_entries.get_entry(i)?);
       if visitor.visit_desc() {
         let from = i * subtree_byte_size;
         let to = from + subtree_byte_size;
-        visitor = sub_tree.visit_desc(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+        visitor = sub_tree.visit_desc(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
       }
       if visitor.visit_asc() {
         let from = (i + 1) * subtree_byte_size;
         let to = from + subtree_byte_size;
-        visitor = sub_tree.visit_asc(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+        visitor = sub_tree.visit_asc(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
       }
       (i as i32 - 1, i + 1)
     }
     Err(i) => {
       let from = i * subtree_byte_size;
       let to = from + subtree_byte_size;
-      visitor = sub_tree.visit(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+      visitor = sub_tree.visit(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
       (i as i32 - 1, i)
     }
   };
@@ -1890,7 +3051,7 @@ where
     }
     let from = l as usize * subtree_byte_size;
     let to = from + subtree_byte_size;
-    visitor = sub_tree.visit_desc(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+    visitor = sub_tree.visit_desc(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
     l -= 1;
   }
   while r < n_l1 {
@@ -1903,19 +3064,20 @@ where
     }
     let from = (r + 1) * subtree_byte_size;
     let to = from + subtree_byte_size;
-    visitor = sub_tree.visit_asc(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+    visitor = sub_tree.visit_asc(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
     r += 1;
   }
   Ok(visitor)
 }
 
-fn visit_desc_l1page<I, V, IRW, VRW, S, T>(
+fn visit_desc_l1page<I, V, IRW, VRW, S, T, B>(
   mut visitor: T,
   id_rw: &IRW,
   val_rw: &VRW,
-  l1_buff: &[u8],
+  page_layout: PageLayout,
+  l1_buff: B,
   sub_tree: &S,
-  subtree_buff: &[u8],
+  subtree_buff: B,
 ) -> Result<T, Error>
 where
   I: Id,
@@ -1924,6 +3086,7 @@ where
   VRW: ReadWrite<Type = V>,
   S: SubTreeR,
   T: Visitor<I = I, V = V>,
+  B: RawBlock,
 {
   assert!(!l1_buff.is_empty());
   let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
@@ -1931,10 +3094,11 @@ where
   let n_l1 = l1_buff.len() / entry_byte_size;
   assert_eq!(l1_buff.len(), n_l1 * entry_byte_size);
   assert_eq!(subtree_buff.len(), (n_l1 + 1) * subtree_byte_size);
-  let mut l1_entries = RawEntries::new(l1_buff, id_rw, val_rw);
+  let l1_bytes = l1_buff.bytes()?;
+  let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, page_layout);
   let from = n_l1 * subtree_byte_size;
   let to = from + subtree_byte_size;
-  visitor = sub_tree.visit_desc(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+  visitor = sub_tree.visit_desc(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
   let mut i = 0;
   while i < n_l1 && visitor.visit_desc() {
     visitor.visit_le_center(l1_entries.get_entry(i)?);
@@ -1943,19 +3107,20 @@ where
     }
     let from = i * subtree_byte_size;
     let to = from + subtree_byte_size;
-    visitor = sub_tree.visit_desc(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+    visitor = sub_tree.visit_desc(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
     i += 1;
   }
   Ok(visitor)
 }
 
-fn visit_asc_l1page<I, V, IRW, VRW, S, T>(
+fn visit_asc_l1page<I, V, IRW, VRW, S, T, B>(
   mut visitor: T,
   id_rw: &IRW,
   val_rw: &VRW,
-  l1_buff: &[u8],
+  page_layout: PageLayout,
+  l1_buff: B,
   sub_tree: &S,
-  subtree_buff: &[u8],
+  subtree_buff: B,
 ) -> Result<T, Error>
 where
   I: Id,
@@ -1964,6 +3129,7 @@ where
   VRW: ReadWrite<Type = V>,
   S: SubTreeR,
   T: Visitor<I = I, V = V>,
+  B: RawBlock,
 {
   assert!(!l1_buff.is_empty());
   let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
@@ -1971,12 +3137,13 @@ where
   let n_l1 = l1_buff.len() / entry_byte_size;
   assert_eq!(l1_buff.len(), n_l1 * entry_byte_size);
   assert_eq!(subtree_buff.len(), (n_l1 + 1) * subtree_byte_size);
-  let mut l1_entries = RawEntries::new(l1_buff, id_rw, val_rw);
+  let l1_bytes = l1_buff.bytes()?;
+  let l1_entries = RawEntries::with_layout(&l1_bytes, id_rw, val_rw, page_layout);
   let mut i = 0;
   while i < n_l1 {
     let from = i * subtree_byte_size;
     let to = from + subtree_byte_size;
-    visitor = sub_tree.visit_asc(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+    visitor = sub_tree.visit_asc(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
     if !visitor.visit_asc() {
       break;
     }
@@ -1989,16 +3156,100 @@ where
   if i == n_l1 {
     let from = i * subtree_byte_size;
     let to = from + subtree_byte_size;
-    visitor = sub_tree.visit_asc(visitor, &subtree_buff[from..to], id_rw, val_rw)?;
+    visitor = sub_tree.visit_asc(visitor, subtree_buff.slice(from..to), id_rw, val_rw)?;
   }
   Ok(visitor)
 }
 
+/// Returns the location (see `SubTreeRank::locate`) of the entry having the given rank in a L1
+/// page followed by its sub-trees.
+/// # Args
+/// * `rank`: rank of the entry in the block made of the L1 page and its sub-trees
+/// * `n_l1`: number of entries in the L1 page
+/// * `sub_tree`: type of the sub-trees
+/// * `l1_offset`: index of the first entry of the L1 page
+/// * `subtree_offset`: index of the first entry of the first sub-tree
+fn locate_l1page<S: SubTreeRank>(
+  rank: usize,
+  n_l1: usize,
+  sub_tree: &S,
+  l1_offset: usize,
+  subtree_offset: usize,
+) -> EntryLocation {
+  let n_subtree_entries = sub_tree.n_entries();
+  let (i, r) = (rank / (n_subtree_entries + 1), rank % (n_subtree_entries + 1));
+  if r < n_subtree_entries {
+    sub_tree
+      .locate(r)
+      .shift(subtree_offset + i * n_subtree_entries)
+  } else {
+    debug_assert!(i < n_l1);
+    EntryLocation::new(l1_offset + i, 1, l1_offset, l1_offset + n_l1)
+  }
+}
+
+fn partition_point_l1page<I, V, IRW, VRW, S, P, B>(
+  pred: &P,
+  id_rw: &IRW,
+  val_rw: &VRW,
+  page_layout: PageLayout,
+  l1_buff: B,
+  sub_tree: &S,
+  subtree_buff: B,
+) -> Result<usize, Error>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+  S: SubTreeRank,
+  P: Fn(&V) -> bool,
+  B: RawBlock,
+{
+  assert!(!l1_buff.is_empty());
+  let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+  let subtree_byte_size = sub_tree.byte_size(entry_byte_size);
+  let i = RawEntries::with_layout(&l1_buff.bytes()?, id_rw, val_rw, page_layout)
+    .partition_point(pred)?;
+  let from = i * subtree_byte_size;
+  let to = from + subtree_byte_size;
+  sub_tree
+    .partition_point(pred, subtree_buff.slice(from..to), id_rw, val_rw)
+    .map(|r| i * (sub_tree.n_entries() + 1) + r)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BSTreeMeta {
   pub types: IdVal,
   constants: BSTreeConstants,
   pub layout: BSTreeLayout,
+  nulls: BSTreeNulls,
+  checksums: Option<BSTreeChecksums>,
+  norm: StrNorm,
+  encoding: ValEncoding,
+}
+
+/// Metadata of legacy files (format `0.0`), written before the addition of the explicit format
+/// version.
+#[derive(Deserialize)]
+struct LegacyBSTreeMeta {
+  types: IdVal,
+  constants: LegacyBSTreeConstants,
+  layout: BSTreeLayout,
+}
+
+impl From<LegacyBSTreeMeta> for BSTreeMeta {
+  fn from(meta: LegacyBSTreeMeta) -> Self {
+    BSTreeMeta {
+      types: meta.types,
+      constants: meta.constants.into(),
+      layout: meta.layout,
+      nulls: Default::default(),
+      checksums: None,
+      norm: Default::default(),
+      encoding: Default::default(),
+    }
+  }
 }
 
 impl BSTreeMeta {
@@ -2008,13 +3259,23 @@ impl BSTreeMeta {
     entry_byte_size: usize,
     l1_byte_size: usize,
     ld_byte_size: usize,
+    n_nulls: usize,
+    id_byte_size: usize,
   ) -> BSTreeMeta {
     let constants = BSTreeConstants::new(n_entries, entry_byte_size, l1_byte_size, ld_byte_size);
     let layout = BSTreeLayout::new(&constants);
+    let nulls = BSTreeNulls {
+      n_nulls: n_nulls as u64,
+      id_byte_size: id_byte_size as u8,
+    };
     BSTreeMeta {
       types,
       constants,
       layout,
+      nulls,
+      checksums: None,
+      norm: Default::default(),
+      encoding: Default::default(),
     }
   }
 
@@ -2022,11 +3283,115 @@ impl BSTreeMeta {
     self.layout.get_root(&self.constants)
   }
 
+  /// Total number of entries stored in the tree.
+  pub fn n_entries(&self) -> usize {
+    self.constants.n_entries as usize
+  }
+
+  /// Number of bytes used to store a single entry.
+  pub fn entry_byte_size(&self) -> usize {
+    self.constants.entry_byte_size as usize
+  }
+
+  /// Number of identifiers stored in the NULL values block.
+  pub fn n_nulls(&self) -> usize {
+    self.nulls.n_nulls as usize
+  }
+
+  /// Size, in bytes, of the NULL values block (located between the metadata and the tree data).
+  pub fn nulls_byte_size(&self) -> usize {
+    self.nulls.n_nulls as usize * self.nulls.id_byte_size as usize
+  }
+
+  /// Size, in bytes, of the data part of the file (i.e. of the tree).
+  pub fn data_byte_size(&self) -> usize {
+    self.n_entries() * self.entry_byte_size()
+  }
+
+  /// Returns `true` if the file ends with a checksums section.
+  pub fn has_checksums(&self) -> bool {
+    self.checksums.is_some()
+  }
+
+  /// Normalisation applied to the string values, both at build and at query time.
+  pub fn norm(&self) -> &StrNorm {
+    &self.norm
+  }
+
+  /// Encoding of the values stored in the tree.
+  pub fn encoding(&self) -> ValEncoding {
+    self.encoding
+  }
+
+  /// Layout of the entries inside the pages.
+  pub fn page_layout(&self) -> PageLayout {
+    self.constants.page_layout
+  }
+
+  /// Size, in bytes, of the data blocks a checksum is computed on (`None` if the file
+  /// contains no checksums section).
+  pub fn checksum_block_byte_size(&self) -> Option<usize> {
+    self
+      .checksums
+      .as_ref()
+      .map(|c| c.block_byte_size as usize)
+  }
+
+  /// Number of data blocks a checksum is computed on (0 if the file contains no checksums section).
+  pub fn n_checksum_blocks(&self) -> usize {
+    self
+      .checksum_block_byte_size()
+      .map(|block_byte_size| self.data_byte_size().div_ceil(block_byte_size))
+      .unwrap_or(0)
+  }
+
+  /// Size, in bytes, of the checksums section (located after the tree data):
+  /// one CRC32 per data block, plus the CRC32 of the whole file.
+  pub fn checksums_byte_size(&self) -> usize {
+    if self.has_checksums() {
+      (self.n_checksum_blocks() + 1) * CHECKSUM_BYTE_SIZE
+    } else {
+      0
+    }
+  }
+
+  /// Set the checksums section, a checksum being computed on each LD block worth of data.
+  fn set_checksums(&mut self) {
+    let cte = &self.constants;
+    // nLD = nL1InLD * (nL1 + 1) - 1
+    let n_entries_per_ldpage = ((cte.n_l1page_per_ldpage as usize)
+      * (cte.n_entries_per_l1page as usize + 1))
+      .saturating_sub(1);
+    let block_byte_size = n_entries_per_ldpage.max(1) * cte.entry_byte_size as usize;
+    self.checksums = Some(BSTreeChecksums {
+      block_byte_size: block_byte_size as u64,
+    });
+  }
+
   /*fn get_data_byte_size(&self) -> usize {
     (self.constants.n_entries * (self.constants.entry_byte_size as u64)) as usize
   }*/
 }
 
+/// Block storing the identifiers of the rows having a NULL value (so not stored in the tree),
+/// in the order they have been provided (i.e. ordered by `id` for record numbers).
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BSTreeNulls {
+  /// Number of identifiers in the block
+  n_nulls: u64,
+  /// Number of bytes used to store a single identifier
+  id_byte_size: u8,
+}
+
+/// Section, following the tree data, storing the CRC32 (little-endian `u32`) of each block of
+/// `block_byte_size` bytes of the tree data (the last block may be smaller), followed by the CRC32
+/// of all the previous bytes of the file.
+#[derive(Debug, Serialize, Deserialize)]
+struct BSTreeChecksums {
+  /// Number of bytes of data a single checksum is computed on (the byte size of a LD block)
+  block_byte_size: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct BSTreeConstants {
   /// Total number of entries in the tree
@@ -2043,6 +3408,29 @@ struct BSTreeConstants {
   /// A LD block contains `nL1InLD - 1` entries plus the `nL1InLD * nL1` entries in the L1 pages.
   /// Thus, the total number of entries in a LD block is `nLD = (nL1InLD - 1 + nL1InLD * nL1`
   n_l1page_per_ldpage: u16,
+  /// Layout of the entries inside each page (L1 pages and LD blocks entries).
+  page_layout: PageLayout,
+}
+
+/// Constants of legacy files (format `0.0`).
+#[derive(Deserialize)]
+struct LegacyBSTreeConstants {
+  n_entries: u64,
+  entry_byte_size: u8,
+  n_entries_per_l1page: u16,
+  n_l1page_per_ldpage: u16,
+}
+
+impl From<LegacyBSTreeConstants> for BSTreeConstants {
+  fn from(cte: LegacyBSTreeConstants) -> Self {
+    BSTreeConstants {
+      n_entries: cte.n_entries,
+      entry_byte_size: cte.entry_byte_size,
+      n_entries_per_l1page: cte.n_entries_per_l1page,
+      n_l1page_per_ldpage: cte.n_l1page_per_ldpage,
+      page_layout: Default::default(),
+    }
+  }
 }
 
 impl BSTreeConstants {
@@ -2068,6 +3456,7 @@ impl BSTreeConstants {
       entry_byte_size: entry_byte_size as u8,
       n_entries_per_l1page: n_entries_per_l1page as u16, // : l1_byte_size as u16,
       n_l1page_per_ldpage: n_l1page_per_ldpage as u16,   //: ld_byte_size as u16
+      page_layout: Default::default(),
     }
   }
 
@@ -2216,11 +3605,15 @@ impl BSTreeLayout {
   fn get_root(&self, cte: &BSTreeConstants) -> Root {
     match (self.depth, self.depth & 1, self.rigthmost_subtree.as_ref()) {
       // Depth 0
-      (0, _, _) => Root::L1Leaf(L1Leaf::new(self.n_entries_root as usize)),
+      (0, _, _) => Root::L1Leaf(L1Leaf::new(self.n_entries_root as usize, cte.page_layout)),
       // Depth 1
       (1, _, None) => Root::L1Node(
         // Used as a LDLeaf
-        L1Node::new(self.n_entries_root as usize, self.get_subtree(1, cte)),
+        L1Node::new(
+          self.n_entries_root as usize,
+          self.get_subtree(1, cte),
+          cte.page_layout,
+        ),
       ),
       (1, _, Some(sub_layout)) => Root::RootL1Node(
         // Used as a LDLeaf
@@ -2228,6 +3621,7 @@ impl BSTreeLayout {
           self.n_entries_root as usize,
           self.get_subtree(1, cte),
           sub_layout.get_root(cte),
+          cte.page_layout,
         ),
       ),
       // Other depth
@@ -2235,23 +3629,27 @@ impl BSTreeLayout {
       (_, 0, None) => Root::L1Node(L1Node::new(
         self.n_entries_root as usize,
         self.get_subtree(1, cte),
+        cte.page_layout,
       )),
       (_, 1, None) => Root::LDNode(LDNode::new(
         self.n_entries_root as usize,
         cte.n_entries_per_l1page as usize,
         self.get_ld_subtree(2, cte),
+        cte.page_layout,
       )),
       // - frequent cases
       (_, 0, Some(sub_layout)) => Root::RootL1Node(RootL1Node::new(
         self.n_entries_root as usize,
         self.get_subtree(1, cte),
         sub_layout.get_root(cte),
+        cte.page_layout,
       )),
       (_, 1, Some(sub_layout)) => Root::RootLDNode(RootLDNode::new(
         self.n_entries_root as usize,
         cte.n_entries_per_l1page as usize,
         self.get_ld_subtree(2, cte),
         sub_layout.get_root(cte),
+        cte.page_layout,
       )),
       (_, _, _) => unreachable!(),
     }
@@ -2259,18 +3657,20 @@ impl BSTreeLayout {
 
   fn get_subtree(&self, d: u8, cte: &BSTreeConstants) -> SubTree {
     if d == self.depth {
-      SubTree::L1Leaf(L1Leaf::new(cte.n_entries_per_l1page as usize))
+      SubTree::L1Leaf(L1Leaf::new(cte.n_entries_per_l1page as usize, cte.page_layout))
     } else if d == (self.depth - 1) {
       SubTree::L1Node(L1Node::new(
         // Used as a LDLeaf
         cte.n_l1page_per_ldpage as usize - 1,
         self.get_subtree(d + 1, cte),
+        cte.page_layout,
       ))
     } else {
       SubTree::LDNode(LDNode::new(
         cte.n_l1page_per_ldpage as usize - 1,
         cte.n_entries_per_l1page as usize,
         self.get_ld_subtree(d + 2, cte),
+        cte.page_layout,
       ))
     }
   }
@@ -2282,12 +3682,14 @@ impl BSTreeLayout {
         // Used as a LDLeaf
         cte.n_l1page_per_ldpage as usize - 1,
         self.get_subtree(d + 1, cte),
+        cte.page_layout,
       ))
     } else {
       LDSubTree::LDNode(LDNode::new(
         cte.n_l1page_per_ldpage as usize - 1,
         cte.n_entries_per_l1page as usize,
         self.get_ld_subtree(d + 2, cte),
+        cte.page_layout,
       ))
     }
   }
@@ -2322,51 +3724,151 @@ where
   IRW: ReadWrite<Type = I>,
   VRW: ReadWrite<Type = V>,
   T: Iterator<Item = Entry<I, V>>,
+{
+  build_with_options(
+    output_file,
+    mem_args,
+    n_entries,
+    entries_iterator,
+    std::iter::empty(),
+    types,
+    id_rw,
+    val_rw,
+    &BuildOptions::default(),
+  )
+}
+
+/// Optional features of a tree file, set at build time.
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+  /// Add, after the tree data, a section storing the CRC32 of each LD block and of the whole file
+  pub checksums: bool,
+  /// Normalisation of the string values, recorded in the metadata (the values provided to the
+  /// build must already be normalised)
+  pub norm: StrNorm,
+  /// Encoding of the values, recorded in the metadata
+  pub encoding: ValEncoding,
+  /// Layout of the entries inside the pages, recorded in the metadata
+  pub page_layout: PageLayout,
+}
+
+/// Same as [build](fn.build.html), but also stores (in a block located between the metadata
+/// and the tree) the identifiers of the rows having a NULL value, and takes build options.
+/// # Args
+/// * `nulls_iterator`: identifiers of the rows having a NULL value
+/// * `opts`: optional features of the file
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(clippy::too_many_arguments)]
+pub fn build_with_options<I, V, IRW, VRW, T, N>(
+  output_file: PathBuf,
+  mem_args: &MemSizeArgs,
+  n_entries: usize,
+  entries_iterator: T,
+  nulls_iterator: N,
+  types: &IdVal,
+  id_rw: &IRW,
+  val_rw: &VRW,
+  opts: &BuildOptions,
+) -> Result<(), Error>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+  T: Iterator<Item = Entry<I, V>>,
+  N: ExactSizeIterator<Item = I>,
 {
   // KMerge<TmpFileIter<'a, I, V, IRW, VRW>>
 
   // Decorate with an iterator that ensure that the input iterator is sorted?
   let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
-  let meta = BSTreeMeta::from(
+  let mut meta = BSTreeMeta::from(
     types.clone(),
     n_entries,
     entry_byte_size,
     mem_args.l1_byte_size(),
     mem_args.disk_byte_size(),
+    nulls_iterator.len(),
+    id_rw.n_bytes(),
   );
+  if opts.checksums {
+    meta.set_checksums();
+  }
+  if !opts.norm.is_identity() {
+    if !val_rw.is_str() {
+      return Err(Error::new(
+        ErrorKind::Other,
+        "Normalisation is only supported on string values",
+      ));
+    }
+    meta.norm = opts.norm;
+  }
+  let val_rw = &EncodedRW::new(val_rw.clone(), opts.encoding)?;
+  meta.encoding = opts.encoding;
+  meta.constants.page_layout = opts.page_layout;
   let encoded_meta: Vec<u8> = bincode::serialize(&meta).unwrap();
+  if encoded_meta.len() > u16::MAX as usize {
+    return Err(
+      BSTreeError::MetaTooLarge {
+        byte_size: encoded_meta.len(),
+      }
+      .into(),
+    );
+  }
   // Open file
   let file = OpenOptions::new()
     .read(true)
     .write(true)
     .create(true)
+    .truncate(true)
     .open(output_file)?;
-  // dbg!(File::create(&output_file))?;
-  let before_meta_len = FILE_TYPE.len() + 3 + 2;
-  let data_starting_byte = before_meta_len + encoded_meta.len();
-  let file_byte_size = data_starting_byte + n_entries * entry_byte_size;
+  let nulls_starting_byte = HEADER_BYTE_SIZE + encoded_meta.len();
+  let data_starting_byte = nulls_starting_byte + meta.nulls_byte_size();
+  let data_ending_byte = data_starting_byte + meta.data_byte_size();
+  let file_byte_size = data_ending_byte + meta.checksums_byte_size();
   // Reserve space
   file.set_len(file_byte_size as u64)?;
   // Write file
   let mut mmap = unsafe { MmapMut::map_mut(&file)? };
   // - meta
-  write_meta(&mut mmap[0..data_starting_byte], encoded_meta)?;
+  write_meta(&mut mmap[0..nulls_starting_byte], encoded_meta)?;
+  // - nulls
+  let mut nulls_buff = &mut mmap[nulls_starting_byte..data_starting_byte];
+  for id in nulls_iterator {
+    id_rw.write(&mut nulls_buff, &id)?;
+  }
+  if !nulls_buff.is_empty() {
+    return Err(Error::new(
+      ErrorKind::Other,
+      "Less NULL values identifiers than expected",
+    ));
+  }
   mmap.flush_range(0, data_starting_byte)?;
-  // - data
+  // - data (the search strategy is not used to write the tree)
   let root = meta.get_root();
   root.write(
     entries_iterator,
     id_rw,
     val_rw,
-    &mut mmap[data_starting_byte..file_byte_size],
+    &mut mmap[data_starting_byte..data_ending_byte],
   )?;
+  // - checksums
+  if let Some(block_byte_size) = meta.checksum_block_byte_size() {
+    let (content, mut checksums_buff) = mmap.split_at_mut(data_ending_byte);
+    for block in content[data_starting_byte..].chunks(block_byte_size) {
+      checksums_buff.write_u32::<LittleEndian>(crc32fast::hash(block))?;
+    }
+    let file_checksum = crc32fast::hash(&mmap[..file_byte_size - CHECKSUM_BYTE_SIZE]);
+    (&mut mmap[file_byte_size - CHECKSUM_BYTE_SIZE..]).write_u32::<LittleEndian>(file_checksum)?;
+  }
   mmap.flush()?;
   file.sync_all()
 }
 
-fn write_meta(mut buff: &mut [u8], encoded_meta: Vec<u8>) -> Result<(), Error> {
+fn write_meta(mut buff: &mut [u8], encoded_meta: Vec<u8>) -> Result<(), BSTreeError> {
   let v_nums = parse_version().unwrap();
   buff.write_all(FILE_TYPE)?;
+  buff.write_all(&[FORMAT_MARKER, FORMAT_VERSION.major, FORMAT_VERSION.minor])?;
   buff.write_all(&v_nums)?;
   buff.write_u16::<LittleEndian>(encoded_meta.len() as u16)?;
   assert_eq!(buff.len(), encoded_meta.len());
@@ -2374,188 +3876,1123 @@ fn write_meta(mut buff: &mut [u8], encoded_meta: Vec<u8>) -> Result<(), Error> {
   Ok(())
 }
 
-// Plan a read taking readers!
-/*
-fn read(input_file: PathBuf) -> Result<Root, Error> {
-  // Get the size of the file
-  let metadata = fs::metadata(&input_file)?;
-  let byte_size = metadata.len();
-  // Open the file and read the metadata part
-  let file = File::open(&input_file)?;
-  let mmap = unsafe { MmapOptions::new().map(&file)? };
-  let (_version, data_starting_byte, meta) = read_meta(&mmap)?;
-  assert_eq!(byte_size - (data_starting_byte as u64), meta.get_data_byte_size() as u64);
-  let root = meta.get_root();
-  Ok(root)
-}*/
-
+/// A read-only tree file, memory mapped (or read with positioned reads, see
+/// [open_pread](#method.open_pread)), together with its metadata.
+///
+/// It avoids to have to open and map the file, to read the metadata and to build the root
+/// node each time a query has to be executed.
+/// The typed query methods take the `id` and `val` codecs in parameter: in a generic context,
+/// they are provided by `IdVal::exec` (see the [Process](../trait.Process.html) trait).
+/// The `val` codec is the one of the value type: the encoding of the values stored in the
+/// tree (see [encoding](#method.encoding)) is applied internally.
+///
+/// # Example
+/// ```rust,no_run
+/// use bstree_file_readonly::{bstree::BSTreeFile, rw::U64RW};
+///
+/// let bstree = BSTreeFile::open("test.bstree").unwrap();
+/// let entry = bstree.get(42_u64, &U64RW, &U64RW).unwrap();
+/// ```
 #[cfg(not(target_arch = "wasm32"))]
-struct GetProcess<'a> {
-  value: String,
-  meta: &'a BSTreeMeta,
-  mmap: &'a Mmap,
+pub struct BSTreeFile<S: BlockSource = MmapSource> {
+  source: S,
+  format_version: FormatVersion,
+  version: [u8; 3],
   data_starting_byte: usize,
+  meta: BSTreeMeta,
+  root: Root,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-impl<'a> Process for GetProcess<'a> {
-  type Output = Option<(String, String)>;
+impl BSTreeFile {
+  /// Open and memory map the given file, and read its metadata.
+  /// Fails if the file is not a tree file, if its size is not consistent with its metadata or
+  /// if its types are not supported by the compiled code.
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<BSTreeFile, BSTreeError> {
+    if std::fs::metadata(&path)?.len() == 0 {
+      // Empty files can not be memory mapped
+      return Err(BSTreeError::Truncated {
+        expected: LEGACY_HEADER_BYTE_SIZE,
+        actual: 0,
+      });
+    }
+    BSTreeFile::from_source(MmapSource::open(path)?)
+  }
 
-  fn exec<I, V, D, IRW, VRW>(
-    self,
-    _types: IdVal,
-    id_rw: IRW,
-    val_rw: VRW,
-    _dist: D,
-  ) -> Result<Self::Output, std::io::Error>
-  where
-    I: Id,
-    V: Val,
-    D: Fn(&V, &V) -> V,
-    IRW: ReadWrite<Type = I>,
-    VRW: ReadWrite<Type = V>,
-  {
-    let v = self
-      .value
-      .parse::<V>()
-      .map_err(|_e| Error::new(ErrorKind::Other, ""))?; // V::from_str(&self.value).unwrap();
-    let root = self.meta.get_root();
-    let opt_entry = root.get(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-    Ok(opt_entry.map(|Entry { id, val }| (format!("{:?}", id), format!("{:?}", val))))
+  /// Returns the data part of the file, i.e. the raw entries.
+  pub fn data(&self) -> &[u8] {
+    self.data_block()
   }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-struct GetExactProcess<'a> {
-  value: String,
-  meta: &'a BSTreeMeta,
-  mmap: &'a Mmap,
-  data_starting_byte: usize,
+#[cfg(all(not(target_arch = "wasm32"), any(unix, windows)))]
+impl BSTreeFile<PreadSource> {
+  /// Same as [open](#method.open), but the file is read with positioned reads instead of being
+  /// memory mapped.
+  /// # Args
+  /// * `cache_byte_size`: maximum number of bytes kept in the cache of the last read pages
+  ///   (see `source::DEFAULT_CACHE_BYTE_SIZE`)
+  pub fn open_pread<P: AsRef<Path>>(
+    path: P,
+    cache_byte_size: usize,
+  ) -> Result<BSTreeFile<PreadSource>, BSTreeError> {
+    BSTreeFile::from_source(PreadSource::open(path, cache_byte_size)?)
+  }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-impl<'a> Process for GetExactProcess<'a> {
-  type Output = Option<(String, String)>;
+impl<S: BlockSource> BSTreeFile<S> {
+  /// Read the metadata of the tree file provided by the given source.
+  pub fn from_source(source: S) -> Result<BSTreeFile<S>, BSTreeError> {
+    let actual = source.byte_size();
+    // Read the header to get the metadata size, and then the header plus the metadata
+    let meta_ending_byte =
+      meta_ending_byte(&source.block(0, actual.min(HEADER_BYTE_SIZE)).bytes()?).min(actual);
+    let (format_version, version, data_starting_byte, meta) =
+      read_meta(&source.block(0, meta_ending_byte).bytes()?)?;
+    if !meta.types.is_in_mem_supported() {
+      return Err(BSTreeError::UnsupportedTypes(meta.types));
+    }
+    let expected = data_starting_byte + meta.data_byte_size() + meta.checksums_byte_size();
+    if actual < expected {
+      return Err(BSTreeError::Truncated { expected, actual });
+    } else if actual > expected {
+      return Err(BSTreeError::FileSizeMismatch { expected, actual });
+    }
+    let root = meta.get_root();
+    Ok(BSTreeFile {
+      source,
+      format_version,
+      version,
+      data_starting_byte,
+      meta,
+      root,
+    })
+  }
 
-  fn exec<I, V, D, IRW, VRW>(
-    self,
-    _types: IdVal,
-    id_rw: IRW,
-    val_rw: VRW,
-    _dist: D,
-  ) -> Result<Self::Output, std::io::Error>
-  where
-    I: Id,
-    V: Val,
-    D: Fn(&V, &V) -> V,
-    IRW: ReadWrite<Type = I>,
-    VRW: ReadWrite<Type = V>,
-  {
-    let v = self
-      .value
-      .parse::<V>()
-      .map_err(|_e| Error::new(ErrorKind::Other, ""))?; // V::from_str(&self.value).unwrap();
-    let visitor = VisitorExact::new(v);
+  /// Version of the format of the file.
+  pub fn format_version(&self) -> FormatVersion {
+    self.format_version
+  }
 
-    let root = self.meta.get_root();
-    let visitor = root.visit(
-      visitor,
-      &self.mmap[self.data_starting_byte..],
-      &id_rw,
-      &val_rw,
-    )?;
-    Ok(
-      visitor
-        .entry
-        .map(|Entry { id, val }| (format!("{:?}", id), format!("{:?}", val))),
-    )
+  /// Version of the code used to build the tree (`[major, minor, patch]`).
+  pub fn version(&self) -> [u8; 3] {
+    self.version
   }
-}
 
-/*
-// Plan a read taking readers!
-fn get(value: String, input_file: PathBuf) -> Result<(), Error> {
-  let now = Instant::now();
-  // Get the size of the file
-  let metadata = fs::metadata(&input_file)?;
-  let byte_size = metadata.len();
-  // Open the file and read the metadata part
-  let file = File::open(&input_file)?;
-  let mmap = unsafe { MmapOptions::new().map(&file)? };
-  let (_version, data_starting_byte, meta) = read_meta(&mmap)?;
-  println!("File read in {:?} ms", now.elapsed().as_millis());
-  println!("Struct: {:?}", &meta);
-  assert_eq!(byte_size - (data_starting_byte as u64), meta.get_data_byte_size() as u64);
-
-  let now = Instant::now();
-  let idval = &meta.types;
-  let p = GetProcess {
-    value,
-    meta: &meta,
-    mmap: &mmap,
-    data_starting_byte,
-  };
-  if let Some((id, val)) = idval.exec(p)? {
-    println!("Value found: id: {}, val: {} in {} ms", id, val, now.elapsed().as_millis());
-  } else {
-    println!("Not found in {} ms", now.elapsed().as_millis());
+  pub fn meta(&self) -> &BSTreeMeta {
+    &self.meta
   }
-  Ok(())
-}
 
-// Plan a read taking readers!
-fn get_v2(value: String, input_file: PathBuf) -> Result<(), Error> {
-  let now = Instant::now();
-  // Get the size of the file
-  let metadata = fs::metadata(&input_file)?;
-  let byte_size = metadata.len();
-  // Open the file and read the metadata part
-  let file = File::open(&input_file)?;
-  let mmap = unsafe { MmapOptions::new().map(&file)? };
-  let (_version, data_starting_byte, meta) = read_meta(&mmap)?;
-  println!("File read in {:?} ms", now.elapsed().as_millis());
-  println!("Struct: {:?}", &meta);
-  assert_eq!(byte_size - (data_starting_byte as u64), meta.get_data_byte_size() as u64);
-
-  let now = Instant::now();
-  let idval = &meta.types;
-  let p = GetExactProcess {
-    value,
-    meta: &meta,
-    mmap: &mmap,
-    data_starting_byte,
-  };
-  if let Some((id, val)) = idval.exec(p)? {
-    println!("Value found: id: {}, val: {} in {} ms", id, val, now.elapsed().as_millis());
-  } else {
-    println!("Not found in {} ms", now.elapsed().as_millis());
+  /// Types of the identifiers and of the values stored in the tree.
+  pub fn types(&self) -> &IdVal {
+    &self.meta.types
   }
-  Ok(())
-}
-*/
 
-/// Returns:
-/// * `[u8; 3]`: the version of the code used to build the tree
-/// * `usize`: the index of the first data byte
-/// * `BSTreeMeta`: the tree structure informations
-pub fn read_meta(mut buff: &[u8]) -> Result<([u8; 3], usize, BSTreeMeta), Error> {
-  let mut file_type = *FILE_TYPE;
-  buff.read_exact(&mut file_type)?;
-  assert_eq!((*FILE_TYPE), file_type);
-  let mut v_nums: [u8; 3] = Default::default();
-  buff.read_exact(&mut v_nums)?;
-  // eprintln!("File content: {} v{}.{}.{}", from_utf8(&file_type).unwrap(), v_nums[0], v_nums[1], v_nums[2]);
-  let meta_byte_size = buff.read_u16::<LittleEndian>()? as usize;
-  let meta: BSTreeMeta = bincode::deserialize_from(&buff[..meta_byte_size])
-    .map_err(|_e| Error::new(ErrorKind::Other, String::from("Unable to dezerialize meta")))?;
-  Ok((v_nums, file_type.len() + 3 + 2 + meta_byte_size, meta))
-}
+  pub fn root(&self) -> &Root {
+    &self.root
+  }
 
-/*
-fn read_id<I, V, IRW, VRW>(&self, val: V, raw_entries: &[u8], id_rw: &IRW, val_rw: &VRW) -> Result<I, Error>
-  where I: Id,
-        V: Val,
-        IRW: ReadWrite<Type=I>,
+  /// Total number of entries stored in the tree.
+  pub fn n_entries(&self) -> usize {
+    self.meta.n_entries()
+  }
+
+  /// Returns the block of the data part of the file, i.e. of the raw entries.
+  pub fn data_block(&self) -> S::Block<'_> {
+    self
+      .source
+      .block(self.data_starting_byte, self.meta.data_byte_size())
+  }
+
+  /// Size of the file, in bytes.
+  pub fn byte_size(&self) -> usize {
+    self.source.byte_size()
+  }
+
+  /// Size the file should have, in bytes, according to its metadata.
+  pub fn expected_byte_size(&self) -> usize {
+    self.data_starting_byte + self.meta.data_byte_size() + self.meta.checksums_byte_size()
+  }
+
+  /// Returns `true` if the file contains a checksums section.
+  pub fn has_checksums(&self) -> bool {
+    self.meta.has_checksums()
+  }
+
+  /// Normalisation applied to the string values when building the tree, and that must be
+  /// applied to the query values (see [StrNorm::parse](../norm/struct.StrNorm.html#method.parse)).
+  pub fn norm(&self) -> &StrNorm {
+    self.meta.norm()
+  }
+
+  /// Encoding of the values stored in the tree.
+  pub fn encoding(&self) -> ValEncoding {
+    self.meta.encoding()
+  }
+
+  /// Layout of the entries inside the pages of the tree.
+  pub fn page_layout(&self) -> PageLayout {
+    self.meta.page_layout()
+  }
+
+  /// Returns the indices of the data blocks whose CRC32 differs from the one stored in the
+  /// checksums section (empty if the file contains no checksums section).
+  pub fn corrupted_blocks(&self) -> Result<Vec<usize>, Error> {
+    let block_byte_size = match self.meta.checksum_block_byte_size() {
+      Some(block_byte_size) => block_byte_size,
+      None => return Ok(Vec::new()),
+    };
+    let n_blocks = self.meta.n_checksum_blocks();
+    let data_ending_byte = self.data_starting_byte + self.meta.data_byte_size();
+    let checksums = self
+      .source
+      .block(data_ending_byte, n_blocks * CHECKSUM_BYTE_SIZE)
+      .bytes()?;
+    let mut checksums_buff = &checksums[..];
+    let data = self.data_block();
+    let mut corrupted = Vec::new();
+    for i in 0..n_blocks {
+      let from = i * block_byte_size;
+      let to = data.len().min(from + block_byte_size);
+      let block = data.slice(from..to).bytes()?;
+      if crc32fast::hash(&block) != checksums_buff.read_u32::<LittleEndian>()? {
+        corrupted.push(i);
+      }
+    }
+    Ok(corrupted)
+  }
+
+  /// Returns `false` if the CRC32 of the whole file differs from the one stored at the end of
+  /// the checksums section (`true` if the file contains no checksums section).
+  pub fn check_file_checksum(&self) -> Result<bool, Error> {
+    if !self.meta.has_checksums() {
+      return Ok(true);
+    }
+    // Size of the parts of the file successively read to compute the checksum
+    const CHUNK_BYTE_SIZE: usize = 1 << 22;
+    let checksum_starting_byte = self.expected_byte_size() - CHECKSUM_BYTE_SIZE;
+    let expected = self
+      .source
+      .block(checksum_starting_byte, CHECKSUM_BYTE_SIZE)
+      .bytes()?
+      .as_ref()
+      .read_u32::<LittleEndian>()?;
+    let mut hasher = crc32fast::Hasher::new();
+    for from in (0..checksum_starting_byte).step_by(CHUNK_BYTE_SIZE) {
+      let len = CHUNK_BYTE_SIZE.min(checksum_starting_byte - from);
+      hasher.update(&self.source.block(from, len).bytes()?);
+    }
+    Ok(hasher.finalize() == expected)
+  }
+
+  /// Number of rows having a NULL value, i.e. of identifiers stored in the NULL values block.
+  pub fn n_nulls(&self) -> usize {
+    self.meta.n_nulls()
+  }
+
+  /// Returns an iterator over the identifiers of the rows having a NULL value.
+  pub fn nulls<'a, I, IRW>(
+    &'a self,
+    id_rw: &'a IRW,
+  ) -> Result<impl Iterator<Item = Result<I, Error>> + 'a, Error>
+  where
+    I: Id,
+    IRW: ReadWrite<Type = I>,
+  {
+    if self.meta.n_nulls() > 0 && id_rw.n_bytes() != self.meta.nulls.id_byte_size as usize {
+      return Err(Error::new(
+        ErrorKind::Other,
+        format!(
+          "Wrong codec: identifier byte size {} different from the expected one ({})",
+          id_rw.n_bytes(),
+          self.meta.nulls.id_byte_size
+        ),
+      ));
+    }
+    let id_byte_size = id_rw.n_bytes();
+    let nulls_starting_byte = self.data_starting_byte - self.meta.nulls_byte_size();
+    let nulls = self
+      .source
+      .block(nulls_starting_byte, self.meta.nulls_byte_size())
+      .bytes()?;
+    Ok((0..self.meta.n_nulls()).map(move |i| {
+      let mut raw_id = &nulls[i * id_byte_size..(i + 1) * id_byte_size];
+      id_rw.read(&mut raw_id)
+    }))
+  }
+
+  /// Visit the tree with the given visitor.
+  pub fn visit<I, V, IRW, VRW, T>(&self, visitor: T, id_rw: &IRW, val_rw: &VRW) -> Result<T, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+    T: Visitor<I = I, V = V>,
+  {
+    let val_rw = &self.check_codecs(id_rw, val_rw)?;
+    self.root.visit(visitor, self.data_block(), id_rw, val_rw)
+  }
+
+  /// Returns the first entry found having a value equal to the given value.
+  pub fn get<I, V, IRW, VRW>(
+    &self,
+    value: V,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Option<Entry<I, V>>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .visit(VisitorExact::new(value), id_rw, val_rw)
+      .map(|v| v.entry)
+  }
+
+  /// Returns (at most `limit`) entries having a value equal to the given value.
+  pub fn all<I, V, IRW, VRW>(
+    &self,
+    value: V,
+    limit: usize,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Vec<Entry<I, V>>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .visit(VisitorAll::new(value, limit), id_rw, val_rw)
+      .map(|v| v.entries)
+  }
+
+  /// Returns the number of entries (bounded by `limit`) having a value equal to the given value.
+  pub fn count_all<I, V, IRW, VRW>(
+    &self,
+    value: V,
+    limit: usize,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .visit(VisitorAllCount::new(value, limit), id_rw, val_rw)
+      .map(|v| v.n_entries)
+  }
+
+  /// Returns the entry having the nearest value from the given value, if its distance
+  /// is lower than `d_max`.
+  pub fn nn<I, V, U, D, IRW, VRW>(
+    &self,
+    value: V,
+    dist: &D,
+    d_max: Option<U>,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Option<Neigbhour<I, V, U>>, Error>
+  where
+    I: Id,
+    V: Val,
+    U: Ord,
+    D: Fn(&V, &V) -> U,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .visit(VisitorNn::new(value, dist, d_max), id_rw, val_rw)
+      .map(|v| v.nn)
+  }
+
+  /// Returns the `k` entries having the nearest values from the given value, ordered by
+  /// increasing distance.
+  pub fn knn<I, V, U, D, IRW, VRW>(
+    &self,
+    value: V,
+    dist: D,
+    k: usize,
+    d_max: Option<U>,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Vec<Neigbhour<I, V, U>>, Error>
+  where
+    I: Id,
+    V: Val,
+    U: Ord,
+    D: Fn(&V, &V) -> U,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .visit(VisitorKnn::new(value, dist, k, d_max), id_rw, val_rw)
+      .map(|v| v.knn.into_sorted_vec())
+  }
+
+  /// Same as `get` for each of the given values, the values being sharded in (at most)
+  /// `n_threads` contiguous chunks queried concurrently. Results are in input order.
+  pub fn get_list<I, V, IRW, VRW>(
+    &self,
+    values: Vec<V>,
+    n_threads: usize,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Vec<Option<Entry<I, V>>>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    par_map_chunks(values, n_threads, |chunk| {
+      chunk
+        .into_iter()
+        .map(|v| self.get(v, id_rw, val_rw))
+        .collect()
+    })
+  }
+
+  /// Same as `nn` for each of the given values, the values being sharded in (at most)
+  /// `n_threads` contiguous chunks queried concurrently. Results are in input order.
+  #[allow(clippy::type_complexity)]
+  pub fn nn_list<I, V, U, D, IRW, VRW>(
+    &self,
+    values: Vec<V>,
+    n_threads: usize,
+    dist: &D,
+    d_max: Option<U>,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Vec<Option<Neigbhour<I, V, U>>>, Error>
+  where
+    I: Id,
+    V: Val,
+    U: Ord + Clone + Send + Sync,
+    D: Fn(&V, &V) -> U + Sync,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    par_map_chunks(values, n_threads, |chunk| {
+      chunk
+        .into_iter()
+        .map(|v| self.nn(v, dist, d_max.clone(), id_rw, val_rw))
+        .collect()
+    })
+  }
+
+  /// Same as `get` for each of the given values, the tree being walked only once: the values
+  /// are sorted internally so that each page is read once for all the values it may contain.
+  /// The sorted values are sharded in (at most) `n_threads` contiguous chunks, each one walking
+  /// the tree concurrently.
+  /// Each result comes with the index of its value in `values`; results are returned in the
+  /// given `order`.
+  #[allow(clippy::type_complexity)]
+  pub fn get_batch<I, V, IRW, VRW>(
+    &self,
+    values: Vec<V>,
+    order: BatchOrder,
+    n_threads: usize,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Vec<(usize, Option<Entry<I, V>>)>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let val_rw = &self.check_codecs(id_rw, val_rw)?;
+    let res = par_map_chunks(sort_batch(values), n_threads, |chunk| {
+      let (indices, sorted_values): (Vec<usize>, Vec<V>) = chunk.into_iter().unzip();
+      let mut res = vec![None; sorted_values.len()];
+      self
+        .root
+        .get_batch(&sorted_values, &mut res, self.data_block(), id_rw, val_rw)?;
+      Ok(indices.into_iter().zip(res).collect())
+    })?;
+    Ok(order.reorder(res))
+  }
+
+  /// Same as `nn` for each of the given values, the tree being walked only once like in
+  /// `get_batch`: the walk collects the entries surrounding each value, the nearest one being
+  /// its nearest neighbour.
+  /// The sorted values are sharded in (at most) `n_threads` contiguous chunks, each one walking
+  /// the tree concurrently.
+  /// Each result comes with the index of its value in `values`; results are returned in the
+  /// given `order`.
+  #[allow(clippy::type_complexity, clippy::too_many_arguments)]
+  pub fn nn_batch<I, V, U, D, IRW, VRW>(
+    &self,
+    values: Vec<V>,
+    order: BatchOrder,
+    n_threads: usize,
+    dist: &D,
+    d_max: Option<U>,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Vec<(usize, Option<Neigbhour<I, V, U>>)>, Error>
+  where
+    I: Id,
+    V: Val,
+    U: Ord + Clone + Send + Sync,
+    D: Fn(&V, &V) -> U + Sync,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let val_rw = &self.check_codecs(id_rw, val_rw)?;
+    let res = par_map_chunks(sort_batch(values), n_threads, |chunk| {
+      let (indices, sorted_values): (Vec<usize>, Vec<V>) = chunk.into_iter().unzip();
+      let mut res = vec![Surrounding::default(); sorted_values.len()];
+      self
+        .root
+        .get_batch(&sorted_values, &mut res, self.data_block(), id_rw, val_rw)?;
+      Ok(
+        indices
+          .into_iter()
+          .zip(sorted_values.iter().zip(res))
+          .map(|(i, (v, s))| (i, s.nearest(v, dist, d_max.as_ref())))
+          .collect(),
+      )
+    })?;
+    Ok(order.reorder(res))
+  }
+
+  /// Returns (at most `limit`) entries having a value in the range `[lo, hi]`.
+  pub fn range<I, V, IRW, VRW>(
+    &self,
+    lo: V,
+    hi: V,
+    limit: usize,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Vec<Entry<I, V>>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .visit(VisitorRange::new(lo, hi, limit), id_rw, val_rw)
+      .map(|v| v.entries)
+  }
+
+  /// Returns a lazy iterator over the entries having a value in the range `[lo, hi]`,
+  /// in increasing order of their values.
+  #[allow(clippy::type_complexity)]
+  pub fn range_iter<I, V, IRW, VRW>(
+    &self,
+    lo: V,
+    hi: V,
+    id_rw: IRW,
+    val_rw: VRW,
+  ) -> Result<RangeIter<&BSTreeFile<S>, I, V, IRW, EncodedRW<VRW>, S>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let val_rw = self.check_codecs(&id_rw, &val_rw)?;
+    let (from, to) = self.range_ranks(lo, hi, &id_rw, &val_rw)?;
+    Ok(RangeIter::new(self, from, to, id_rw, val_rw))
+  }
+
+  /// Returns a lazy iterator over all the entries of the tree, in increasing order of their values.
+  #[allow(clippy::type_complexity)]
+  pub fn iter<I, V, IRW, VRW>(
+    &self,
+    id_rw: IRW,
+    val_rw: VRW,
+  ) -> Result<RangeIter<&BSTreeFile<S>, I, V, IRW, EncodedRW<VRW>, S>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let val_rw = self.check_codecs(&id_rw, &val_rw)?;
+    Ok(RangeIter::new(self, 0, self.root.n_entries(), id_rw, val_rw))
+  }
+
+  /// Same as [range_iter](#method.range_iter), but the returned iterator owns the tree file.
+  #[allow(clippy::type_complexity)]
+  pub fn into_range_iter<I, V, IRW, VRW>(
+    self,
+    lo: V,
+    hi: V,
+    id_rw: IRW,
+    val_rw: VRW,
+  ) -> Result<RangeIter<BSTreeFile<S>, I, V, IRW, EncodedRW<VRW>, S>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let val_rw = self.check_codecs(&id_rw, &val_rw)?;
+    let (from, to) = self.range_ranks(lo, hi, &id_rw, &val_rw)?;
+    Ok(RangeIter::new(self, from, to, id_rw, val_rw))
+  }
+
+  /// Returns the rank of the first entry having a value greater than or equal to `lo` and
+  /// the rank following the one of the last entry having a value lower than or equal to `hi`
+  /// (`val_rw` being the codec returned by `check_codecs`).
+  fn range_ranks<I, V, IRW, VRW>(
+    &self,
+    lo: V,
+    hi: V,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(usize, usize), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let from = self
+      .root
+      .partition_point(&|v: &V| v < &lo, self.data_block(), id_rw, val_rw)?;
+    let to = self
+      .root
+      .partition_point(&|v: &V| v <= &hi, self.data_block(), id_rw, val_rw)?;
+    Ok((from, to.max(from)))
+  }
+
+  /// Returns the number of entries (bounded by `limit`) having a value in the range `[lo, hi]`.
+  pub fn count_range<I, V, IRW, VRW>(
+    &self,
+    lo: V,
+    hi: V,
+    limit: usize,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .visit(VisitorRangeCount::new(lo, hi, limit), id_rw, val_rw)
+      .map(|v| v.n_entries)
+  }
+
+  /// Returns a lazy iterator over the entries having a value starting with the given prefix,
+  /// in increasing order of their values.
+  /// Only supported on string values, which are lexicographically ordered.
+  #[allow(clippy::type_complexity)]
+  pub fn prefix_iter<I, V, IRW, VRW>(
+    &self,
+    prefix: V,
+    id_rw: IRW,
+    val_rw: VRW,
+  ) -> Result<PrefixIter<&BSTreeFile<S>, I, V, IRW, EncodedRW<VRW>, S>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    check_str_values(&val_rw)?;
+    let val_rw = self.check_codecs(&id_rw, &val_rw)?;
+    // The values starting with the prefix follow the values lower than the prefix
+    let from = self
+      .root
+      .partition_point(&|v: &V| v < &prefix, self.data_block(), &id_rw, &val_rw)?;
+    let mut raw_prefix = Vec::with_capacity(val_rw.n_bytes());
+    val_rw.write(&mut raw_prefix, &prefix)?;
+    // Remove the '\0' padding
+    while raw_prefix.last() == Some(&0) {
+      raw_prefix.pop();
+    }
+    Ok(PrefixIter {
+      range: RangeIter::new(self, from, self.n_entries(), id_rw, val_rw),
+      prefix: raw_prefix,
+    })
+  }
+
+  /// Returns (at most `limit`) entries having a value starting with the given prefix, in
+  /// increasing order of their values.
+  /// Only supported on string values, which are lexicographically ordered.
+  pub fn prefix<I, V, IRW, VRW>(
+    &self,
+    prefix: V,
+    limit: usize,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<Vec<Entry<I, V>>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .prefix_iter(prefix, id_rw.clone(), val_rw.clone())?
+      .take(limit)
+      .collect()
+  }
+
+  /// Returns the number of entries (bounded by `limit`) having a value starting with the
+  /// given prefix. Only supported on string values, which are lexicographically ordered.
+  pub fn count_prefix<I, V, IRW, VRW>(
+    &self,
+    prefix: V,
+    limit: usize,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<usize, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self
+      .prefix_iter(prefix, id_rw.clone(), val_rw.clone())?
+      .take(limit)
+      .try_fold(0, |n, entry| entry.map(|_| n + 1))
+  }
+
+  /// Ensures that the given codecs are consistent with the entry size stored in the metadata,
+  /// and returns the value codec applying the encoding of the tree (see
+  /// [encoding](#method.encoding)).
+  fn check_codecs<I, V, IRW, VRW>(
+    &self,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<EncodedRW<VRW>, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
+    if entry_byte_size != self.meta.entry_byte_size() {
+      Err(Error::new(
+        ErrorKind::Other,
+        format!(
+          "Wrong codecs: entry byte size {} different from the expected one ({})",
+          entry_byte_size,
+          self.meta.entry_byte_size()
+        ),
+      ))
+    } else {
+      EncodedRW::new(val_rw.clone(), self.encoding())
+    }
+  }
+}
+
+/// Order of the results of a batch query (see `BSTreeFile::get_batch`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchOrder {
+  /// Same order as the input values
+  #[default]
+  Input,
+  /// Order of the tree, i.e. by increasing input value (input order for equal values)
+  Tree,
+}
+
+impl BatchOrder {
+  /// Reorders results computed in tree order, each result coming with the index of its value
+  /// in the input.
+  fn reorder<T>(self, mut res: Vec<(usize, T)>) -> Vec<(usize, T)> {
+    if self == BatchOrder::Input {
+      res.sort_unstable_by_key(|(i, _)| *i);
+    }
+    res
+  }
+}
+
+/// Sorts the given values, each one coming with its original index.
+#[cfg(not(target_arch = "wasm32"))]
+fn sort_batch<V: Val>(values: Vec<V>) -> Vec<(usize, V)> {
+  let mut indexed: Vec<(usize, V)> = values.into_iter().enumerate().collect();
+  // Stable sort: equal values remain in input order
+  indexed.sort_by(|(_, a), (_, b)| a.cmp(b));
+  indexed
+}
+
+/// Returns an error if the given values codec is not the one of strings.
+#[cfg(not(target_arch = "wasm32"))]
+fn check_str_values<V: Val, VRW: ReadWrite<Type = V>>(val_rw: &VRW) -> Result<(), Error> {
+  if val_rw.is_str() {
+    Ok(())
+  } else {
+    Err(Error::new(
+      ErrorKind::Other,
+      "Prefix queries are only supported on string values",
+    ))
+  }
+}
+
+/// Splits the given values in (at most) `n_threads` contiguous chunks, applies `f` to each chunk
+/// in a different thread and concatenates the results in the chunks order.
+#[cfg(not(target_arch = "wasm32"))]
+fn par_map_chunks<T, R, F>(mut values: Vec<T>, n_threads: usize, f: F) -> Result<Vec<R>, Error>
+where
+  T: Send,
+  R: Send,
+  F: Fn(Vec<T>) -> Result<Vec<R>, Error> + Sync,
+{
+  let n_threads = n_threads.clamp(1, values.len().max(1));
+  if n_threads == 1 {
+    return f(values);
+  }
+  let chunk_size = values.len().div_ceil(n_threads);
+  let mut chunks = Vec::with_capacity(n_threads);
+  while values.len() > chunk_size {
+    let tail = values.split_off(chunk_size);
+    chunks.push(values);
+    values = tail;
+  }
+  chunks.push(values);
+  thread::scope(|scope| {
+    let f = &f;
+    let workers: Vec<_> = chunks
+      .into_iter()
+      .map(|chunk| scope.spawn(move || f(chunk)))
+      .collect();
+    let mut res = Vec::new();
+    for worker in workers {
+      res.extend(
+        worker
+          .join()
+          .unwrap_or_else(|_| Err(Error::new(ErrorKind::Other, "Query thread panicked")))?,
+      );
+    }
+    Ok(res)
+  })
+}
+
+/// Lazy iterator over a range of ranks of the entries of a tree file, i.e. over entries
+/// sorted by increasing values.
+/// The entries are read from the file one at a time, so the memory footprint does not depend
+/// on the number of entries returned.
+/// `B` is either a `BSTreeFile` or a reference on a `BSTreeFile`.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct RangeIter<B, I, V, IRW, VRW, S = MmapSource>
+where
+  B: Borrow<BSTreeFile<S>>,
+  S: BlockSource,
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  bstree: B,
+  id_rw: IRW,
+  val_rw: VRW,
+  /// Rank of the next entry to be returned
+  rank: usize,
+  /// Rank following the one of the last entry to be returned
+  end: usize,
+  /// Index, in the data part, of the next entry to be returned
+  index: usize,
+  /// Index following the one of the last entry of the current run, i.e. of the entries stored
+  /// contiguously in increasing rank order
+  run_to: usize,
+  /// Index of the first entry of the page containing the current run
+  page_from: usize,
+  /// Index following the one of the last entry of the page containing the current run
+  page_to: usize,
+  _types: PhantomData<(I, V, S)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B, I, V, IRW, VRW, S> RangeIter<B, I, V, IRW, VRW, S>
+where
+  B: Borrow<BSTreeFile<S>>,
+  S: BlockSource,
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  fn new(bstree: B, from: usize, to: usize, id_rw: IRW, val_rw: VRW) -> Self {
+    RangeIter {
+      bstree,
+      id_rw,
+      val_rw,
+      rank: from,
+      end: to,
+      index: 0,
+      run_to: 0,
+      page_from: 0,
+      page_to: 0,
+      _types: PhantomData,
+    }
+  }
+
+  /// Returns the next entry if the raw bytes of its value satisfy the given predicate, else
+  /// ends the iteration (without decoding the entry).
+  fn next_if_raw<P>(&mut self, pred: P) -> Option<Result<Entry<I, V>, Error>>
+  where
+    P: FnOnce(&[u8]) -> bool,
+  {
+    if self.rank >= self.end {
+      return None;
+    }
+    let bstree = self.bstree.borrow();
+    if self.index == self.run_to {
+      let loc = bstree.root.locate(self.rank);
+      self.index = loc.index;
+      self.run_to = loc.index + loc.run;
+      self.page_from = loc.page_from;
+      self.page_to = loc.page_to;
+    }
+    let entry_byte_size = bstree.meta.entry_byte_size();
+    let page = match bstree
+      .data_block()
+      .slice(self.page_from * entry_byte_size..self.page_to * entry_byte_size)
+      .bytes()
+    {
+      Ok(page) => page,
+      Err(e) => {
+        self.rank += 1;
+        self.index += 1;
+        return Some(Err(e));
+      }
+    };
+    let entries = RawEntries::with_layout(&page, &self.id_rw, &self.val_rw, bstree.page_layout());
+    let i = self.index - self.page_from;
+    if !pred(entries.raw_val(i)) {
+      self.rank = self.end;
+      return None;
+    }
+    let res = entries.get_entry(i);
+    self.rank += 1;
+    self.index += 1;
+    Some(res)
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B, I, V, IRW, VRW, S> Iterator for RangeIter<B, I, V, IRW, VRW, S>
+where
+  B: Borrow<BSTreeFile<S>>,
+  S: BlockSource,
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  type Item = Result<Entry<I, V>, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_if_raw(|_| true)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let len = self.end - self.rank;
+    (len, Some(len))
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B, I, V, IRW, VRW, S> ExactSizeIterator for RangeIter<B, I, V, IRW, VRW, S>
+where
+  B: Borrow<BSTreeFile<S>>,
+  S: BlockSource,
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+}
+
+/// Lazy iterator over the entries having a (string) value starting with a given prefix, sorted
+/// by increasing values: a `RangeIter` starting from the prefix, taken while the raw bytes of
+/// the values start with the raw bytes of the prefix.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct PrefixIter<B, I, V, IRW, VRW, S = MmapSource>
+where
+  B: Borrow<BSTreeFile<S>>,
+  S: BlockSource,
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  range: RangeIter<B, I, V, IRW, VRW, S>,
+  /// Raw bytes of the prefix, without padding
+  prefix: Vec<u8>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B, I, V, IRW, VRW, S> Iterator for PrefixIter<B, I, V, IRW, VRW, S>
+where
+  B: Borrow<BSTreeFile<S>>,
+  S: BlockSource,
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  type Item = Result<Entry<I, V>, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let prefix = &self.prefix;
+    self.range.next_if_raw(|raw_val| raw_val.starts_with(prefix))
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (0, self.range.size_hint().1)
+  }
+}
+
+/// Returns the index of the byte following the metadata, given the header of the file (or less
+/// if the header is incomplete, the error being detected while reading the metadata).
+fn meta_ending_byte(mut header: &[u8]) -> usize {
+  let header_byte_size = if header.get(FILE_TYPE.len()) == Some(&FORMAT_MARKER) {
+    HEADER_BYTE_SIZE
+  } else {
+    LEGACY_HEADER_BYTE_SIZE
+  };
+  if header.len() < header_byte_size {
+    return header.len();
+  }
+  header = &header[header_byte_size - 2..];
+  header_byte_size + header.read_u16::<LittleEndian>().unwrap() as usize
+}
+
+/// Version of the layout of a tree file, independent of the version of the code.
+///
+/// A change of `minor` denotes a backward compatible change (a file written with a
+/// lower `minor` can still be read), a change of `major` an incompatible one.
+/// The compatibility matrix, for the code writing the format `1.0`, is:
+///
+/// | File format | Layout                                                 | Readable |
+/// |-------------|--------------------------------------------------------|----------|
+/// | `0.0`       | legacy header (reversed code version), legacy metadata | yes      |
+/// | `1.0`       | explicit format version, metadata listed below         | yes      |
+/// | `1.x`, x>0  | written by a newer code                                | no       |
+/// | `>=2.0`     | written by a newer code                                | no       |
+///
+/// In addition to the legacy metadata, the format `1.0` metadata contains:
+/// * the NULL values block description
+/// * the optional checksums section description
+/// * the string normalisation
+/// * the values encoding
+/// * the layout of the entries inside the pages
+///
+/// Legacy files store the (reversed) code version instead of a format version, their format
+/// version is `0.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FormatVersion {
+  pub major: u8,
+  pub minor: u8,
+}
+
+impl FormatVersion {
+  /// Returns `true` if a file storing this explicit format version (i.e. having the
+  /// `FORMAT_MARKER`) can be read by this code.
+  /// Legacy files, without marker, are always read as `LEGACY_FORMAT_VERSION`.
+  pub fn is_supported(&self) -> bool {
+    self.major == FORMAT_VERSION.major && *self <= FORMAT_VERSION
+  }
+}
+
+impl Display for FormatVersion {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}", self.major, self.minor)
+  }
+}
+
+/// Returns:
+/// * `FormatVersion`: the version of the file format
+/// * `[u8; 3]`: the version of the code used to build the tree (`[major, minor, patch]`)
+/// * `usize`: the index of the first data byte (i.e. following the NULL values block)
+/// * `BSTreeMeta`: the tree structure informations
+#[allow(clippy::type_complexity)]
+pub fn read_meta(
+  mut buff: &[u8],
+) -> Result<(FormatVersion, [u8; 3], usize, BSTreeMeta), BSTreeError> {
+  let mut file_type = [0_u8; 10];
+  let n = buff.len().min(file_type.len());
+  file_type[..n].copy_from_slice(&buff[..n]);
+  if FILE_TYPE[..n] != file_type[..n] {
+    return Err(BSTreeError::BadMagic(file_type));
+  }
+  let is_legacy = buff.get(file_type.len()) != Some(&FORMAT_MARKER);
+  let header_byte_size = if is_legacy {
+    LEGACY_HEADER_BYTE_SIZE
+  } else {
+    HEADER_BYTE_SIZE
+  };
+  if buff.len() < header_byte_size {
+    return Err(BSTreeError::Truncated {
+      expected: header_byte_size,
+      actual: buff.len(),
+    });
+  }
+  buff = &buff[file_type.len()..];
+  let mut format_version = None;
+  if !is_legacy {
+    let mut f_nums = [0_u8; 3];
+    buff.read_exact(&mut f_nums)?;
+    let v = FormatVersion {
+      major: f_nums[1],
+      minor: f_nums[2],
+    };
+    if !v.is_supported() {
+      return Err(BSTreeError::UnsupportedFormatVersion(v));
+    }
+    format_version = Some(v);
+  }
+  let mut v_nums: [u8; 3] = Default::default();
+  buff.read_exact(&mut v_nums)?;
+  if is_legacy {
+    // Legacy files store the code version in reverse order
+    v_nums.reverse();
+  }
+  let meta_byte_size = buff.read_u16::<LittleEndian>()? as usize;
+  if buff.len() < meta_byte_size {
+    return Err(BSTreeError::Truncated {
+      expected: header_byte_size + meta_byte_size,
+      actual: header_byte_size + buff.len(),
+    });
+  }
+  let meta_buff = &buff[..meta_byte_size];
+  let (format_version, meta) = match format_version {
+    Some(format_version) => deserialize_exact::<BSTreeMeta>(meta_buff)
+      .map(|meta| (format_version, meta))
+      .map_err(|_| BSTreeError::InvalidMeta)?,
+    None => deserialize_exact::<LegacyBSTreeMeta>(meta_buff)
+      .map(|meta| (LEGACY_FORMAT_VERSION, meta.into()))
+      .map_err(|_| BSTreeError::InvalidMeta)?,
+  };
+  let data_starting_byte = header_byte_size + meta_byte_size + meta.nulls_byte_size();
+  Ok((format_version, v_nums, data_starting_byte, meta))
+}
+
+/// Same as `bincode::deserialize`, but fails if the whole buffer is not consumed
+/// (so that metadata written by a previous version of the code are not misinterpreted).
+fn deserialize_exact<'a, T: Deserialize<'a>>(buff: &'a [u8]) -> bincode::Result<T> {
+  bincode::DefaultOptions::new()
+    .with_fixint_encoding()
+    .reject_trailing_bytes()
+    .deserialize(buff)
+}
+
+/*
+fn read_id<I, V, IRW, VRW>(&self, val: V, raw_entries: &[u8], id_rw: &IRW, val_rw: &VRW) -> Result<I, Error>
+  where I: Id,
+        V: Val,
+        IRW: ReadWrite<Type=I>,
         VRW: ReadWrite<Type=V> {
 
 }
@@ -2577,32 +5014,12 @@ fn read_entry<I, V, IRW, VRW>(&self, val: V, raw_entries: &[u8], id_rw: &IRW, va
 
 pub fn parse_version() -> Result<[u8; 3], ParseIntError> {
   //-> [u8; 3] {
-  let rv: Result<Vec<u8>, ParseIntError> = VERSION.rsplit('.').map(|i| i.parse::<u8>()).collect();
+  let rv: Result<Vec<u8>, ParseIntError> = VERSION.split('.').map(|i| i.parse::<u8>()).collect();
   let v = rv?;
   assert_eq!(v.len(), 3);
   Ok([v[0], v[1], v[2]])
 }
 
-/*
-struct BSTreeReader {
-  pub fn new() ->
-}
-
-
-// Part that implement Process!!
-/// # Args
-/// * `data`: slice on the data part of the file
-pub fn get<I, V, IRW, VRW, T>(
-  meta: BSTreeMeta,
-  data: &[u8],
-  types: &IdVal,
-  id_rw: &IRW,
-  val_rw: &VRW,
-) -> Result<(), Error> {
-
-}
-*/
-
 // impl iterator (that is sorted ;) )
 
 // eq      -> return the Id associated to the given val
@@ -2616,18 +5033,65 @@ pub fn get<I, V, IRW, VRW, T>(
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::{rw::U64RW, IdType, ValType};
+  use crate::{
+    rw::{U32RW, U64RW},
+    testutils::TmpDir,
+    IdType, ValType,
+  };
+  use std::path::Path;
+
+  /// Small L1 pages (1 kB) and LD blocks (4 kB), to get deep trees from a few thousand entries.
+  const SMALL_PAGES: MemSizeArgs = MemSizeArgs {
+    l1: 1,
+    disk: 4,
+    fill_factor: 1.0,
+  };
+
+  /// Only even values, each value 3 times.
+  fn even_x3_entries(n: u32) -> impl Iterator<Item = Entry<u32, u32>> {
+    (0..n).map(|i| Entry::new(i, (i / 3) * 2))
+  }
+
+  /// Builds, with small pages and the given options, the tree of the given `u32` identifiers
+  /// entries, and opens it.
+  fn build_small<V, VRW, T>(
+    path: &Path,
+    n_entries: usize,
+    entries: T,
+    val_type: ValType,
+    val_rw: &VRW,
+    opts: &BuildOptions,
+  ) -> BSTreeFile
+  where
+    V: Val,
+    VRW: ReadWrite<Type = V>,
+    T: Iterator<Item = Entry<u32, V>>,
+  {
+    build_with_options(
+      path.to_path_buf(),
+      &SMALL_PAGES,
+      n_entries,
+      entries,
+      std::iter::empty(),
+      &IdVal(IdType::U32, val_type),
+      &U32RW,
+      val_rw,
+      opts,
+    )
+    .unwrap();
+    BSTreeFile::open(path).unwrap()
+  }
 
   #[test]
   fn testok_num_nside() {
     assert_eq!(VERSION, "0.1.1");
-    assert_eq!(parse_version().unwrap(), [1_u8, 1_u8, 0_u8]);
+    assert_eq!(parse_version().unwrap(), [0_u8, 1_u8, 1_u8]);
   }
 
   #[test]
   fn testok_build() {
-    use std::path::PathBuf;
-    let path = PathBuf::from("./test_u64u64_x3.bstree");
+    let tmp = TmpDir::new("u64u64_x3");
+    let path = tmp.join("u64u64_x3.bstree");
     // Write
     {
       let mem_args = MemSizeArgs {
@@ -2652,20 +5116,782 @@ mod tests {
       res.unwrap();
     }
     // Read
-    /*
     {
-      // let root = read(path.clone());
-      // let value = 0_u64;
-      // root.get(value, raw_entries: &[u8], &U64RW, &U64RW);
+      let bstree = BSTreeFile::open(&path).unwrap();
+      assert_eq!(bstree.n_entries(), 3_000_000);
+      let get = |v: u64| {
+        bstree
+          .get(v, &U64RW, &U64RW)
+          .unwrap()
+          .map(|e| (e.id, e.val))
+      };
+      assert_eq!(get(0), Some((0, 0)));
+      assert_eq!(get(2_999_999), Some((2_999_999, 2_999_999)));
+      assert_eq!(get(3_000_000), None);
+      assert_eq!(bstree.all(1_234_u64, 10, &U64RW, &U64RW).unwrap().len(), 1);
+      let range = bstree
+        .range(999_990_u64, 1_000_010_u64, usize::MAX, &U64RW, &U64RW)
+        .unwrap();
+      assert_eq!(
+        range.into_iter().map(|e| e.val).collect::<Vec<u64>>(),
+        (999_990_u64..=1_000_010_u64).collect::<Vec<u64>>()
+      );
+      assert_eq!(
+        bstree
+          .count_range(10_u64, 2_999_999_u64, usize::MAX, &U64RW, &U64RW)
+          .unwrap(),
+        2_999_990
+      );
+      let it = bstree
+        .range_iter(999_990_u64, 1_000_010_u64, U64RW, U64RW)
+        .unwrap();
+      assert_eq!(it.len(), 21);
+      assert_eq!(
+        it.map(|e| e.unwrap().val).collect::<Vec<u64>>(),
+        (999_990_u64..=1_000_010_u64).collect::<Vec<u64>>()
+      );
+      let it = bstree.range_iter(0_u64, u64::MAX, U64RW, U64RW).unwrap();
+      assert_eq!(it.len(), 3_000_000);
+      assert!(it.zip(0_u64..).all(|(e, i)| {
+        let e = e.unwrap();
+        e.id == i && e.val == i
+      }));
+      assert_eq!(
+        bstree
+          .range_iter(20_u64, 10_u64, U64RW, U64RW)
+          .unwrap()
+          .count(),
+        0
+      );
+      let dist = |a: &u64, b: &u64| if *a > *b { *a - *b } else { *b - *a };
+      let nn = bstree
+        .nn(5_000_000_u64, &dist, None, &U64RW, &U64RW)
+        .unwrap()
+        .unwrap();
+      assert_eq!(nn.neighbour.val, 2_999_999);
+      let knn = bstree
+        .knn(1_500_000_u64, dist, 3, Some(10), &U64RW, &U64RW)
+        .unwrap();
+      assert_eq!(
+        knn.into_iter().map(|n| n.distance).collect::<Vec<u64>>(),
+        vec![0, 1, 1]
+      );
+      assert!(bstree.get(0_u32, &U32RW, &U32RW).is_err());
     }
-    {
-      get(String::from("2999999"), path.clone());
-      get(String::from("3000000"), path.clone());
+  }
+
+  #[test]
+  fn testok_root_traversals() {
+    let tmp = TmpDir::new("u32u32_roots");
+    let path = tmp.join("u32u32_roots.bstree");
+    let dist = |a: &u32, b: &u32| a.abs_diff(*b);
+    for (n, l1_root) in [(1_000_u32, true), (100_000_u32, false)] {
+      let vals: Vec<u32> = even_x3_entries(n).map(|e| e.val).collect();
+      let bstree = build_small(
+        &path,
+        n as usize,
+        even_x3_entries(n),
+        ValType::U32,
+        &U32RW,
+        &BuildOptions::default(),
+      );
+      assert_eq!(matches!(bstree.root(), Root::RootL1Node(_)), l1_root);
+      assert_eq!(matches!(bstree.root(), Root::RootLDNode(_)), !l1_root);
+      // Ranges starting from each value, so that ascending visits start from each root entry
+      let k = 12;
+      for v in 0..vals[vals.len() - 1] + 2 {
+        let mut range: Vec<(u32, u32)> = bstree
+          .range(v, v + 40, usize::MAX, &U32RW, &U32RW)
+          .unwrap()
+          .into_iter()
+          .map(|e| (e.id, e.val))
+          .collect();
+        range.sort_unstable_by_key(|(id, val)| (*val, *id));
+        let p = vals.partition_point(|val| *val < v);
+        let q = vals.partition_point(|val| *val <= v + 40);
+        let expected: Vec<(u32, u32)> = (p..q).map(|i| (i as u32, vals[i])).collect();
+        assert_eq!(range, expected);
+        // The k nearest neighbours are among the k values on each side of the insertion point
+        let mut expected: Vec<u32> = vals[p.saturating_sub(k)..(p + k).min(vals.len())]
+          .iter()
+          .map(|val| dist(&v, val))
+          .collect();
+        expected.sort_unstable();
+        expected.truncate(k);
+        let knn: Vec<u32> = bstree
+          .knn(v, dist, k, None, &U32RW, &U32RW)
+          .unwrap()
+          .into_iter()
+          .map(|n| n.distance)
+          .collect();
+        assert_eq!(knn, expected);
+      }
     }
-    {
-      get_v2(String::from("2999999"), path.clone());
-      get_v2(String::from("3000000"), path.clone());
+  }
+
+  #[test]
+  fn testok_range_ld_nodes() {
+    use crate::rw::StrRW;
+    let tmp = TmpDir::new("u32str_ld");
+    let path = tmp.join("u32str_ld.bstree");
+    // Large entries (64 bytes), so that a small tree has LD nodes as sub-trees
+    let str_rw = StrRW { n_bytes: 60 };
+    let n = 100_000_u32;
+    // Only even values, each value 3 times (zero-padded to be ordered like numbers)
+    let vals: Vec<u32> = (0..n).map(|i| 2 * (i / 3)).collect();
+    for disk in [4, 8] {
+      let entries: Vec<Entry<u32, String>> = vals
+        .iter()
+        .enumerate()
+        .map(|(i, v)| Entry::new(i as u32, format!("{:08}", v)))
+        .collect();
+      build(
+        path.clone(),
+        &MemSizeArgs {
+          l1: 1,
+          disk,
+          fill_factor: 1.0,
+        },
+        entries.len(),
+        entries.into_iter(),
+        &IdVal(IdType::U32, ValType::Str { n_chars: 60 }),
+        &U32RW,
+        &str_rw,
+      )
+      .unwrap();
+      let bstree = BSTreeFile::open(&path).unwrap();
+      // Ranges of thousands of entries, fully covering LD nodes
+      let max = vals[vals.len() - 1];
+      for (lo, hi) in (0..max).step_by(997).map(|lo| (lo, lo + 3_000)).chain([(0, max)]) {
+        let mut range: Vec<(u32, u32)> = bstree
+          .range(format!("{:08}", lo), format!("{:08}", hi), usize::MAX, &U32RW, &str_rw)
+          .unwrap()
+          .into_iter()
+          .map(|e| (e.id, e.val.parse().unwrap()))
+          .collect();
+        range.sort_unstable_by_key(|(id, val)| (*val, *id));
+        let p = vals.partition_point(|val| *val < lo);
+        let q = vals.partition_point(|val| *val <= hi);
+        let expected: Vec<(u32, u32)> = (p..q).map(|i| (i as u32, vals[i])).collect();
+        assert_eq!(range, expected);
+        let count = bstree
+          .count_range(format!("{:08}", lo), format!("{:08}", hi), usize::MAX, &U32RW, &str_rw)
+          .unwrap();
+        assert_eq!(count, q - p);
+      }
+    }
+  }
+
+  #[test]
+  fn testok_build_with_nulls() {
+    let tmp = TmpDir::new("u32u32_nulls");
+    let path = tmp.join("u32u32_nulls.bstree");
+    let mem_args = MemSizeArgs {
+      l1: 32,
+      disk: 8192,
+      fill_factor: 1.0,
+    };
+    let entries: Vec<Entry<u32, u32>> = (0..1000_u32).map(|i| Entry::new(i, i / 2)).collect();
+    let nulls = vec![1000_u32, 1003, 1010];
+    build_with_options(
+      path.clone(),
+      &mem_args,
+      entries.len(),
+      entries.into_iter(),
+      nulls.clone().into_iter(),
+      &IdVal(IdType::U32, ValType::U32),
+      &U32RW,
+      &U32RW,
+      &BuildOptions::default(),
+    )
+    .unwrap();
+    let bstree = BSTreeFile::open(&path).unwrap();
+    assert_eq!(bstree.n_entries(), 1000);
+    assert_eq!(bstree.n_nulls(), 3);
+    assert_eq!(
+      bstree
+        .nulls(&U32RW)
+        .unwrap()
+        .collect::<Result<Vec<u32>, Error>>()
+        .unwrap(),
+      nulls
+    );
+    assert_eq!(bstree.count_all(10_u32, usize::MAX, &U32RW, &U32RW).unwrap(), 2);
+    assert_eq!(bstree.get(499_u32, &U32RW, &U32RW).unwrap().unwrap().val, 499);
+  }
+
+  #[test]
+  fn testok_build_with_checksums() {
+    let tmp = TmpDir::new("u32u32_checksums");
+    let path = tmp.join("u32u32_checksums.bstree");
+    let mem_args = MemSizeArgs {
+      l1: 32,
+      disk: 64,
+      fill_factor: 1.0,
+    };
+    let entries: Vec<Entry<u32, u32>> = (0..10000_u32).map(|i| Entry::new(i, i / 2)).collect();
+    build_with_options(
+      path.clone(),
+      &mem_args,
+      entries.len(),
+      entries.into_iter(),
+      std::iter::empty(),
+      &IdVal(IdType::U32, ValType::U32),
+      &U32RW,
+      &U32RW,
+      &BuildOptions {
+        checksums: true,
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    let bstree = BSTreeFile::open(&path).unwrap();
+    assert!(bstree.has_checksums());
+    assert!(bstree.meta().n_checksum_blocks() > 1);
+    assert_eq!(bstree.byte_size(), bstree.expected_byte_size());
+    assert!(bstree.corrupted_blocks().unwrap().is_empty());
+    assert!(bstree.check_file_checksum().unwrap());
+    let vals: Vec<u32> = bstree
+      .iter(U32RW, U32RW)
+      .unwrap()
+      .map(|e| e.map(|e| e.val))
+      .collect::<Result<_, Error>>()
+      .unwrap();
+    assert_eq!(vals, (0..10000_u32).map(|i| i / 2).collect::<Vec<u32>>());
+    // Corrupt a byte of the second data block
+    let block_byte_size = bstree.meta().checksum_block_byte_size().unwrap();
+    let byte_index = bstree.expected_byte_size() - bstree.meta().checksums_byte_size()
+      - bstree.meta().data_byte_size()
+      + block_byte_size
+      + 1;
+    drop(bstree);
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[byte_index] ^= 0xFF;
+    std::fs::write(&path, &bytes).unwrap();
+    let bstree = BSTreeFile::open(&path).unwrap();
+    assert_eq!(bstree.corrupted_blocks().unwrap(), vec![1]);
+    assert!(!bstree.check_file_checksum().unwrap());
+    // Truncate the file
+    drop(bstree);
+    std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(matches!(
+      BSTreeFile::open(&path),
+      Err(BSTreeError::Truncated { .. })
+    ));
+  }
+
+  #[test]
+  fn testok_open_pread() {
+    let tmp = TmpDir::new("u32u32_pread");
+    let path = tmp.join("u32u32_pread.bstree");
+    let n = 100_000_u32;
+    let entries: Vec<Entry<u32, u32>> = (0..n).map(|i| Entry::new(i, i / 3)).collect();
+    build_with_options(
+      path.clone(),
+      &SMALL_PAGES,
+      entries.len(),
+      entries.into_iter(),
+      vec![n, n + 7].into_iter(),
+      &IdVal(IdType::U32, ValType::U32),
+      &U32RW,
+      &U32RW,
+      &BuildOptions {
+        checksums: true,
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    let mmap = BSTreeFile::open(&path).unwrap();
+    // Cache smaller than a LD page, to test evictions
+    let pread = BSTreeFile::open_pread(&path, 2048).unwrap();
+    assert_eq!(pread.n_entries(), mmap.n_entries());
+    let dist = |a: &u32, b: &u32| a.abs_diff(*b);
+    for v in [0_u32, 1, 777, 12_345, 33_332, 33_333, 50_000] {
+      assert_eq!(
+        pread.get(v, &U32RW, &U32RW).unwrap().map(|e| e.val),
+        mmap.get(v, &U32RW, &U32RW).unwrap().map(|e| e.val)
+      );
+      assert_eq!(
+        pread.all(v, usize::MAX, &U32RW, &U32RW).unwrap().len(),
+        mmap.all(v, usize::MAX, &U32RW, &U32RW).unwrap().len()
+      );
+      assert_eq!(
+        pread.nn(v, &dist, None, &U32RW, &U32RW).unwrap().map(|n| n.distance),
+        mmap.nn(v, &dist, None, &U32RW, &U32RW).unwrap().map(|n| n.distance)
+      );
+      assert_eq!(
+        pread.knn(v, dist, 10, None, &U32RW, &U32RW).unwrap().len(),
+        mmap.knn(v, dist, 10, None, &U32RW, &U32RW).unwrap().len()
+      );
+      let hi = v + 1_000;
+      let range: Vec<(u32, u32)> = pread
+        .range_iter(v, hi, U32RW, U32RW)
+        .unwrap()
+        .map(|e| e.map(|e| (e.id, e.val)))
+        .collect::<Result<_, Error>>()
+        .unwrap();
+      assert_eq!(range.len(), mmap.count_range(v, hi, usize::MAX, &U32RW, &U32RW).unwrap());
+      assert!(range.windows(2).all(|w| w[0].1 <= w[1].1));
+    }
+    let vals: Vec<u32> = pread
+      .iter(U32RW, U32RW)
+      .unwrap()
+      .map(|e| e.map(|e| e.val))
+      .collect::<Result<_, Error>>()
+      .unwrap();
+    assert_eq!(vals, (0..n).map(|i| i / 3).collect::<Vec<u32>>());
+    assert_eq!(
+      pread.nulls(&U32RW).unwrap().collect::<Result<Vec<u32>, Error>>().unwrap(),
+      vec![n, n + 7]
+    );
+    assert!(pread.corrupted_blocks().unwrap().is_empty());
+    assert!(pread.check_file_checksum().unwrap());
+  }
+
+  #[test]
+  fn testok_ordered_encoding() {
+    use crate::{
+      float::FiniteFloat,
+      rw::{EncodedRW, F64RW, I40RW},
+    };
+    // The encoded bytes are ordered like the values
+    let i40_rw = EncodedRW::new(I40RW, ValEncoding::Ordered).unwrap();
+    let vals = [-(1_i64 << 39), -256, -1, 0, 1, 255, 256, (1 << 39) - 1];
+    let encoded: Vec<Vec<u8>> = vals
+      .iter()
+      .map(|v| {
+        let mut buff = Vec::new();
+        i40_rw.write(&mut buff, v).unwrap();
+        assert_eq!(i40_rw.read(&mut buff.as_slice()).unwrap(), *v);
+        buff
+      })
+      .collect();
+    assert!(encoded.windows(2).all(|w| w[0] < w[1]));
+    let f64_rw = EncodedRW::new(F64RW, ValEncoding::Ordered).unwrap();
+    let floats: Vec<FiniteFloat<f64>> = [-1e300, -2.5, -1e-300, -0.0, 1e-300, 2.5, 1e300]
+      .into_iter()
+      .map(|f| FiniteFloat::new(f).unwrap())
+      .collect();
+    let encoded: Vec<Vec<u8>> = floats
+      .iter()
+      .map(|v| {
+        let mut buff = Vec::new();
+        f64_rw.write(&mut buff, v).unwrap();
+        assert_eq!(f64_rw.read(&mut buff.as_slice()).unwrap(), *v);
+        buff
+      })
+      .collect();
+    assert!(encoded.windows(2).all(|w| w[0] < w[1]));
+    // Queries on a tree built with the ordered encoding
+    let tmp = TmpDir::new("u32u32_ordered");
+    let n = 100_000_u32;
+    let bstree = build_small(
+      &tmp.join("u32u32_ordered.bstree"),
+      n as usize,
+      (0..n).map(|i| Entry::new(i, i * 3)),
+      ValType::U32,
+      &U32RW,
+      &BuildOptions {
+        encoding: ValEncoding::Ordered,
+        ..Default::default()
+      },
+    );
+    assert_eq!(bstree.encoding(), ValEncoding::Ordered);
+    for v in [0_u32, 1, 255, 256, 65_535, 65_536, 299_997, 299_998] {
+      let expected = if v % 3 == 0 { Some(v / 3) } else { None };
+      assert_eq!(
+        bstree.get(v, &U32RW, &U32RW).unwrap().map(|e| e.id),
+        expected
+      );
     }
-    */
+    assert_eq!(
+      bstree.count_range(256, 65_536, usize::MAX, &U32RW, &U32RW).unwrap(),
+      (256..=65_536_u32).filter(|v| v % 3 == 0).count()
+    );
+    let range: Vec<u32> = bstree
+      .range_iter(256, 65_536, U32RW, U32RW)
+      .unwrap()
+      .map(|e| e.unwrap().val)
+      .collect();
+    assert_eq!(range, (256..=65_536_u32).filter(|v| v % 3 == 0).collect::<Vec<u32>>());
+    let batch = bstree
+      .get_batch(vec![299_997, 3, 4], BatchOrder::Input, 1, &U32RW, &U32RW)
+      .unwrap();
+    assert_eq!(
+      batch.into_iter().map(|(_, e)| e.map(|e| e.id)).collect::<Vec<_>>(),
+      vec![Some(99_999), Some(1), None]
+    );
+  }
+
+  #[test]
+  fn testok_ordered_encoding_out_of_range() {
+    use crate::rw::{I24RW, U24RW};
+    let tmp = TmpDir::new("ordered_out_of_range");
+    let opts = BuildOptions {
+      encoding: ValEncoding::Ordered,
+      ..Default::default()
+    };
+    let n = 100_000_u32;
+    // Query values out of the range of the 3 bytes integers are not encoded
+    let u3 = build_small(
+      &tmp.join("u32u24_ordered.bstree"),
+      n as usize,
+      (0..n).map(|i| Entry::new(i, i * 167)),
+      ValType::U24,
+      &U24RW,
+      &opts,
+    );
+    let dist = |a: &u32, b: &u32| a.abs_diff(*b);
+    for v in [1_u32 << 24, u32::MAX] {
+      assert!(u3.get(v, &U32RW, &U24RW).unwrap().is_none());
+      assert_eq!(u3.count_range(v, u32::MAX, usize::MAX, &U32RW, &U24RW).unwrap(), 0);
+      assert_eq!(u3.count_range(0, v, usize::MAX, &U32RW, &U24RW).unwrap(), n as usize);
+      let nn = u3.nn(v, &dist, None, &U32RW, &U24RW).unwrap().unwrap();
+      assert_eq!(nn.neighbour.id, n - 1);
+    }
+    let range = u3.range(1 << 24, u32::MAX, usize::MAX, &U32RW, &U24RW).unwrap();
+    assert!(range.is_empty());
+    let range: Vec<u32> = u3
+      .range_iter(16_699_000, 1 << 24, U32RW, U24RW)
+      .unwrap()
+      .map(|e| e.unwrap().id)
+      .collect();
+    assert_eq!(range, (99_995..n).collect::<Vec<u32>>());
+    let i3 = build_small(
+      &tmp.join("u32i24_ordered.bstree"),
+      n as usize,
+      (0..n).map(|i| Entry::new(i, i as i32 * 167 - (1 << 23))),
+      ValType::I24,
+      &I24RW,
+      &opts,
+    );
+    let dist = |a: &i32, b: &i32| a.abs_diff(*b);
+    for v in [-(1_i32 << 23) - 1, i32::MIN] {
+      assert!(i3.get(v, &U32RW, &I24RW).unwrap().is_none());
+      assert_eq!(i3.count_range(i32::MIN, v, usize::MAX, &U32RW, &I24RW).unwrap(), 0);
+      assert_eq!(i3.count_range(v, i32::MAX, usize::MAX, &U32RW, &I24RW).unwrap(), n as usize);
+      let nn = i3.nn(v, &dist, None, &U32RW, &I24RW).unwrap().unwrap();
+      assert_eq!(nn.neighbour.id, 0);
+    }
+    for v in [1_i32 << 23, i32::MAX] {
+      assert!(i3.get(v, &U32RW, &I24RW).unwrap().is_none());
+      let nn = i3.nn(v, &dist, None, &U32RW, &I24RW).unwrap().unwrap();
+      assert_eq!(nn.neighbour.id, n - 1);
+    }
+    let range = i3.range(i32::MIN, -(1 << 23) + 167, usize::MAX, &U32RW, &I24RW).unwrap();
+    assert_eq!(range.into_iter().map(|e| e.id).collect::<Vec<_>>(), vec![0, 1]);
+  }
+
+  #[test]
+  fn testok_columnar_pages() {
+    let tmp = TmpDir::new("u32u32_columnar");
+    let build = |file_name: &str, n: u32, page_layout: PageLayout| {
+      let opts = BuildOptions {
+        page_layout,
+        ..Default::default()
+      };
+      build_small(&tmp.join(file_name), n as usize, even_x3_entries(n), ValType::U32, &U32RW, &opts)
+    };
+    let dist = |a: &u32, b: &u32| a.abs_diff(*b);
+    // From a single L1 leaf to a tree having a rightmost sub-tree
+    for n in [10_u32, 5_000, 100_003] {
+      let interleaved = build("interleaved.bstree", n, PageLayout::Interleaved);
+      let columnar = build("columnar.bstree", n, PageLayout::Columnar);
+      assert_eq!(interleaved.page_layout(), PageLayout::Interleaved);
+      assert_eq!(columnar.page_layout(), PageLayout::Columnar);
+      let entries: Vec<(u32, u32)> = columnar
+        .iter(U32RW, U32RW)
+        .unwrap()
+        .map(|e| e.map(|e| (e.id, e.val)))
+        .collect::<Result<_, Error>>()
+        .unwrap();
+      assert_eq!(entries, (0..n).map(|i| (i, (i / 3) * 2)).collect::<Vec<_>>());
+      for v in (0..(n / 3) * 2 + 3).step_by(11) {
+        assert_eq!(
+          columnar.get(v, &U32RW, &U32RW).unwrap().map(|e| e.id / 3),
+          interleaved.get(v, &U32RW, &U32RW).unwrap().map(|e| e.id / 3)
+        );
+        assert_eq!(
+          columnar.knn(v, dist, 7, None, &U32RW, &U32RW).unwrap().len(),
+          interleaved.knn(v, dist, 7, None, &U32RW, &U32RW).unwrap().len()
+        );
+        assert_eq!(
+          columnar.count_range(v, v + 100, usize::MAX, &U32RW, &U32RW).unwrap(),
+          interleaved.count_range(v, v + 100, usize::MAX, &U32RW, &U32RW).unwrap()
+        );
+      }
+      let values: Vec<u32> = (0..(n / 3) * 2).step_by(5).collect();
+      let ids = |bstree: &BSTreeFile| -> Vec<Option<u32>> {
+        bstree
+          .get_batch(values.clone(), BatchOrder::Input, 1, &U32RW, &U32RW)
+          .unwrap()
+          .into_iter()
+          .map(|(_, e)| e.map(|e| e.id / 3))
+          .collect()
+      };
+      assert_eq!(ids(&columnar), ids(&interleaved));
+    }
+  }
+
+  #[test]
+  fn testok_get_batch() {
+    let tmp = TmpDir::new("u32u32_batch");
+    let path = tmp.join("u32u32_batch.bstree");
+    let dist = |a: &u32, b: &u32| a.abs_diff(*b);
+    // Different sizes lead to different root types
+    for n in [10_u32, 1_000, 100_000] {
+      let bstree = build_small(
+        &path,
+        n as usize,
+        even_x3_entries(n),
+        ValType::U32,
+        &U32RW,
+        &BuildOptions::default(),
+      );
+      // Unsorted values, with duplicates and values out of the tree bounds
+      let values: Vec<u32> = (0..2_000_u32)
+        .map(|i| (i * 7_919) % (n + 10))
+        .chain([0, n + 100, 1, 0])
+        .collect();
+      let res = bstree
+        .get_batch(values.clone(), BatchOrder::Input, 1, &U32RW, &U32RW)
+        .unwrap();
+      assert_eq!(res.len(), values.len());
+      for (k, (i, e)) in res.into_iter().enumerate() {
+        assert_eq!(i, k);
+        let expected = bstree.get(values[i], &U32RW, &U32RW).unwrap();
+        assert_eq!(e.map(|e| e.val), expected.map(|e| e.val));
+      }
+      let res = bstree.get_list(values.clone(), 4, &U32RW, &U32RW).unwrap();
+      assert_eq!(res.len(), values.len());
+      for (v, e) in values.iter().zip(res) {
+        assert_eq!(e.is_some(), bstree.get(*v, &U32RW, &U32RW).unwrap().is_some());
+      }
+      let res = bstree
+        .get_batch(values.clone(), BatchOrder::Tree, 3, &U32RW, &U32RW)
+        .unwrap();
+      assert!(res.windows(2).all(|w| values[w[0].0] <= values[w[1].0]));
+      for d_max in [None, Some(0), Some(1)] {
+        let res = bstree
+          .nn_batch(values.clone(), BatchOrder::Input, 3, &dist, d_max, &U32RW, &U32RW)
+          .unwrap();
+        assert_eq!(res.len(), values.len());
+        for (i, nn) in res {
+          let expected = bstree.nn(values[i], &dist, d_max, &U32RW, &U32RW).unwrap();
+          assert_eq!(nn.map(|n| n.distance), expected.map(|n| n.distance));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn testok_prefix() {
+    use crate::rw::StrRW;
+    let tmp = TmpDir::new("u32str_prefix");
+    let path = tmp.join("u32str_prefix.bstree");
+    let str_rw = StrRW { n_bytes: 8 };
+    // Different sizes lead to different root types
+    for n in [10_u32, 1_000, 100_000] {
+      let mut values: Vec<String> = (0..n).map(|i| format!("G{}", i * 13)).collect();
+      values.extend(["G1", "G1", "G", "F9", "H"].iter().map(|s| s.to_string()));
+      values.sort();
+      let bstree = build_small(
+        &path,
+        values.len(),
+        values.iter().enumerate().map(|(i, v)| Entry::new(i as u32, v.clone())),
+        ValType::Str { n_chars: 8 },
+        &str_rw,
+        &BuildOptions::default(),
+      );
+      for prefix in ["G1", "G12", "G", "G0", "F", "G99999", "I", "G260"] {
+        let expected: Vec<&String> = values.iter().filter(|v| v.starts_with(prefix)).collect();
+        let res: Vec<String> = bstree
+          .prefix(prefix.to_string(), usize::MAX, &U32RW, &str_rw)
+          .unwrap()
+          .into_iter()
+          .map(|e| e.val)
+          .collect();
+        assert_eq!(res.iter().collect::<Vec<_>>(), expected);
+        let count = bstree
+          .count_prefix(prefix.to_string(), 10, &U32RW, &str_rw)
+          .unwrap();
+        assert_eq!(count, expected.len().min(10));
+      }
+      assert!(bstree.count_prefix(1_u32, 10, &U32RW, &U32RW).is_err());
+    }
+  }
+
+  #[test]
+  fn testok_norm() {
+    use crate::{norm::StrNorm, rw::StrRW};
+    let norm: StrNorm = "case-fold,trim,remove-spaces".parse().unwrap();
+    let tmp = TmpDir::new("u32str_norm");
+    let path = tmp.join("u32str_norm.bstree");
+    let mem_args = MemSizeArgs {
+      l1: 32,
+      disk: 8192,
+      fill_factor: 1.0,
+    };
+    let str_rw = StrRW { n_bytes: 8 };
+    let mut values: Vec<String> = ["HD 1234", "hd1235", " Hd 12 36"]
+      .iter()
+      .map(|v| norm.apply(v).into_owned())
+      .collect();
+    values.sort();
+    let opts = BuildOptions {
+      norm,
+      ..Default::default()
+    };
+    let build_str = |opts: &BuildOptions| {
+      build_with_options(
+        path.clone(),
+        &mem_args,
+        values.len(),
+        values.iter().enumerate().map(|(i, v)| Entry::new(i as u32, v.clone())),
+        std::iter::empty(),
+        &IdVal(IdType::U32, ValType::Str { n_chars: 8 }),
+        &U32RW,
+        &str_rw,
+        opts,
+      )
+    };
+    build_str(&opts).unwrap();
+    let bstree = BSTreeFile::open(&path).unwrap();
+    assert_eq!(bstree.norm(), &norm);
+    let query = bstree.norm().parse::<String>("hD 12 34").unwrap();
+    assert_eq!(
+      bstree.get(query, &U32RW, &str_rw).unwrap().map(|e| e.val),
+      Some(String::from("hd1234"))
+    );
+    drop(bstree);
+    // No normalisation recorded by default
+    build_str(&BuildOptions::default()).unwrap();
+    assert!(BSTreeFile::open(&path).unwrap().norm().is_identity());
+    // Normalisation of non-string values
+    let entries = (0..10_u32).map(|i| Entry::new(i, i));
+    assert!(build_with_options(
+      path.clone(),
+      &mem_args,
+      10,
+      entries,
+      std::iter::empty(),
+      &IdVal(IdType::U32, ValType::U32),
+      &U32RW,
+      &U32RW,
+      &opts,
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn testerr_format_version() {
+    let tmp = TmpDir::new("u32u32_format");
+    let path = tmp.join("u32u32_format.bstree");
+    let mem_args = MemSizeArgs {
+      l1: 32,
+      disk: 8192,
+      fill_factor: 1.0,
+    };
+    let entries: Vec<Entry<u32, u32>> = (0..100_u32).map(|i| Entry::new(i, i)).collect();
+    build(
+      path.clone(),
+      &mem_args,
+      entries.len(),
+      entries.into_iter(),
+      &IdVal(IdType::U32, ValType::U32),
+      &U32RW,
+      &U32RW,
+    )
+    .unwrap();
+    let bstree = BSTreeFile::open(&path).unwrap();
+    assert_eq!(bstree.format_version(), FORMAT_VERSION);
+    assert_eq!(bstree.version(), parse_version().unwrap());
+    drop(bstree);
+    let mut bytes = std::fs::read(&path).unwrap();
+    // Newer versions, and legacy versions which are only valid without the format marker
+    let unsupported_versions = [
+      (FORMAT_VERSION.major, FORMAT_VERSION.minor + 1),
+      (FORMAT_VERSION.major + 1, 0),
+      (0, 0),
+      (0, 1),
+    ];
+    for (major, minor) in unsupported_versions {
+      bytes[FILE_TYPE.len() + 1] = major;
+      bytes[FILE_TYPE.len() + 2] = minor;
+      std::fs::write(&path, &bytes).unwrap();
+      match BSTreeFile::open(&path) {
+        Err(BSTreeError::UnsupportedFormatVersion(v)) => {
+          assert_eq!(v, FormatVersion { major, minor })
+        }
+        _ => panic!("Unsupported format version not detected"),
+      }
+    }
+  }
+
+  #[test]
+  fn testok_legacy_format() {
+    let tmp = TmpDir::new("u32u32_legacy");
+    let path = tmp.join("u32u32_legacy.bstree");
+    let n = 10_000_u32;
+    let bstree = build_small(
+      &path,
+      n as usize,
+      (0..n).map(|i| Entry::new(i, i * 2)),
+      ValType::U32,
+      &U32RW,
+      &BuildOptions::default(),
+    );
+    // Legacy header (reversed code version) and metadata, followed by the same data
+    let (meta, cte) = (bstree.meta(), &bstree.meta().constants);
+    let legacy_meta = bincode::serialize(&(
+      &meta.types,
+      (
+        cte.n_entries,
+        cte.entry_byte_size,
+        cte.n_entries_per_l1page,
+        cte.n_l1page_per_ldpage,
+      ),
+      &meta.layout,
+    ))
+    .unwrap();
+    let mut bytes = FILE_TYPE.to_vec();
+    bytes.extend([1, 1, 0]);
+    bytes.write_u16::<LittleEndian>(legacy_meta.len() as u16).unwrap();
+    bytes.extend(legacy_meta);
+    bytes.extend(bstree.data());
+    drop(bstree);
+    std::fs::write(&path, &bytes).unwrap();
+    let bstree = BSTreeFile::open(&path).unwrap();
+    assert_eq!(bstree.format_version(), LEGACY_FORMAT_VERSION);
+    assert_eq!(bstree.version(), [0, 1, 1]);
+    assert_eq!(bstree.n_nulls(), 0);
+    assert!(!bstree.has_checksums());
+    for v in [0, 1, 2, 9_998, (n - 1) * 2] {
+      let expected = if v % 2 == 0 { Some(v / 2) } else { None };
+      assert_eq!(bstree.get(v, &U32RW, &U32RW).unwrap().map(|e| e.id), expected);
+    }
+  }
+
+  #[test]
+  fn testerr_open() {
+    let tmp = TmpDir::new("not_a_bstree");
+    let path = tmp.join("not_a_bstree.bstree");
+    std::fs::write(&path, b"id,val\n1,2\n3,4\n").unwrap();
+    assert!(matches!(
+      BSTreeFile::open(&path),
+      Err(BSTreeError::BadMagic(_))
+    ));
+    std::fs::write(&path, b"BSTree").unwrap();
+    assert!(matches!(
+      BSTreeFile::open(&path),
+      Err(BSTreeError::Truncated { .. })
+    ));
+    std::fs::write(&path, b"").unwrap();
+    assert!(matches!(
+      BSTreeFile::open(&path),
+      Err(BSTreeError::Truncated { .. })
+    ));
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(BSTreeFile::open(&path), Err(BSTreeError::Io(_))));
   }
 }
@@ -1,16 +1,21 @@
 //! Arguments used to provide the columns datatypes
+use std::io::{Error, ErrorKind};
+
 use structopt::StructOpt;
 
 use crate::{IdType, IdVal, ValType};
 
 #[derive(Debug, StructOpt)]
 pub struct ColTypeArgs {
-  #[structopt(long)]
+  #[structopt(long, required_unless = "infer-types")]
   /// Datatype of the value
-  id_type: IdType,
-  #[structopt(long)]
+  id_type: Option<IdType>,
+  #[structopt(long, required_unless = "infer-types")]
   /// Datatype of the value
-  val_type: ValType,
+  val_type: Option<ValType>,
+  #[structopt(long)]
+  /// Infer the narrowest types not given explicitly from a pre-scan of the input file
+  infer_types: bool,
   #[structopt(short = "u", long)]
   /// Support null values in the value field
   null_val: bool,
@@ -18,14 +23,54 @@ pub struct ColTypeArgs {
 
 impl ColTypeArgs {
   pub fn is_recno_compatible(&self) -> bool {
-    self.id_type.is_recno_compatible()
+    // An inferred record number type is always compatible
+    self
+      .id_type
+      .as_ref()
+      .is_none_or(IdType::is_recno_compatible)
   }
 
   pub fn supports_null(&self) -> bool {
     self.null_val
   }
 
-  pub fn to_idval(self) -> IdVal {
-    IdVal(self.id_type, self.val_type)
+  pub fn infer_types(&self) -> bool {
+    self.infer_types
+  }
+
+  /// Returns the identifier type given explicitly, if any.
+  pub fn id_type(&self) -> Option<&IdType> {
+    self.id_type.as_ref()
+  }
+
+  /// Returns the value type given explicitly, if any.
+  pub fn val_type(&self) -> Option<&ValType> {
+    self.val_type.as_ref()
+  }
+
+  /// Sets the (e.g. inferred) identifier and value types.
+  pub fn set_types(&mut self, types: IdVal) {
+    self.id_type = Some(types.id_type().clone());
+    self.val_type = Some(types.val_type().clone());
+  }
+
+  /// Sets the width (in bytes, at least 1) of the identifier and of the value, if they are strings.
+  pub fn set_str_byte_sizes(&mut self, id_byte_size: usize, val_byte_size: usize) {
+    if let Some(IdType::Str { n_chars }) = &mut self.id_type {
+      *n_chars = id_byte_size.max(1);
+    }
+    if let Some(ValType::Str { n_chars }) = &mut self.val_type {
+      *n_chars = val_byte_size.max(1);
+    }
+  }
+
+  pub fn to_idval(self) -> Result<IdVal, Error> {
+    match (self.id_type, self.val_type) {
+      (Some(id_type), Some(val_type)) => Ok(IdVal(id_type, val_type)),
+      _ => Err(Error::new(
+        ErrorKind::Other,
+        "Missing identifier or value type: use --id-type and --val-type, or --infer-types",
+      )),
+    }
   }
 }
@@ -35,6 +35,11 @@ pub struct CsvArgs {
 }
 
 impl CsvArgs {
+  /// Returns `true` if the input is a file, `false` if it is stdin.
+  pub fn has_input_file(&self) -> bool {
+    self.input.is_some()
+  }
+
   fn create_reader_builder(&self) -> ReaderBuilder {
     let mut reader_builder = csv::ReaderBuilder::new();
     reader_builder.delimiter(self.delimiter as u8);
@@ -55,7 +60,9 @@ impl CsvArgs {
   /// ## Note
   /// * we use this strategy to use monomorphization instead of returning a trait object of type
   ///   `Reader<Box<dyn Read>>`
-  pub fn call_once<F>(self, func: F) -> Result<F::Output, Error>
+  /// * the input file can be read several times (e.g. a pre-scan before the actual read), but not
+  ///   stdin
+  pub fn call_once<F>(&self, func: F) -> Result<F::Output, Error>
   where
     F: FnUsingReader,
   {
@@ -1,16 +1,26 @@
 //! Other arguments needed when building the bs-tree
 use std::{
   fs::{self, File},
-  io::{BufReader, BufWriter, Error, ErrorKind},
+  io::{BufReader, BufWriter, Error, ErrorKind, Write},
+  marker::PhantomData,
   path::{Path, PathBuf},
+  str::FromStr,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{sync_channel, Receiver, SyncSender},
+    Arc, Mutex,
+  },
+  thread::{self, JoinHandle},
 };
 
 use itertools::{Itertools, KMerge};
 use log::{debug, error};
 use structopt::StructOpt;
 
-use crate::rw::ReadWrite;
-use crate::{Entry, Id, Val};
+use crate::bstree::BuildOptions;
+use crate::norm::StrNorm;
+use crate::rw::{ReadWrite, ValEncoding};
+use crate::{Entry, Id, PageLayout, Val};
 
 #[derive(Debug, StructOpt)]
 pub struct MkAlgoArgs {
@@ -29,6 +39,73 @@ pub struct MkAlgoArgs {
   #[structopt(parse(from_os_str))]
   /// Output file basename (without the .bstree extension)
   pub output: PathBuf,
+  #[structopt(long, default_value = "1")]
+  /// Number of threads: the input is parsed by the main thread while the chunks are sorted and
+  /// written in temporary files by `threads - 1` other threads.
+  ///
+  /// To keep the memory bounded, each of the (at most) `threads` chunks in memory at the same
+  /// time contains `chunk-size / threads` rows.
+  /// It is also the maximum number of groups of 'kway' temporary files merged concurrently.
+  pub threads: usize,
+  #[structopt(long)]
+  /// Add a checksums section (CRC32 of each LD block and of the whole file) at the end of the
+  /// output file, so that it can be checked with 'qbst FILE verify'
+  pub checksums: bool,
+  #[structopt(long, default_value = "none")]
+  /// Normalisation of the string values, recorded in the output file so that it is also applied
+  /// to the query values: comma separated list of 'case-fold', 'trim', 'collapse-spaces' or
+  /// 'remove-spaces' (or 'none')
+  pub normalize: StrNorm,
+  #[structopt(long, default_value = "warn", possible_values = &Truncation::NAMES)]
+  /// What to do with string identifiers or values longer than their declared 'tN' width:
+  /// 'error' stops the build, 'warn' truncates them (at a character boundary) and reports the
+  /// number of truncated strings, 'auto' pre-scans the input file to set the width to the
+  /// longest string
+  pub truncation: Truncation,
+  #[structopt(long, default_value = "little-endian", possible_values = &ValEncoding::NAMES)]
+  /// Encoding of the values in the output file: 'little-endian' or 'ordered' (big-endian with
+  /// flipped sign bits, so that the raw bytes of the values can be compared with memcmp by
+  /// other tools; queries are slower than with the default 'little-endian')
+  pub encoding: ValEncoding,
+  #[structopt(long, default_value = "interleaved", possible_values = &PageLayout::NAMES)]
+  /// Layout of the entries inside the pages, recorded in the output file: 'interleaved'
+  /// (id|val records) or 'columnar' (all values, then all ids, so that searches read only
+  /// value bytes)
+  pub page_layout: PageLayout,
+}
+
+/// Policy applied to the strings longer than the width of their column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Truncation {
+  /// The build fails
+  Error,
+  /// The strings are truncated at a character boundary, and counted
+  #[default]
+  Warn,
+  /// The width of the string columns is set from a pre-scan of the input
+  Auto,
+}
+
+impl Truncation {
+  /// Names of the policies, as accepted on the command line.
+  pub const NAMES: [&'static str; 3] = ["error", "warn", "auto"];
+}
+
+impl FromStr for Truncation {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "error" => Ok(Truncation::Error),
+      "warn" => Ok(Truncation::Warn),
+      "auto" => Ok(Truncation::Auto),
+      _ => Err(format!(
+        "Could not parse truncation policy: '{}'. Must be one of {:?}.",
+        s,
+        Truncation::NAMES
+      )),
+    }
+  }
 }
 
 impl MkAlgoArgs {
@@ -45,6 +122,27 @@ impl MkAlgoArgs {
         .map(|p| p.as_ref().to_path_buf())
         .unwrap_or(PathBuf::from(".bstree_tmp")),
       output: output.as_ref().to_path_buf(),
+      threads: 1,
+      checksums: false,
+      normalize: Default::default(),
+      truncation: Default::default(),
+      encoding: Default::default(),
+      page_layout: Default::default(),
+    }
+  }
+
+  /// Number of rows in a single chunk, taking into account the number of threads.
+  pub fn thread_chunk_size(&self) -> usize {
+    (self.chunk_size / self.threads.max(1)).max(1)
+  }
+
+  /// Optional features of the output file.
+  pub fn build_options(&self) -> BuildOptions {
+    BuildOptions {
+      checksums: self.checksums,
+      norm: self.normalize,
+      encoding: self.encoding,
+      page_layout: self.page_layout,
     }
   }
 
@@ -61,6 +159,7 @@ impl MkAlgoArgs {
 }
 
 const TMP_FILE_PREFIX: &str = ".bstree_chunk";
+const TMP_NULLS_FILE: &str = ".bstree_nulls";
 
 pub struct TmpDir {
   path: PathBuf,
@@ -112,12 +211,15 @@ impl TmpDir {
     VRW: ReadWrite<Type = V>,
     T: IntoIterator<Item = Entry<I, V>>,
   {
-    let mut buff = BufWriter::new(File::create(self.get_file_path(self.n_files))?);
-    for entry in entries.into_iter() {
-      entry.write(&mut buff, id_rw, val_rw)?;
-    }
+    write_entries(&self.reserve_tmp_file(), id_rw, val_rw, entries)
+  }
+
+  /// Returns the path of the next temporary file, to be written (possibly by another thread)
+  /// before reading the files of this directory.
+  pub fn reserve_tmp_file(&mut self) -> PathBuf {
+    let file_path = self.get_file_path(self.n_files);
     self.n_files += 1;
-    Ok(())
+    file_path
   }
 
   // Recursive function working level by level till the remaining number of temporary file is
@@ -128,6 +230,24 @@ impl TmpDir {
     val_rw: &VRW,
     k: usize,
   ) -> Result<Self, Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self.par_reduce_to_k_files(id_rw, val_rw, k, 1)
+  }
+
+  // Same as `reduce_to_k_files`, but the (independent) groups of `k` files of a same level are
+  // merged concurrently by (at most) `n_threads` threads.
+  pub fn par_reduce_to_k_files<I, V, IRW, VRW>(
+    self,
+    id_rw: &IRW,
+    val_rw: &VRW,
+    k: usize,
+    n_threads: usize,
+  ) -> Result<Self, Error>
   where
     I: Id,
     V: Val,
@@ -136,27 +256,72 @@ impl TmpDir {
   {
     if self.n_files > k {
       let mut next_level_dir = self.next_level();
-      // reduce by k-way merge using itertools
-      for chunk in &(0..self.n_files).chunks(k) {
-        // Merge k tmp files into a new file
-        next_level_dir.write_tmp_file(
-          id_rw,
-          val_rw,
-          chunk
-            .map(|i| {
-              debug!("level: {}; i_chunk: {}", self.level, &i);
-              self.to_sorted_entry_iter(id_rw, val_rw, i)
+      // Output file of each group of (at most) k files to be merged, reserved in the group order
+      let groups: Vec<(Vec<usize>, PathBuf)> = (0..self.n_files)
+        .chunks(k)
+        .into_iter()
+        .map(|chunk| (chunk.collect(), next_level_dir.reserve_tmp_file()))
+        .collect();
+      let next_group = AtomicUsize::new(0);
+      let n_workers = n_threads.clamp(1, groups.len());
+      thread::scope(|scope| {
+        let workers: Vec<_> = (0..n_workers)
+          .map(|_| {
+            let id_rw = id_rw.clone();
+            let val_rw = val_rw.clone();
+            let (this, groups, next_group) = (&self, &groups, &next_group);
+            scope.spawn(move || loop {
+              match groups.get(next_group.fetch_add(1, Ordering::Relaxed)) {
+                Some((indices, file_path)) => {
+                  this.merge_tmp_files(&id_rw, &val_rw, indices, file_path)?
+                }
+                None => return Ok(()),
+              }
             })
-            .kmerge(),
-        )?;
-      }
+          })
+          .collect();
+        workers.into_iter().try_for_each(|worker| {
+          worker
+            .join()
+            .unwrap_or_else(|_| Err(Error::new(ErrorKind::Other, "Merge thread panicked")))
+        })
+      })?;
       // Merge k files till number of temporary files is larger than k
-      next_level_dir.reduce_to_k_files(id_rw, val_rw, k)
+      next_level_dir.par_reduce_to_k_files(id_rw, val_rw, k, n_threads)
     } else {
       Ok(self)
     }
   }
 
+  // Merge the given tmp files of this level into the given file
+  fn merge_tmp_files<I, V, IRW, VRW>(
+    &self,
+    id_rw: &IRW,
+    val_rw: &VRW,
+    indices: &[usize],
+    file_path: &Path,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    // reduce by k-way merge using itertools
+    write_entries(
+      file_path,
+      id_rw,
+      val_rw,
+      indices
+        .iter()
+        .map(|i| {
+          debug!("level: {}; i_chunk: {}", self.level, i);
+          self.to_sorted_entry_iter(id_rw, val_rw, *i)
+        })
+        .kmerge(),
+    )
+  }
+
   pub fn to_sorted_iter<'a, I, V, IRW, VRW>(
     &mut self,
     id_rw: &'a IRW,
@@ -229,6 +394,151 @@ impl Drop for TmpDir {
   }
 }
 
+fn write_entries<I, V, IRW, VRW, T>(
+  file_path: &Path,
+  id_rw: &IRW,
+  val_rw: &VRW,
+  entries: T,
+) -> Result<(), Error>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+  T: IntoIterator<Item = Entry<I, V>>,
+{
+  let mut buff = BufWriter::new(File::create(file_path)?);
+  for entry in entries.into_iter() {
+    entry.write(&mut buff, id_rw, val_rw)?;
+  }
+  buff.flush()
+}
+
+type Chunk<I, V> = (PathBuf, Vec<Entry<I, V>>);
+
+/// Sort chunks of entries and write them in temporary files.
+/// If more than one thread is requested, the chunks are sorted and written by `n_threads - 1`
+/// worker threads, so that the caller can prepare the next chunk in the meantime.
+pub struct ChunkWriter<I, V, IRW, VRW>
+where
+  I: 'static + Id,
+  V: 'static + Val,
+  IRW: 'static + ReadWrite<Type = I>,
+  VRW: 'static + ReadWrite<Type = V>,
+{
+  id_rw: IRW,
+  val_rw: VRW,
+  /// Channel used to send the chunks to the workers (`None` if no worker)
+  sender: Option<SyncSender<Chunk<I, V>>>,
+  workers: Vec<JoinHandle<Result<(), Error>>>,
+}
+
+impl<I, V, IRW, VRW> ChunkWriter<I, V, IRW, VRW>
+where
+  I: 'static + Id,
+  V: 'static + Val,
+  IRW: 'static + ReadWrite<Type = I>,
+  VRW: 'static + ReadWrite<Type = V>,
+{
+  pub fn new(n_threads: usize, id_rw: &IRW, val_rw: &VRW) -> Self {
+    let n_workers = n_threads.saturating_sub(1);
+    let (sender, workers) = if n_workers == 0 {
+      (None, Vec::new())
+    } else {
+      // Rendez-vous channel: the caller is blocked till a worker is available, so at most
+      // `n_threads` chunks are in memory at the same time.
+      let (sender, receiver) = sync_channel::<Chunk<I, V>>(0);
+      let receiver = Arc::new(Mutex::new(receiver));
+      let workers = (0..n_workers)
+        .map(|_| {
+          let receiver = receiver.clone();
+          let id_rw = id_rw.clone();
+          let val_rw = val_rw.clone();
+          thread::spawn(move || sort_and_write_chunks(receiver, id_rw, val_rw))
+        })
+        .collect();
+      (Some(sender), workers)
+    };
+    ChunkWriter {
+      id_rw: id_rw.clone(),
+      val_rw: val_rw.clone(),
+      sender,
+      workers,
+    }
+  }
+
+  /// Sort the given chunk and write it in a new temporary file of the given directory.
+  pub fn write(
+    &mut self,
+    tmp_dir: &mut TmpDir,
+    mut entries: Vec<Entry<I, V>>,
+  ) -> Result<(), Error> {
+    let file_path = tmp_dir.reserve_tmp_file();
+    match &self.sender {
+      None => {
+        entries.sort_unstable();
+        write_entries(&file_path, &self.id_rw, &self.val_rw, entries)
+      }
+      Some(sender) => {
+        if sender.send((file_path, entries)).is_err() {
+          // All workers stopped, due to an error
+          self.join()?;
+          return Err(Error::new(ErrorKind::Other, "Sort threads unexpectedly stopped"));
+        }
+        Ok(())
+      }
+    }
+  }
+
+  /// Wait for all chunks to be written.
+  pub fn finish(mut self) -> Result<(), Error> {
+    self.join()
+  }
+
+  fn join(&mut self) -> Result<(), Error> {
+    // Dropping the sender stops the workers once all chunks have been processed
+    self.sender = None;
+    let mut res = Ok(());
+    for worker in self.workers.drain(..) {
+      let worker_res = worker
+        .join()
+        .unwrap_or_else(|_| Err(Error::new(ErrorKind::Other, "Sort thread panicked")));
+      if res.is_ok() {
+        res = worker_res;
+      }
+    }
+    res
+  }
+}
+
+fn sort_and_write_chunks<I, V, IRW, VRW>(
+  receiver: Arc<Mutex<Receiver<Chunk<I, V>>>>,
+  id_rw: IRW,
+  val_rw: VRW,
+) -> Result<(), Error>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  loop {
+    let chunk = receiver
+      .lock()
+      .map_err(|_| Error::new(ErrorKind::Other, "Poisoned lock"))?
+      .recv();
+    match chunk {
+      Ok((file_path, mut entries)) => {
+        debug!("Sort chunk...");
+        entries.sort_unstable();
+        write_entries(&file_path, &id_rw, &val_rw, entries)?;
+      }
+      // The sender has been dropped: no more chunks
+      Err(_) => return Ok(()),
+    }
+  }
+}
+
 struct TmpFile<'a, I, V, IRW, VRW>
 where
   I: Id,
@@ -308,3 +618,110 @@ where
     }
   }
 }
+
+/// Temporary file storing the identifiers of the rows having a NULL value, in the order they
+/// are appended.
+pub struct TmpNullsFile {
+  path: PathBuf,
+  writer: BufWriter<File>,
+  n_nulls: usize,
+}
+
+impl TmpNullsFile {
+  /// Create the file in the given (already existing) temporary directory.
+  pub fn new(tmp_dir: &Path) -> Result<TmpNullsFile, Error> {
+    let path = tmp_dir.join(TMP_NULLS_FILE);
+    let writer = BufWriter::new(File::create(&path)?);
+    Ok(TmpNullsFile {
+      path,
+      writer,
+      n_nulls: 0,
+    })
+  }
+
+  pub fn n_nulls(&self) -> usize {
+    self.n_nulls
+  }
+
+  pub fn append<I, IRW>(&mut self, id_rw: &IRW, id: &I) -> Result<(), Error>
+  where
+    I: Id,
+    IRW: ReadWrite<Type = I>,
+  {
+    id_rw.write(&mut self.writer, id)?;
+    self.n_nulls += 1;
+    Ok(())
+  }
+
+  /// Returns an iterator over all identifiers written so far.
+  pub fn to_iter<'a, I, IRW>(
+    &mut self,
+    id_rw: &'a IRW,
+  ) -> Result<TmpNullsFileIter<'a, I, IRW>, Error>
+  where
+    I: Id,
+    IRW: ReadWrite<Type = I>,
+  {
+    self.writer.flush()?;
+    Ok(TmpNullsFileIter {
+      reader: BufReader::new(File::open(&self.path)?),
+      id_rw,
+      n_nulls: self.n_nulls,
+      n_read: 0,
+      _id: PhantomData,
+    })
+  }
+}
+
+impl Drop for TmpNullsFile {
+  fn drop(&mut self) {
+    if let Err(e) = fs::remove_file(&self.path) {
+      error!("Unable to remove temporary file: {:?}. Err: {:?}", self.path, e);
+    }
+  }
+}
+
+pub struct TmpNullsFileIter<'a, I, IRW>
+where
+  I: Id,
+  IRW: ReadWrite<Type = I>,
+{
+  reader: BufReader<File>,
+  id_rw: &'a IRW,
+  n_nulls: usize,
+  n_read: usize,
+  _id: PhantomData<I>,
+}
+
+impl<'a, I, IRW> Iterator for TmpNullsFileIter<'a, I, IRW>
+where
+  I: Id,
+  IRW: ReadWrite<Type = I>,
+{
+  type Item = I;
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let n_remaining = self.n_nulls - self.n_read;
+    (n_remaining, Some(n_remaining))
+  }
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.n_read < self.n_nulls {
+      self.n_read += 1;
+      let id = self
+        .id_rw
+        .read(&mut self.reader)
+        .unwrap_or_else(|e| panic!("Error reading identifier: {:?}", &e));
+      Some(id)
+    } else {
+      None
+    }
+  }
+}
+
+impl<'a, I, IRW> ExactSizeIterator for TmpNullsFileIter<'a, I, IRW>
+where
+  I: Id,
+  IRW: ReadWrite<Type = I>,
+{
+}
@@ -6,3 +6,4 @@ pub mod csvargs;
 pub mod memsize;
 pub mod mkargs;
 pub mod mode;
+pub mod output;
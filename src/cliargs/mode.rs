@@ -1,5 +1,3 @@
-#[cfg(not(target_arch = "wasm32"))]
-use memmap::{Mmap, MmapOptions};
 use structopt::StructOpt;
 
 use std::{
@@ -10,10 +8,11 @@ use std::{
 };
 
 use crate::{
-  bstree::{read_meta, BSTreeMeta, SubTreeR},
+  bstree::{BSTreeFile, BatchOrder},
+  dist::Dist,
+  norm::StrNorm,
   rw::ReadWrite,
-  visitors::*,
-  Id, IdVal, Process, Val,
+  Id, IdVal, PageLayout, Process, Val,
 };
 
 #[derive(Clone, Debug, StructOpt, serde::Serialize, serde::Deserialize)]
@@ -36,8 +35,12 @@ pub enum Mode {
   #[structopt(name = "all")]
   /// Returns all entries having a value equal to the given value
   All {
-    #[structopt(short = "v", long)]
-    value: String,
+    #[structopt(short = "v", long, required_unless = "list")]
+    value: Option<String>,
+    #[structopt(long, parse(from_os_str), conflicts_with = "value")]
+    /// Execute the command for each value in the given file (the output starting with the index
+    /// of the value in the file)
+    list: Option<PathBuf>,
     #[structopt(short = "l", long)]
     /// Limits the number of entries in output
     limit: Option<usize>,
@@ -56,8 +59,12 @@ pub enum Mode {
   #[structopt(name = "knn")]
   /// Returns the k entries having the nearest value from the the given value
   Knn {
-    #[structopt(short = "v", long)]
-    value: String,
+    #[structopt(short = "v", long, required_unless = "list")]
+    value: Option<String>,
+    #[structopt(long, parse(from_os_str), conflicts_with = "value")]
+    /// Execute the command for each value in the given file (the output starting with the index
+    /// of the value in the file)
+    list: Option<PathBuf>,
     #[structopt(short = "k", long)]
     k: u16,
     #[structopt(long)]
@@ -66,12 +73,39 @@ pub enum Mode {
   #[structopt(name = "range")]
   /// Returns all entries having a value in the given value range
   Range {
-    #[structopt(short = "f", long = "from")]
+    #[structopt(short = "f", long = "from", required_unless = "list")]
     /// HLower value of the range
-    lo: String,
-    #[structopt(short = "t", long = "to")]
+    lo: Option<String>,
+    #[structopt(short = "t", long = "to", required_unless = "list")]
     /// Higher value of the range
-    hi: String,
+    hi: Option<String>,
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["lo", "hi"])]
+    /// Execute the command for each range in the given file, one `lo,hi` range per line (the
+    /// output starting with the index of the range in the file)
+    list: Option<PathBuf>,
+    #[structopt(short = "l", long)]
+    /// Limits the number of entries in output
+    limit: Option<usize>,
+    #[structopt(short = "c", long)]
+    /// Returns the size of the result instead of the result itself
+    count: bool,
+  },
+  #[structopt(name = "prefix")]
+  /// Returns all entries having a (string) value starting with the given prefix
+  Prefix {
+    #[structopt(short = "p", long)]
+    /// Prefix the values must start with
+    prefix: String,
+    #[structopt(short = "l", long)]
+    /// Limits the number of entries in output
+    limit: Option<usize>,
+    #[structopt(short = "c", long)]
+    /// Returns the size of the result instead of the result itself
+    count: bool,
+  },
+  #[structopt(name = "nulls")]
+  /// Returns the identifiers of the rows having a NULL value
+  Nulls {
     #[structopt(short = "l", long)]
     /// Limits the number of entries in output
     limit: Option<usize>,
@@ -79,6 +113,10 @@ pub enum Mode {
     /// Returns the size of the result instead of the result itself
     count: bool,
   },
+  #[structopt(name = "verify")]
+  /// Checks the file integrity: size, checksums (if any), order of the in-order traversal and
+  /// number of entries
+  Verify,
 }
 
 #[derive(Clone, Debug, StructOpt, serde::Serialize, serde::Deserialize)]
@@ -88,46 +126,121 @@ pub enum ValOrFile {
   Value { value: String },
   #[structopt(name = "list")]
   /// Execute the command for each value in the given file
-  List { file: PathBuf },
+  List {
+    file: PathBuf,
+    #[structopt(long)]
+    /// Sort the values to perform all the queries in a single walk of the tree
+    batch: bool,
+    #[structopt(long, requires = "batch")]
+    /// Output the results of a batch in tree order (i.e. by increasing value) instead of in input
+    /// order
+    tree_order: bool,
+    #[structopt(long, default_value = "1")]
+    /// Number of threads sharing the queries (the output order is preserved)
+    threads: usize,
+    #[structopt(long)]
+    /// Output one row per input value, starting with the index of its line in the input, the
+    /// (normalised) value and a found/not-found flag (the other columns being empty if not found)
+    echo: bool,
+  },
 }
 
+impl ValOrFile {
+  /// Returns the order of the batch results, or `None` if the list is not processed as a batch.
+  pub fn batch_order(&self) -> Option<BatchOrder> {
+    match self {
+      ValOrFile::List {
+        batch: true,
+        tree_order,
+        ..
+      } => Some(if *tree_order {
+        BatchOrder::Tree
+      } else {
+        BatchOrder::Input
+      }),
+      _ => None,
+    }
+  }
+
+  /// Returns the number of threads to be used to query the values of a list.
+  pub fn n_threads(&self) -> usize {
+    match self {
+      ValOrFile::List { threads, .. } => *threads,
+      _ => 1,
+    }
+  }
+}
+
+/// Parses the given (mandatory) command line value, after normalisation.
+pub fn parse_required<V: Val>(value: Option<&str>, name: &str, norm: &StrNorm) -> Result<V, Error> {
+  norm.parse(value.ok_or_else(|| Error::new(ErrorKind::Other, format!("Missing {}", name)))?)
+}
+
+/// Parses a range made of two values, separated by a comma, a tabulation or spaces.
+/// Each value is normalised.
+pub fn parse_range<V: Val>(line: &str, norm: &StrNorm) -> Result<(V, V), Error> {
+  let mut it = line
+    .split(|c: char| c == ',' || c.is_whitespace())
+    .filter(|s| !s.is_empty());
+  match (it.next(), it.next(), it.next()) {
+    (Some(lo), Some(hi), None) => Ok((
+      parse_required(Some(lo), "lower bound", norm)?,
+      parse_required(Some(hi), "upper bound", norm)?,
+    )),
+    _ => Err(Error::new(
+      ErrorKind::Other,
+      format!("Wrong range '{}': expected 'lo,hi'", line),
+    )),
+  }
+}
+
+/// Reads and parses the ranges of the given file, one `lo,hi` range per line.
+pub fn read_ranges<V: Val>(file: &Path, norm: &StrNorm) -> Result<Vec<(V, V)>, Error> {
+  BufReader::new(File::open(file)?)
+    .lines()
+    .map(|line| line.and_then(|l| parse_range(&l, norm)))
+    .collect()
+}
+
+/// Reads and parses (after normalisation) the values of the given file, one value per line.
+pub fn read_values<V: Val>(file: &Path, norm: &StrNorm) -> Result<Vec<V>, Error> {
+  BufReader::new(File::open(file)?)
+    .lines()
+    .map(|line| line.and_then(|v| norm.parse::<V>(&v)))
+    .collect()
+}
+
+/// Executes the given query on the given tree file, the identifiers having to be record numbers.
+/// Each returned identifier comes with the index of the query it results from, i.e. the index of
+/// the value (or range) in the `list` file, or `0` for a single query. For counts, the number of
+/// entries is printed instead (one `index,count` row per query for lists).
 #[cfg(not(target_arch = "wasm32"))]
-pub fn get_iter(path: &Path, mode: Mode) -> Result<Box<dyn Iterator<Item = u64> + Send>, Error> {
-  // Get the size of the file
-  // let metadata = fs::metadata(&path)?;
-  // let byte_size = metadata.len();
-  // Open the file and read the metadata part
-  let file = File::open(path)?;
-  let mmap = unsafe { MmapOptions::new().map(&file)? };
-  let (_version, data_starting_byte, meta) = read_meta(&mmap)?;
-  if !meta.types.id_type().is_recno_compatible() {
+pub fn get_iter(
+  path: &Path,
+  mode: Mode,
+) -> Result<Box<dyn Iterator<Item = (usize, u64)> + Send>, Error> {
+  let bstree = BSTreeFile::open(path)?;
+  if !bstree.types().id_type().is_recno_compatible() {
     return Err(Error::new(
       ErrorKind::Other,
       "Index identifier type not compatible with a record number",
     ));
   }
-  let idval = meta.types.clone();
-  idval.exec(QueryIter {
-    mode,
-    meta,
-    mmap,
-    data_starting_byte,
-  })
+  let idval = bstree.types().clone();
+  idval.exec_any(QueryIter { mode, bstree })
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 struct QueryIter {
   mode: Mode,
-  meta: BSTreeMeta,
-  mmap: Mmap,
-  data_starting_byte: usize,
+  bstree: BSTreeFile,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 impl Process for QueryIter {
-  type Output = Box<dyn Iterator<Item = u64> + Send>;
+  type Output = Box<dyn Iterator<Item = (usize, u64)> + Send>;
 
-  fn exec<I, V, D, IRW, VRW>(
+  fn exec<I, V, U, D, IRW, VRW>(
     self,
     _types: IdVal,
     id_rw: IRW,
@@ -137,81 +250,117 @@ impl Process for QueryIter {
   where
     I: 'static + Id,
     V: 'static + Val,
-    D: 'static + Fn(&V, &V) -> V + Send,
+    U: 'static + Dist,
+    D: 'static + Fn(&V, &V) -> U + Send + Sync,
     IRW: 'static + ReadWrite<Type = I>,
     VRW: 'static + ReadWrite<Type = V>,
   {
-    let root = self.meta.get_root();
+    let norm = *self.bstree.norm();
     match self.mode {
       Mode::Info => {
-        println!("{}", serde_json::to_string_pretty(&self.meta)?);
+        println!("{}", serde_json::to_string_pretty(self.bstree.meta())?);
         Ok(Box::new(iter::empty()))
       },
       Mode::Data { limit } => {
+        if self.bstree.page_layout() != PageLayout::Interleaved {
+          return Err(Error::new(
+            ErrorKind::Other,
+            "The data mode is only supported for files having interleaved pages",
+          ));
+        }
         let limit = limit.unwrap_or(1000);
         let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
-        let it = self.mmap[self.data_starting_byte..].chunks_exact(entry_byte_size);
+        let it = self.bstree.data().chunks_exact(entry_byte_size);
         let res: Vec<u64> =  it.take(limit).filter_map(move |kv| {
             let mut cursor = Cursor::new(kv);
             id_rw.read(&mut cursor).map(|id| id.to_u64()).ok()
           }).collect();
-        Ok(Box::new(res.into_iter()))
+        Ok(Box::new(res.into_iter().map(|id| (0, id))))
       },
       Mode::GetFirst { ref val_or_file } => match val_or_file {
         ValOrFile::Value { value } => {
-          let v = value
-            .parse::<V>()
-            .map_err(|_e| Error::new(ErrorKind::Other, "Wrong value type"))?;
-          let visitor = VisitorExact::new(v);
-          let visitor = root.visit(
-            visitor,
-            &self.mmap[self.data_starting_byte..],
-            &id_rw,
-            &val_rw,
-          )?;
-          Ok(Box::new(visitor.entry.into_iter().map(|e| e.id.to_u64())))
+          let v = norm.parse::<V>(value)?;
+          let entry = self.bstree.get(v, &id_rw, &val_rw)?;
+          Ok(Box::new(entry.into_iter().map(|e| (0, e.id.to_u64()))))
+        }
+        ValOrFile::List { file, .. } if val_or_file.batch_order().is_some() => {
+          let order = val_or_file.batch_order().unwrap();
+          let res = self
+            .bstree
+            .get_batch(read_values::<V>(file, &norm)?, order, val_or_file.n_threads(), &id_rw, &val_rw)?;
+          Ok(Box::new(
+            res.into_iter().filter_map(|(i, e)| e.map(|e| (i, e.id.to_u64()))),
+          ))
         }
-        ValOrFile::List { file } => Ok(Box::new(
+        ValOrFile::List { file, threads, .. } if *threads > 1 => {
+          let res = self
+            .bstree
+            .get_list(read_values::<V>(file, &norm)?, *threads, &id_rw, &val_rw)?;
+          Ok(Box::new(
+            res
+              .into_iter()
+              .enumerate()
+              .filter_map(|(i, e)| e.map(|e| (i, e.id.to_u64()))),
+          ))
+        }
+        ValOrFile::List { file, .. } => Ok(Box::new(
           BufReader::new(File::open(file)?)
             .lines()
-            .filter_map(move |line| {
+            .enumerate()
+            .filter_map(move |(i, line)| {
               line
                 .and_then(|v| {
-                  v.parse::<V>()
-                    .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))
-                })
-                .and_then(|v| {
-                  root.visit(
-                    VisitorExact::new(v),
-                    &self.mmap[self.data_starting_byte..],
-                    &id_rw,
-                    &val_rw,
-                  )
+                  norm.parse::<V>(&v)
                 })
+                .and_then(|v| self.bstree.get(v, &id_rw, &val_rw))
                 .ok()
-                .and_then(|v| v.entry)
-            })
-            .map(|e| e.id.to_u64()),
+                .flatten()
+                .map(|e| (i, e.id.to_u64()))
+            }),
         )),
       },
+      Mode::All {
+        value: _,
+        list: Some(file),
+        limit,
+        count,
+      } => {
+        let limit = limit.unwrap_or(usize::MAX);
+        let values = read_values::<V>(&file, &norm)?;
+        if count {
+          println!("index,count");
+          for (i, v) in values.into_iter().enumerate() {
+            println!("{},{}", i, self.bstree.count_all(v, limit, &id_rw, &val_rw)?);
+          }
+          Ok(Box::new(iter::empty()))
+        } else {
+          let mut res = Vec::new();
+          for (i, v) in values.into_iter().enumerate() {
+            let entries = self.bstree.all(v, limit, &id_rw, &val_rw)?;
+            res.extend(entries.into_iter().map(|e| (i, e.id.to_u64())));
+          }
+          Ok(Box::new(res.into_iter()))
+        }
+      }
       Mode::All {
         value,
+        list: None,
         limit,
         count,
       } => {
-        let v = value
-          .parse::<V>()
-          .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
+        let v = parse_required::<V>(value.as_deref(), "value", &norm)?;
         if count {
-          let v = VisitorAllCount::new(v, limit.unwrap_or(std::usize::MAX));
-          let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
+          let n_entries = self
+            .bstree
+            .count_all(v, limit.unwrap_or(usize::MAX), &id_rw, &val_rw)?;
           println!("index output count");
-          println!("{}", v.n_entries);
+          println!("{}", n_entries);
           Ok(Box::new(iter::empty()))
         } else {
-          let v = VisitorAll::new(v, limit.unwrap_or(std::usize::MAX));
-          let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-          Ok(Box::new(v.entries.into_iter().map(|e| e.id.to_u64())))
+          let entries = self
+            .bstree
+            .all(v, limit.unwrap_or(usize::MAX), &id_rw, &val_rw)?;
+          Ok(Box::new(entries.into_iter().map(|e| (0, e.id.to_u64()))))
         }
       }
       Mode::Nn {
@@ -221,92 +370,255 @@ impl Process for QueryIter {
         let d_max = d_max
           .as_ref()
           .map(|d| {
-            d.parse::<V>()
+            d.parse::<U>()
               .map_err(|_| Error::new(ErrorKind::Other, "Wrong distance type"))
           })
           .transpose()?;
         match val_or_file {
           ValOrFile::Value { value } => {
-            let v = value
-              .parse::<V>()
-              .map_err(|_| Error::new(ErrorKind::Other, ""))?;
-            let v = VisitorNn::new(v, &dist, d_max);
-            let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
+            let v = norm.parse::<V>(value)?;
+            let nn = self.bstree.nn(v, &dist, d_max, &id_rw, &val_rw)?;
             Ok(Box::new(
-              v.nn.into_iter().map(|neig| neig.neighbour.id.to_u64()),
+              nn.into_iter().map(|neig| (0, neig.neighbour.id.to_u64())),
             ))
           }
-          ValOrFile::List { file } => Ok(Box::new(
+          ValOrFile::List { file, .. } if val_or_file.batch_order().is_some() => {
+            let order = val_or_file.batch_order().unwrap();
+            let res = self.bstree.nn_batch(
+              read_values::<V>(file, &norm)?,
+              order,
+              val_or_file.n_threads(),
+              &dist,
+              d_max,
+              &id_rw,
+              &val_rw,
+            )?;
+            Ok(Box::new(res.into_iter().filter_map(|(i, nn)| {
+              nn.map(|neig| (i, neig.neighbour.id.to_u64()))
+            })))
+          }
+          ValOrFile::List { file, threads, .. } if *threads > 1 => {
+            let res = self.bstree.nn_list(
+              read_values::<V>(file, &norm)?,
+              *threads,
+              &dist,
+              d_max,
+              &id_rw,
+              &val_rw,
+            )?;
+            Ok(Box::new(res.into_iter().enumerate().filter_map(|(i, nn)| {
+              nn.map(|neig| (i, neig.neighbour.id.to_u64()))
+            })))
+          }
+          ValOrFile::List { file, .. } => Ok(Box::new(
             BufReader::new(File::open(file)?)
               .lines()
-              .filter_map(move |line| {
+              .enumerate()
+              .filter_map(move |(i, line)| {
                 line
                   .and_then(|v| {
-                    v.parse::<V>()
-                      .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))
-                  })
-                  .and_then(|v| {
-                    root.visit(
-                      VisitorNn::new(v, &dist, d_max.clone()),
-                      &self.mmap[self.data_starting_byte..],
-                      &id_rw,
-                      &val_rw,
-                    )
+                    norm.parse::<V>(&v)
                   })
+                  .and_then(|v| self.bstree.nn(v, &dist, d_max.clone(), &id_rw, &val_rw))
                   .ok()
-                  .and_then(|v| v.nn)
-              })
-              .map(|neig| neig.neighbour.id.to_u64()),
+                  .flatten()
+                  .map(|neig| (i, neig.neighbour.id.to_u64()))
+              }),
           )),
         }
       }
-      Mode::Knn { value, k, d_max } => {
-        let v = value
-          .parse::<V>()
-          .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
-        let v: VisitorKnn<I, V, V, _> = VisitorKnn::new(
-          v,
-          dist,
-          k as usize,
-          d_max
-            .map(|d| {
-              d.parse::<V>()
-                .map_err(|_| Error::new(ErrorKind::Other, "Wrong distance type"))
-            })
-            .transpose()?,
-        );
-        let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-        Ok(Box::new(
-          v.knn
-            .into_sorted_vec()
-            .into_iter()
-            .map(|neig| neig.neighbour.id.to_u64()),
-        ))
+      Mode::Knn {
+        value,
+        list,
+        k,
+        d_max,
+      } => {
+        let d_max = d_max
+          .map(|d| {
+            d.parse::<U>()
+              .map_err(|_| Error::new(ErrorKind::Other, "Wrong distance type"))
+          })
+          .transpose()?;
+        let values = match list {
+          Some(file) => read_values::<V>(&file, &norm)?,
+          None => vec![parse_required::<V>(value.as_deref(), "value", &norm)?],
+        };
+        let mut res = Vec::new();
+        for (i, v) in values.into_iter().enumerate() {
+          let knn = self
+            .bstree
+            .knn(v, &dist, k as usize, d_max.clone(), &id_rw, &val_rw)?;
+          res.extend(knn.into_iter().map(|neig| (i, neig.neighbour.id.to_u64())));
+        }
+        Ok(Box::new(res.into_iter()))
+      }
+      Mode::Range {
+        list: Some(file),
+        limit,
+        count,
+        ..
+      } => {
+        let limit = limit.unwrap_or(usize::MAX);
+        let ranges = read_ranges::<V>(&file, &norm)?;
+        if count {
+          println!("index,count");
+          for (i, (lo, hi)) in ranges.into_iter().enumerate() {
+            println!("{},{}", i, self.bstree.count_range(lo, hi, limit, &id_rw, &val_rw)?);
+          }
+          Ok(Box::new(iter::empty()))
+        } else {
+          let mut res = Vec::new();
+          for (i, (lo, hi)) in ranges.into_iter().enumerate() {
+            let entries = self.bstree.range(lo, hi, limit, &id_rw, &val_rw)?;
+            res.extend(entries.into_iter().map(|e| (i, e.id.to_u64())));
+          }
+          Ok(Box::new(res.into_iter()))
+        }
       }
       Mode::Range {
         lo,
         hi,
+        list: None,
         limit,
         count,
       } => {
-        let lo = lo
-          .parse::<V>()
-          .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
-        let hi = hi
-          .parse::<V>()
-          .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?;
+        let lo = parse_required::<V>(lo.as_deref(), "lower bound", &norm)?;
+        let hi = parse_required::<V>(hi.as_deref(), "upper bound", &norm)?;
         if count {
-          let v = VisitorRangeCount::new(lo, hi, limit.unwrap_or(std::usize::MAX));
-          let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
+          let n_entries = self
+            .bstree
+            .count_range(lo, hi, limit.unwrap_or(usize::MAX), &id_rw, &val_rw)?;
           println!("index output count");
-          println!("{}", v.n_entries);
+          println!("{}", n_entries);
           Ok(Box::new(iter::empty()))
         } else {
-          let v = VisitorRange::new(lo, hi, limit.unwrap_or(std::usize::MAX));
-          let v = root.visit(v, &self.mmap[self.data_starting_byte..], &id_rw, &val_rw)?;
-          Ok(Box::new(v.entries.into_iter().map(|e| e.id.to_u64())))
+          let entries = self.bstree.into_range_iter(lo, hi, id_rw, val_rw)?;
+          Ok(Box::new(
+            entries
+              .take(limit.unwrap_or(usize::MAX))
+              .filter_map(|e| e.ok())
+              .map(|e| (0, e.id.to_u64())),
+          ))
         }
       }
+      Mode::Prefix {
+        prefix,
+        limit,
+        count,
+      } => {
+        let prefix = parse_required::<V>(Some(&prefix), "prefix", &norm)?;
+        let limit = limit.unwrap_or(usize::MAX);
+        if count {
+          let n_entries = self.bstree.count_prefix(prefix, limit, &id_rw, &val_rw)?;
+          println!("index output count");
+          println!("{}", n_entries);
+          Ok(Box::new(iter::empty()))
+        } else {
+          let entries = self.bstree.prefix(prefix, limit, &id_rw, &val_rw)?;
+          Ok(Box::new(entries.into_iter().map(|e| (0, e.id.to_u64()))))
+        }
+      }
+      Mode::Nulls { limit, count } => {
+        if count {
+          println!("index output count");
+          println!("{}", self.bstree.n_nulls());
+          Ok(Box::new(iter::empty()))
+        } else {
+          let res: Vec<u64> = self
+            .bstree
+            .nulls(&id_rw)?
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|id| id.map(|id| id.to_u64()))
+            .collect::<Result<_, Error>>()?;
+          Ok(Box::new(res.into_iter().map(|id| (0, id))))
+        }
+      }
+      Mode::Verify => Err(Error::new(
+        ErrorKind::Other,
+        "Verify mode not supported as an index query",
+      )),
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    bstree::build,
+    cliargs::memsize::MemSizeArgs,
+    rw::U32RW,
+    testutils::TmpDir,
+    Entry, IdType, ValType,
+  };
+
+  #[test]
+  fn testok_get_iter_list_index() {
+    let dir = TmpDir::new("mode_list_index");
+    let path = dir.join("u32u32.bstree");
+    // Each value 3 times
+    let entries: Vec<Entry<u32, u32>> = (0..300_u32).map(|i| Entry::new(i, i / 3)).collect();
+    build(
+      path.clone(),
+      &MemSizeArgs {
+        l1: 32,
+        disk: 8192,
+        fill_factor: 1.0,
+      },
+      entries.len(),
+      entries.into_iter(),
+      &IdVal(IdType::U32, ValType::U32),
+      &U32RW,
+      &U32RW,
+    )
+    .unwrap();
+    let values = dir.join("values.txt");
+    std::fs::write(&values, "7\n1000\n2\n").unwrap();
+    let mut res: Vec<(usize, u64)> = get_iter(
+      &path,
+      Mode::All {
+        value: None,
+        list: Some(values.clone()),
+        limit: None,
+        count: false,
+      },
+    )
+    .unwrap()
+    .collect();
+    res.sort_unstable();
+    assert_eq!(res, vec![(0, 21), (0, 22), (0, 23), (2, 6), (2, 7), (2, 8)]);
+    let res: Vec<usize> = get_iter(
+      &path,
+      Mode::Knn {
+        value: None,
+        list: Some(values),
+        k: 2,
+        d_max: None,
+      },
+    )
+    .unwrap()
+    .map(|(i, _)| i)
+    .collect();
+    assert_eq!(res, vec![0, 0, 1, 1, 2, 2]);
+    let ranges = dir.join("ranges.txt");
+    std::fs::write(&ranges, "98,200\n5,5\n").unwrap();
+    let mut res: Vec<(usize, u64)> = get_iter(
+      &path,
+      Mode::Range {
+        lo: None,
+        hi: None,
+        list: Some(ranges),
+        limit: None,
+        count: false,
+      },
+    )
+    .unwrap()
+    .collect();
+    res.sort_unstable();
+    let expected: Vec<(usize, u64)> = (294..300)
+      .map(|id| (0, id))
+      .chain((15..18).map(|id| (1, id)))
+      .collect();
+    assert_eq!(res, expected);
+  }
+}
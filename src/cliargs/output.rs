@@ -0,0 +1,600 @@
+//! Output of the query results, in one of the supported formats.
+
+use std::{
+  fmt::{self, Display, Formatter, Write as FmtWrite},
+  io::{Error, ErrorKind, Write},
+  str::FromStr,
+};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::{dist::Dist, rw::ReadWrite, visitors::Neigbhour, Entry, Id, Val};
+
+/// Format of the query results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+  /// Comma separated values, with a header line (fields are quoted when needed)
+  #[default]
+  Csv,
+  /// Tabulation separated values, with a header line (tabs and new lines are escaped)
+  Tsv,
+  /// A single JSON array of objects
+  Json,
+  /// One JSON object per line
+  NdJson,
+  /// Raw binary rows, without header: identifiers, values and distances are written with the
+  /// codecs of the tree file, counts as `u64` and texts as a `u32` byte size followed by the
+  /// UTF-8 bytes (all in little-endian)
+  Bin,
+}
+
+impl OutputFormat {
+  /// Names of the formats, as accepted on the command line.
+  pub const NAMES: [&'static str; 5] = ["csv", "tsv", "json", "ndjson", "bin"];
+}
+
+impl FromStr for OutputFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "csv" => Ok(OutputFormat::Csv),
+      "tsv" => Ok(OutputFormat::Tsv),
+      "json" => Ok(OutputFormat::Json),
+      "ndjson" => Ok(OutputFormat::NdJson),
+      "bin" => Ok(OutputFormat::Bin),
+      _ => Err(format!(
+        "Could not parse output format: '{}'. Must be one of {:?}.",
+        s,
+        OutputFormat::NAMES
+      )),
+    }
+  }
+}
+
+impl Display for OutputFormat {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      OutputFormat::Csv => OutputFormat::NAMES[0],
+      OutputFormat::Tsv => OutputFormat::NAMES[1],
+      OutputFormat::Json => OutputFormat::NAMES[2],
+      OutputFormat::NdJson => OutputFormat::NAMES[3],
+      OutputFormat::Bin => OutputFormat::NAMES[4],
+    };
+    f.write_str(name)
+  }
+}
+
+/// A field of an output row.
+pub trait Field: Display {
+  /// Tells whether the field is a string (quoted in JSON) or a number.
+  fn is_str(&self) -> bool;
+  /// Tells whether the field is empty (`null` in JSON, an empty string in CSV and TSV).
+  fn is_null(&self) -> bool {
+    false
+  }
+  /// Writes the field in the binary format.
+  fn write_bin(&self, out: &mut dyn Write) -> Result<(), Error>;
+}
+
+/// Identifier or value written with the codec of the tree file.
+pub struct Coded<'a, T, RW> {
+  /// `None` for an empty field, written as zeros in the binary format
+  value: Option<&'a T>,
+  rw: &'a RW,
+}
+
+impl<'a, T, RW> Coded<'a, T, RW> {
+  pub fn new(value: &'a T, rw: &'a RW) -> Self {
+    Coded {
+      value: Some(value),
+      rw,
+    }
+  }
+
+  /// Empty field
+  pub fn null(rw: &'a RW) -> Self {
+    Coded { value: None, rw }
+  }
+}
+
+impl<'a, T: Display, RW> Display for Coded<'a, T, RW> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self.value {
+      Some(value) => value.fmt(f),
+      None => Ok(()),
+    }
+  }
+}
+
+impl<'a, T: Display, RW: ReadWrite<Type = T>> Field for Coded<'a, T, RW> {
+  fn is_str(&self) -> bool {
+    self.rw.is_str()
+  }
+
+  fn is_null(&self) -> bool {
+    self.value.is_none()
+  }
+
+  fn write_bin(&self, mut out: &mut dyn Write) -> Result<(), Error> {
+    match self.value {
+      Some(value) => self.rw.write(&mut out, value),
+      None => out.write_all(&vec![0_u8; self.rw.n_bytes()]),
+    }
+  }
+}
+
+/// Distance between two values.
+pub struct Distance<'a, U>(
+  /// `None` for an empty field
+  pub Option<&'a U>,
+);
+
+impl<'a, U: Display> Display for Distance<'a, U> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self.0 {
+      Some(d) => d.fmt(f),
+      None => Ok(()),
+    }
+  }
+}
+
+impl<'a, U: Dist> Field for Distance<'a, U> {
+  fn is_str(&self) -> bool {
+    false
+  }
+
+  fn is_null(&self) -> bool {
+    self.0.is_none()
+  }
+
+  fn write_bin(&self, out: &mut dyn Write) -> Result<(), Error> {
+    match self.0 {
+      Some(d) => d.write_bin(out),
+      None => U::write_null_bin(out),
+    }
+  }
+}
+
+impl Field for bool {
+  fn is_str(&self) -> bool {
+    false
+  }
+
+  fn write_bin(&self, out: &mut dyn Write) -> Result<(), Error> {
+    out.write_u8(*self as u8)
+  }
+}
+
+impl Field for u64 {
+  fn is_str(&self) -> bool {
+    false
+  }
+
+  fn write_bin(&self, out: &mut dyn Write) -> Result<(), Error> {
+    out.write_u64::<LittleEndian>(*self)
+  }
+}
+
+impl Field for &str {
+  fn is_str(&self) -> bool {
+    true
+  }
+
+  fn write_bin(&self, out: &mut dyn Write) -> Result<(), Error> {
+    let len = u32::try_from(self.len())
+      .map_err(|_| Error::new(ErrorKind::InvalidInput, "Text too long for the binary output"))?;
+    out.write_u32::<LittleEndian>(len)?;
+    out.write_all(self.as_bytes())
+  }
+}
+
+/// Writes rows of results in a given format.
+///
+/// The `header` must be written before the first row, and `finish` must be called after the last
+/// row (e.g. to close the JSON array and to flush the output).
+pub struct ResultWriter<W: Write> {
+  out: W,
+  format: OutputFormat,
+  /// Column names
+  columns: Vec<String>,
+  /// Number of rows already written
+  n_rows: usize,
+  /// Buffer reused to format the fields
+  buf: String,
+}
+
+impl<W: Write> ResultWriter<W> {
+  pub fn new(out: W, format: OutputFormat) -> Self {
+    ResultWriter {
+      out,
+      format,
+      columns: Vec::new(),
+      n_rows: 0,
+      buf: String::new(),
+    }
+  }
+
+  pub fn format(&self) -> OutputFormat {
+    self.format
+  }
+
+  /// Set the names of the columns, written as a header line in CSV and TSV.
+  pub fn header(&mut self, columns: &[&str]) -> Result<(), Error> {
+    self.columns = columns.iter().map(|c| c.to_string()).collect();
+    match self.format {
+      OutputFormat::Csv | OutputFormat::Tsv => {
+        let fields: Vec<&dyn Field> = columns.iter().map(|c| c as &dyn Field).collect();
+        self.text_row(&fields)
+      }
+      OutputFormat::Json => self.out.write_all(b"["),
+      OutputFormat::NdJson | OutputFormat::Bin => Ok(()),
+    }
+  }
+
+  /// Writes a row made of the given fields, one per column.
+  pub fn row(&mut self, fields: &[&dyn Field]) -> Result<(), Error> {
+    debug_assert_eq!(fields.len(), self.columns.len());
+    match self.format {
+      OutputFormat::Csv | OutputFormat::Tsv => self.text_row(fields)?,
+      OutputFormat::Json => {
+        // Format the object first, not to write a separator if it fails
+        self.json_object(fields)?;
+        if self.n_rows > 0 {
+          self.out.write_all(b",")?;
+        }
+        self.out.write_all(b"\n")?;
+        self.out.write_all(self.buf.as_bytes())?;
+      }
+      OutputFormat::NdJson => {
+        self.json_object(fields)?;
+        self.buf.push('\n');
+        self.out.write_all(self.buf.as_bytes())?;
+      }
+      OutputFormat::Bin => {
+        for field in fields {
+          field.write_bin(&mut self.out)?;
+        }
+      }
+    }
+    self.n_rows += 1;
+    Ok(())
+  }
+
+  /// Writes the given entry in the `id, val` columns.
+  pub fn entry<I, V, IRW, VRW>(
+    &mut self,
+    entry: &Entry<I, V>,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self.row(&[
+      &Coded::new(&entry.id, id_rw),
+      &Coded::new(&entry.val, val_rw),
+    ])
+  }
+
+  /// Writes the index of the query in the input, the query value, a flag telling whether an
+  /// entry has been found, and the entry (empty fields if not found) in the
+  /// `index, query, found, id, val` columns.
+  pub fn query_entry<I, V, IRW, VRW>(
+    &mut self,
+    index: usize,
+    query: &V,
+    entry: Option<&Entry<I, V>>,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let index = index as u64;
+    match entry {
+      Some(entry) => self.row(&[
+        &index,
+        &Coded::new(query, val_rw),
+        &true,
+        &Coded::new(&entry.id, id_rw),
+        &Coded::new(&entry.val, val_rw),
+      ]),
+      None => self.row(&[
+        &index,
+        &Coded::new(query, val_rw),
+        &false,
+        &Coded::null(id_rw),
+        &Coded::null(val_rw),
+      ]),
+    }
+  }
+
+  /// Writes the given neighbour in the `distance, id, val` columns.
+  pub fn neighbour<I, V, U, IRW, VRW>(
+    &mut self,
+    neighbour: &Neigbhour<I, V, U>,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    U: Dist,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    self.row(&[
+      &Distance(Some(&neighbour.distance)),
+      &Coded::new(&neighbour.neighbour.id, id_rw),
+      &Coded::new(&neighbour.neighbour.val, val_rw),
+    ])
+  }
+
+  /// Writes the index of the query in the input, the query value, a flag telling whether a
+  /// neighbour has been found, and the neighbour (empty fields if not found) in the
+  /// `index, query, found, distance, id, val` columns.
+  pub fn query_neighbour<I, V, U, IRW, VRW>(
+    &mut self,
+    index: usize,
+    query: &V,
+    neighbour: Option<&Neigbhour<I, V, U>>,
+    id_rw: &IRW,
+    val_rw: &VRW,
+  ) -> Result<(), Error>
+  where
+    I: Id,
+    V: Val,
+    U: Dist,
+    IRW: ReadWrite<Type = I>,
+    VRW: ReadWrite<Type = V>,
+  {
+    let index = index as u64;
+    match neighbour {
+      Some(neighbour) => self.row(&[
+        &index,
+        &Coded::new(query, val_rw),
+        &true,
+        &Distance(Some(&neighbour.distance)),
+        &Coded::new(&neighbour.neighbour.id, id_rw),
+        &Coded::new(&neighbour.neighbour.val, val_rw),
+      ]),
+      None => self.row(&[
+        &index,
+        &Coded::new(query, val_rw),
+        &false,
+        &Distance::<U>(None),
+        &Coded::null(id_rw),
+        &Coded::null(val_rw),
+      ]),
+    }
+  }
+
+  /// Ends the output.
+  pub fn finish(mut self) -> Result<W, Error> {
+    // Nothing to close if no header has been written (e.g. the `info` mode)
+    if self.format == OutputFormat::Json && !self.columns.is_empty() {
+      self.out.write_all(if self.n_rows > 0 { b"\n]\n" } else { b"]\n" })?;
+    }
+    self.out.flush()?;
+    Ok(self.out)
+  }
+
+  fn text_row(&mut self, fields: &[&dyn Field]) -> Result<(), Error> {
+    let sep = if self.format == OutputFormat::Tsv { '\t' } else { ',' };
+    self.buf.clear();
+    for (i, field) in fields.iter().enumerate() {
+      if i > 0 {
+        self.buf.push(sep);
+      }
+      let from = self.buf.len();
+      write!(self.buf, "{}", field).map_err(|e| Error::new(ErrorKind::Other, e))?;
+      if field.is_str() {
+        let text = self.buf.split_off(from);
+        if sep == '\t' {
+          escape_tsv(&text, &mut self.buf);
+        } else {
+          quote_csv(&text, &mut self.buf);
+        }
+      }
+    }
+    self.buf.push('\n');
+    self.out.write_all(self.buf.as_bytes())
+  }
+
+  /// Formats the given fields as a JSON object in the buffer.
+  fn json_object(&mut self, fields: &[&dyn Field]) -> Result<(), Error> {
+    self.buf.clear();
+    self.buf.push('{');
+    for (i, (name, field)) in self.columns.iter().zip(fields).enumerate() {
+      if i > 0 {
+        self.buf.push(',');
+      }
+      self.buf.push_str(&serde_json::to_string(name)?);
+      self.buf.push(':');
+      if field.is_null() {
+        self.buf.push_str("null");
+      } else if field.is_str() {
+        self.buf.push_str(&serde_json::to_string(&field.to_string())?);
+      } else {
+        write!(self.buf, "{}", field).map_err(|e| Error::new(ErrorKind::Other, e))?;
+      }
+    }
+    self.buf.push('}');
+    Ok(())
+  }
+}
+
+/// Quotes the given CSV field if it contains a separator, a quote or a new line (RFC 4180).
+fn quote_csv(text: &str, buf: &mut String) {
+  if text.contains([',', '"', '\n', '\r']) {
+    buf.push('"');
+    buf.push_str(&text.replace('"', "\"\""));
+    buf.push('"');
+  } else {
+    buf.push_str(text);
+  }
+}
+
+/// Escapes the tabs, new lines and backslashes of the given TSV field.
+fn escape_tsv(text: &str, buf: &mut String) {
+  for c in text.chars() {
+    match c {
+      '\t' => buf.push_str("\\t"),
+      '\n' => buf.push_str("\\n"),
+      '\r' => buf.push_str("\\r"),
+      '\\' => buf.push_str("\\\\"),
+      _ => buf.push(c),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::rw::{StrRW, U32RW};
+
+  /// Field failing to be formatted.
+  struct Failing;
+
+  impl Display for Failing {
+    fn fmt(&self, _f: &mut Formatter<'_>) -> fmt::Result {
+      Err(fmt::Error)
+    }
+  }
+
+  impl Field for Failing {
+    fn is_str(&self) -> bool {
+      false
+    }
+
+    fn write_bin(&self, _out: &mut dyn Write) -> Result<(), Error> {
+      Err(Error::other("Failing field"))
+    }
+  }
+
+  fn write_rows(format: OutputFormat, failing: bool) -> Result<String, Error> {
+    let str_rw = StrRW { n_bytes: 8 };
+    let mut out = ResultWriter::new(Vec::new(), format);
+    out.header(&["id", "val"])?;
+    out.row(&[&Coded::new(&String::from("a,\"b\""), &str_rw), &7_u64])?;
+    out.row(&[&Coded::new(&String::from("c\td"), &str_rw), &8_u64])?;
+    let res = if failing {
+      out.row(&[&Coded::null(&str_rw), &Failing])
+    } else {
+      Ok(())
+    };
+    // As in qbst, the output is always ended
+    let bytes = out.finish()?;
+    res.map(|()| String::from_utf8(bytes).unwrap())
+  }
+
+  #[test]
+  fn testok_quote_csv() {
+    let quote = |text: &str| {
+      let mut buf = String::new();
+      quote_csv(text, &mut buf);
+      buf
+    };
+    assert_eq!(quote("abc"), "abc");
+    assert_eq!(quote("a,b"), "\"a,b\"");
+    assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(quote("a\nb"), "\"a\nb\"");
+    assert_eq!(quote(""), "");
+  }
+
+  #[test]
+  fn testok_escape_tsv() {
+    let escape = |text: &str| {
+      let mut buf = String::new();
+      escape_tsv(text, &mut buf);
+      buf
+    };
+    assert_eq!(escape("abc"), "abc");
+    assert_eq!(escape("a\tb\nc\r"), "a\\tb\\nc\\r");
+    assert_eq!(escape("a\\b,\"c\""), "a\\\\b,\"c\"");
+  }
+
+  #[test]
+  fn testok_text_rows() {
+    assert_eq!(
+      write_rows(OutputFormat::Csv, false).unwrap(),
+      "id,val\n\"a,\"\"b\"\"\",7\nc\td,8\n"
+    );
+    assert_eq!(
+      write_rows(OutputFormat::Tsv, false).unwrap(),
+      "id\tval\na,\"b\"\t7\nc\\td\t8\n"
+    );
+  }
+
+  #[test]
+  fn testok_json_rows() {
+    let expected = serde_json::json!([{"id": "a,\"b\"", "val": 7}, {"id": "c\td", "val": 8}]);
+    let json = write_rows(OutputFormat::Json, false).unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), expected);
+    let ndjson = write_rows(OutputFormat::NdJson, false).unwrap();
+    let lines: Vec<serde_json::Value> = ndjson
+      .lines()
+      .map(|line| serde_json::from_str(line).unwrap())
+      .collect();
+    assert_eq!(serde_json::Value::Array(lines), expected);
+    // Without any row
+    let mut out = ResultWriter::new(Vec::new(), OutputFormat::Json);
+    out.header(&["id"]).unwrap();
+    assert_eq!(out.finish().unwrap(), b"[]\n");
+    // No array to close when no header has been written
+    let out = ResultWriter::new(Vec::new(), OutputFormat::Json);
+    assert!(out.finish().unwrap().is_empty());
+  }
+
+  #[test]
+  fn testok_json_closed_on_error() {
+    let mut out = ResultWriter::new(Vec::new(), OutputFormat::Json);
+    out.header(&["id", "val"]).unwrap();
+    out.row(&[&1_u64, &2_u64]).unwrap();
+    assert!(out.row(&[&3_u64, &Failing]).is_err());
+    let json = String::from_utf8(out.finish().unwrap()).unwrap();
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+      serde_json::json!([{"id": 1, "val": 2}])
+    );
+    // Same in the other formats: failing rows are not written
+    for format in [OutputFormat::Csv, OutputFormat::NdJson] {
+      let mut out = ResultWriter::new(Vec::new(), format);
+      out.header(&["id", "val"]).unwrap();
+      assert!(out.row(&[&3_u64, &Failing]).is_err());
+      let text = String::from_utf8(out.finish().unwrap()).unwrap();
+      assert_eq!(text.lines().count(), (format == OutputFormat::Csv) as usize);
+    }
+    assert!(write_rows(OutputFormat::Bin, true).is_err());
+  }
+
+  #[test]
+  fn testok_bin_rows() {
+    let str_rw = StrRW { n_bytes: 4 };
+    let mut out = ResultWriter::new(Vec::new(), OutputFormat::Bin);
+    out.header(&["query", "found", "id", "val", "count", "text"]).unwrap();
+    let query = String::from("ab");
+    let row: [&dyn Field; 6] = [
+      &Coded::new(&query, &str_rw),
+      &true,
+      &Coded::new(&0x01020304_u32, &U32RW),
+      &Coded::<u32, U32RW>::null(&U32RW),
+      &5_u64,
+      &"xyz",
+    ];
+    out.row(&row).unwrap();
+    let mut expected = vec![b'a', b'b', 0, 0, 1, 4, 3, 2, 1, 0, 0, 0, 0];
+    expected.extend(5_u64.to_le_bytes());
+    expected.extend(3_u32.to_le_bytes());
+    expected.extend(b"xyz");
+    // No header, no framing
+    assert_eq!(out.finish().unwrap(), expected);
+  }
+}
@@ -0,0 +1,222 @@
+//! Distances between values, used by the nearest neighbour queries.
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::{
+  cmp::Ordering,
+  fmt::{self, Debug, Display, Formatter},
+  io::{Error, ErrorKind, Write},
+  str::FromStr,
+};
+
+use crate::float::FiniteFloat;
+
+/// Trait defining the minimum requirements to be the distance between two values.
+///
+/// The distance has to be consistent with the order of the values: for `a <= b <= c`, we must
+/// have `d(a, b) <= d(a, c)` and `d(b, c) <= d(a, c)`.
+pub trait Dist: FromStr + Ord + Display + Debug + Clone + Send + Sync {
+  /// Writes the distance in binary (little-endian numbers).
+  fn write_bin(&self, writer: &mut dyn Write) -> Result<(), Error>;
+  /// Writes, in binary, the place holder of a missing distance.
+  fn write_null_bin(writer: &mut dyn Write) -> Result<(), Error>;
+}
+
+macro_rules! impl_dist {
+  ($t:ty, $write:ident, $n_bytes:expr) => {
+    impl Dist for $t {
+      fn write_bin(&self, writer: &mut dyn Write) -> Result<(), Error> {
+        writer.$write::<LittleEndian>(*self)
+      }
+      fn write_null_bin(writer: &mut dyn Write) -> Result<(), Error> {
+        writer.write_all(&[0_u8; $n_bytes])
+      }
+    }
+  };
+}
+
+impl_dist!(u32, write_u32, 4);
+impl_dist!(u64, write_u64, 8);
+impl_dist!(i32, write_i32, 4);
+impl_dist!(i64, write_i64, 8);
+
+impl Dist for FiniteFloat<f32> {
+  fn write_bin(&self, writer: &mut dyn Write) -> Result<(), Error> {
+    writer.write_f32::<LittleEndian>(self.get())
+  }
+  fn write_null_bin(writer: &mut dyn Write) -> Result<(), Error> {
+    writer.write_all(&[0_u8; 4])
+  }
+}
+
+impl Dist for FiniteFloat<f64> {
+  fn write_bin(&self, writer: &mut dyn Write) -> Result<(), Error> {
+    writer.write_f64::<LittleEndian>(self.get())
+  }
+  fn write_null_bin(writer: &mut dyn Write) -> Result<(), Error> {
+    writer.write_all(&[0_u8; 8])
+  }
+}
+
+/// Distance between two strings.
+///
+/// Strings are stored in the tree files on a fixed number of bytes (right padded with `\0`).
+/// Seeing those bytes as big-endian unsigned integers, their order is the lexicographic order of
+/// the strings, so the absolute difference between the two integers is a distance consistent
+/// with the order of the strings.
+/// It is displayed (and parsed, e.g. for `d_max`) as a decimal number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StrDist(
+  /// Big-endian bytes of the difference, without leading zeros
+  Vec<u8>,
+);
+
+impl StrDist {
+  /// Distance between the two given strings, both encoded on `n_bytes` bytes like in a tree file
+  /// (i.e. truncated or right padded with `\0`).
+  pub fn new(a: &str, b: &str, n_bytes: usize) -> StrDist {
+    let a = fixed_width_bytes(a, n_bytes);
+    let b = fixed_width_bytes(b, n_bytes);
+    let (hi, lo) = if a >= b { (a, b) } else { (b, a) };
+    // Big-endian subtraction, from the least significant byte
+    let mut diff = vec![0_u8; n_bytes];
+    let mut borrow = 0_i16;
+    for i in (0..n_bytes).rev() {
+      let mut d = hi[i] as i16 - lo[i] as i16 - borrow;
+      borrow = if d < 0 {
+        d += 256;
+        1
+      } else {
+        0
+      };
+      diff[i] = d as u8;
+    }
+    StrDist::from_be_bytes(diff)
+  }
+
+  /// Returns the distance function between strings encoded on `n_bytes` bytes.
+  pub fn distance(n_bytes: usize) -> impl Fn(&String, &String) -> StrDist + Send + Sync {
+    move |a: &String, b: &String| StrDist::new(a, b, n_bytes)
+  }
+
+  fn from_be_bytes(mut bytes: Vec<u8>) -> StrDist {
+    let n_leading_zeros = bytes.iter().take_while(|b| **b == 0).count();
+    bytes.drain(..n_leading_zeros);
+    StrDist(bytes)
+  }
+}
+
+fn fixed_width_bytes(s: &str, n_bytes: usize) -> Vec<u8> {
+  let mut bytes = s.as_bytes().to_vec();
+  bytes.resize(n_bytes, 0);
+  bytes
+}
+
+impl Ord for StrDist {
+  fn cmp(&self, other: &Self) -> Ordering {
+    // No leading zeros: the longest is the largest
+    self
+      .0
+      .len()
+      .cmp(&other.0.len())
+      .then_with(|| self.0.cmp(&other.0))
+  }
+}
+
+impl PartialOrd for StrDist {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Display for StrDist {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    if self.0.is_empty() {
+      return f.write_str("0");
+    }
+    // Successive divisions by 10 of the big-endian integer
+    let mut bytes = self.0.clone();
+    let mut digits = Vec::new();
+    while !bytes.is_empty() {
+      let mut rem = 0_u16;
+      for b in bytes.iter_mut() {
+        let cur = (rem << 8) | *b as u16;
+        *b = (cur / 10) as u8;
+        rem = cur % 10;
+      }
+      digits.push(b'0' + rem as u8);
+      let n_leading_zeros = bytes.iter().take_while(|b| **b == 0).count();
+      bytes.drain(..n_leading_zeros);
+    }
+    digits.reverse();
+    f.write_str(std::str::from_utf8(&digits).unwrap())
+  }
+}
+
+impl FromStr for StrDist {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s.is_empty() || !s.bytes().all(|c| c.is_ascii_digit()) {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("Wrong string distance '{}': must be a positive integer", s),
+      ));
+    }
+    // Successive multiplications by 10 of the big-endian integer
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.bytes() {
+      let mut carry = (c - b'0') as u16;
+      for b in bytes.iter_mut().rev() {
+        let cur = *b as u16 * 10 + carry;
+        *b = cur as u8;
+        carry = cur >> 8;
+      }
+      if carry > 0 {
+        bytes.insert(0, carry as u8);
+      }
+    }
+    Ok(StrDist::from_be_bytes(bytes))
+  }
+}
+
+impl Dist for StrDist {
+  /// Written as the `u32` number of bytes followed by the big-endian bytes of the distance.
+  fn write_bin(&self, writer: &mut dyn Write) -> Result<(), Error> {
+    writer.write_u32::<LittleEndian>(self.0.len() as u32)?;
+    writer.write_all(&self.0)
+  }
+  fn write_null_bin(writer: &mut dyn Write) -> Result<(), Error> {
+    writer.write_u32::<LittleEndian>(0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn testok_str_dist() {
+    let d = StrDist::distance(6);
+    let a = String::from("ID0010");
+    assert_eq!(d(&a, &String::from("ID0009")).to_string(), "247");
+    assert_eq!(d(&a, &a).to_string(), "0");
+    assert_eq!(d(&a, &String::from("ID0009")), d(&String::from("ID0009"), &a));
+    // Shorter strings are padded with '\0' (here: 0x44 * 256^4)
+    assert_eq!(d(&String::from("ID"), &String::from("I")).to_string(), "292057776128");
+    assert_eq!("292057776128".parse::<StrDist>().unwrap().to_string(), "292057776128");
+    assert!("12a".parse::<StrDist>().is_err());
+    // Consistency with the order of the strings
+    let mut values: Vec<String> = (0..500_u32)
+      .map(|i| format!("{}{:x}", (b'A' + (i % 7) as u8) as char, i * 37))
+      .collect();
+    values.sort();
+    for (i, v) in values.iter().enumerate() {
+      for w in values[..i].windows(2) {
+        assert!(d(v, &w[0]) >= d(v, &w[1]));
+      }
+      for w in values[i..].windows(2) {
+        assert!(d(v, &w[0]) <= d(v, &w[1]));
+      }
+    }
+  }
+}
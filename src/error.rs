@@ -0,0 +1,111 @@
+//! Errors which may occur when opening or building a tree file.
+
+use std::{
+  error,
+  fmt::{self, Display, Formatter},
+  io::{self, ErrorKind},
+};
+
+use crate::{
+  bstree::{FormatVersion, FORMAT_VERSION},
+  IdVal,
+};
+
+/// Errors specific to tree files.
+///
+/// It can be converted into a `std::io::Error` (the original error being kept as the inner error)
+/// so that it can be propagated with `?` in the functions returning a `std::io::Error`.
+#[derive(Debug)]
+pub enum BSTreeError {
+  /// Error while reading or writing the file
+  Io(io::Error),
+  /// The file does not start with the expected file type
+  BadMagic([u8; 10]),
+  /// The file format version is not supported, i.e. the file has been written by a newer
+  /// version of the code using an incompatible layout, or its explicit version is a legacy one
+  UnsupportedFormatVersion(FormatVersion),
+  /// The file is smaller than the size it should have according to its metadata
+  Truncated { expected: usize, actual: usize },
+  /// The serialized metadata does not fit in the header (its size is stored on 2 bytes)
+  MetaTooLarge { byte_size: usize },
+  /// The metadata can not be deserialized
+  InvalidMeta,
+  /// The (identifier, value) types of the tree are not supported by the compiled code
+  /// (see the crate features)
+  UnsupportedTypes(IdVal),
+  /// The file size is larger than the size it should have according to its metadata
+  /// (`n_entries * entry_byte_size` plus the header, the NULL values and the checksums)
+  FileSizeMismatch { expected: usize, actual: usize },
+}
+
+impl Display for BSTreeError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      BSTreeError::Io(e) => write!(f, "I/O error: {}", e),
+      BSTreeError::BadMagic(file_type) => write!(
+        f,
+        "Not a BSTreeFile: wrong file type {:?}",
+        String::from_utf8_lossy(file_type)
+      ),
+      BSTreeError::UnsupportedFormatVersion(v) if *v < FORMAT_VERSION => write!(
+        f,
+        "Unsupported file format version {}: legacy versions are not stored explicitly",
+        v
+      ),
+      BSTreeError::UnsupportedFormatVersion(v) => write!(
+        f,
+        "Unsupported file format version {} (this code reads up to version {}): \
+         the file has been written by a newer version of the code, please upgrade",
+        v, FORMAT_VERSION
+      ),
+      BSTreeError::Truncated { expected, actual } => write!(
+        f,
+        "Truncated file: size {} lower than the expected one ({})",
+        actual, expected
+      ),
+      BSTreeError::MetaTooLarge { byte_size } => write!(
+        f,
+        "Metadata too large: {} bytes (max: {})",
+        byte_size,
+        u16::MAX
+      ),
+      BSTreeError::InvalidMeta => write!(f, "Unable to deserialize the metadata"),
+      BSTreeError::UnsupportedTypes(types) => write!(
+        f,
+        "Types {:?} not supported! See crate features!!",
+        types
+      ),
+      BSTreeError::FileSizeMismatch { expected, actual } => write!(
+        f,
+        "Wrong file size: {} bytes instead of {}",
+        actual, expected
+      ),
+    }
+  }
+}
+
+impl error::Error for BSTreeError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      BSTreeError::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for BSTreeError {
+  fn from(e: io::Error) -> Self {
+    BSTreeError::Io(e)
+  }
+}
+
+impl From<BSTreeError> for io::Error {
+  fn from(e: BSTreeError) -> Self {
+    match e {
+      BSTreeError::Io(e) => e,
+      BSTreeError::Truncated { .. } => io::Error::new(ErrorKind::UnexpectedEof, e),
+      BSTreeError::UnsupportedTypes(_) => io::Error::new(ErrorKind::Unsupported, e),
+      _ => io::Error::new(ErrorKind::InvalidData, e),
+    }
+  }
+}
@@ -35,24 +35,35 @@ use serde::{Deserialize, Serialize};
 
 use std::{
   cmp::Ordering::{self, Equal, Greater, Less},
-  fmt::{Debug, Display},
-  io::{Cursor, ErrorKind, Read, Write},
+  fmt::{self, Debug, Display, Formatter},
+  io::{Cursor, Read, Write},
   marker::PhantomData,
   str::FromStr,
 };
 
 pub mod bstree;
 pub mod cliargs;
+pub mod dist;
+pub mod error;
 pub mod float;
 pub mod mk;
+pub mod norm;
 pub mod rw;
+pub mod source;
 pub mod visitors;
 
+#[cfg(test)]
+mod testutils;
+
+use dist::Dist;
+use error::BSTreeError;
 use float::FiniteFloat;
 use rw::*;
 
 pub trait FromU64: Sized {
   fn from_u64(s: u64) -> Self;
+  /// Same as `from_u64`, but returns `None` instead of wrapping if the value does not fit
+  fn try_from_u64(s: u64) -> Option<Self>;
   fn to_u64(&self) -> u64;
 }
 
@@ -60,6 +71,9 @@ impl FromU64 for u32 {
   fn from_u64(s: u64) -> Self {
     s as u32
   }
+  fn try_from_u64(s: u64) -> Option<Self> {
+    u32::try_from(s).ok()
+  }
   fn to_u64(&self) -> u64 {
     *self as u64
   }
@@ -69,6 +83,9 @@ impl FromU64 for u64 {
   fn from_u64(s: u64) -> Self {
     s
   }
+  fn try_from_u64(s: u64) -> Option<Self> {
+    Some(s)
+  }
   fn to_u64(&self) -> u64 {
     *self
   }
@@ -78,6 +95,9 @@ impl FromU64 for String {
   fn from_u64(s: u64) -> Self {
     format!("{}", &s)
   }
+  fn try_from_u64(s: u64) -> Option<Self> {
+    Some(Self::from_u64(s))
+  }
   fn to_u64(&self) -> u64 {
     panic!("Can't convert string into u64")
   }
@@ -89,8 +109,8 @@ pub trait Id: FromStr + FromU64 + Display + Debug + Clone + Send {}
 impl<T> Id for T where T: FromStr + FromU64 + Display + Debug + Clone + Send {}
 
 /// Trait defining the minimum requirements to be a value
-pub trait Val: FromStr + Ord + Display + Debug + Clone + Send {}
-impl<T> Val for T where T: FromStr + Ord + Display + Debug + Clone + Send {}
+pub trait Val: FromStr + Ord + Display + Debug + Clone + Send + Sync {}
+impl<T> Val for T where T: FromStr + Ord + Display + Debug + Clone + Send + Sync {}
 
 #[derive(Debug)]
 pub enum IdInMemType {
@@ -165,6 +185,17 @@ impl FromStr for IdType {
   }
 }
 
+impl Display for IdType {
+  /// Writes the identifier type as parsed by `from_str` (e.g. `u4` or `t12`).
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      IdType::Str { n_chars } => write!(f, "t{}", n_chars),
+      IdType::Custom => f.write_str("custom"),
+      _ => write!(f, "u{}", self.byte_size()),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub enum ValInMemType {
   U32,
@@ -258,6 +289,23 @@ impl FromStr for ValType {
   }
 }
 
+impl Display for ValType {
+  /// Writes the value type as parsed by `from_str` (e.g. `i3`, `f8` or `t12`).
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      ValType::U24 | ValType::U32 | ValType::U40 | ValType::U48 | ValType::U56 | ValType::U64 => {
+        write!(f, "u{}", self.byte_size())
+      }
+      ValType::I24 | ValType::I32 | ValType::I40 | ValType::I48 | ValType::I56 | ValType::I64 => {
+        write!(f, "i{}", self.byte_size())
+      }
+      ValType::F32 | ValType::F64 => write!(f, "f{}", self.byte_size()),
+      ValType::Str { n_chars } => write!(f, "t{}", n_chars),
+      ValType::Custom => f.write_str("custom"),
+    }
+  }
+}
+
 /// Defines an action which has to read and/or write given identifier and value types.
 /// It is made to be used with the `IdVal` type.
 /// The reason behind is that `IdVal` will contains the giant `match` for all possible
@@ -265,7 +313,8 @@ impl FromStr for ValType {
 pub trait Process {
   type Output;
 
-  fn exec<I, V, D, IRW, VRW>(
+  /// `dist` is the distance between two values (used by the nearest neighbour queries).
+  fn exec<I, V, U, D, IRW, VRW>(
     self,
     types: IdVal,
     id_rw: IRW,
@@ -275,7 +324,8 @@ pub trait Process {
   where
     I: 'static + Id,
     V: 'static + Val,
-    D: 'static + Fn(&V, &V) -> V + Send,
+    U: 'static + Dist,
+    D: 'static + Fn(&V, &V) -> U + Send + Sync,
     IRW: 'static + ReadWrite<Type = I>,
     VRW: 'static + ReadWrite<Type = V>;
 }
@@ -428,7 +478,7 @@ impl IdVal {
         self.clone(),
         U24RW,
         StrRW { n_bytes: *n_chars },
-        |_a: &String, _b: &String| panic!("Distance not implemented for Strings"),
+        dist::StrDist::distance(*n_chars),
       ),
 
       // IdType U32, ValType: All
@@ -530,7 +580,7 @@ impl IdVal {
         self.clone(),
         U32RW,
         StrRW { n_bytes: *n_chars },
-        |_a: &String, _b: &String| panic!("Distance not implemented for Strings"),
+        dist::StrDist::distance(*n_chars),
       ),
 
       // IdType U40, ValType: All
@@ -632,7 +682,7 @@ impl IdVal {
         self.clone(),
         U40RW,
         StrRW { n_bytes: *n_chars },
-        |_a: &String, _b: &String| panic!("Distance not implemented for Strings"),
+        dist::StrDist::distance(*n_chars),
       ),
 
       // IdType U48, ValType: All
@@ -734,7 +784,7 @@ impl IdVal {
         self.clone(),
         U48RW,
         StrRW { n_bytes: *n_chars },
-        |_a: &String, _b: &String| panic!("Distance not implemented for Strings"),
+        dist::StrDist::distance(*n_chars),
       ),
 
       // IdType U56, ValType: All
@@ -836,7 +886,7 @@ impl IdVal {
         self.clone(),
         U56RW,
         StrRW { n_bytes: *n_chars },
-        |_a: &String, _b: &String| panic!("Distance not implemented for Strings"),
+        dist::StrDist::distance(*n_chars),
       ),
 
       // IdType U64, ValType: All
@@ -938,7 +988,7 @@ impl IdVal {
         self.clone(),
         U64RW,
         StrRW { n_bytes: *n_chars },
-        |_a: &String, _b: &String| panic!("Distance not implemented for Strings"),
+        dist::StrDist::distance(*n_chars),
       ),
 
       // IdType Str, ValType: All
@@ -1056,15 +1106,120 @@ impl IdVal {
         StrRW {
           n_bytes: *n_chars_v,
         },
-        |_a: &String, _b: &String| panic!("Distance not implemented for Strings"),
+        dist::StrDist::distance(*n_chars_v),
       ),
 
-      _ => Err(std::io::Error::new(
-        ErrorKind::Other,
-        "Case not supported! See crate features!!",
-      )),
+      _ => Err(BSTreeError::UnsupportedTypes(self.clone()).into()),
+    }
+  }
+
+  /// Alternative to `exec`, monomorphised only on the in-memory types (`IdInMemType` x
+  /// `ValInMemType`), the number of bytes of the integers being handled at runtime.
+  /// * pro: all types are supported whatever the crate features, for a small compiled code
+  /// * con: slower than `exec` since the integer sizes are not known at compile time
+  pub fn exec_in_mem<P>(&self, p: P) -> Result<P::Output, std::io::Error>
+  where
+    P: Process,
+  {
+    match (&self.0, &self.1) {
+      (IdType::Custom, _) | (_, ValType::Custom) => {
+        Err(BSTreeError::UnsupportedTypes(self.clone()).into())
+      }
+      (id_type, _) => {
+        let n_bytes = id_type.byte_size();
+        match id_type.in_mem_type() {
+          IdInMemType::U32 => self.exec_in_mem_val(p, VarU32RW { n_bytes }),
+          IdInMemType::U64 => self.exec_in_mem_val(p, VarU64RW { n_bytes }),
+          IdInMemType::Str { n_chars } => self.exec_in_mem_val(p, StrRW { n_bytes: n_chars }),
+        }
+      }
+    }
+  }
+
+  fn exec_in_mem_val<P, I, IRW>(&self, p: P, id_rw: IRW) -> Result<P::Output, std::io::Error>
+  where
+    P: Process,
+    I: 'static + Id,
+    IRW: 'static + ReadWrite<Type = I>,
+  {
+    let n_bytes = self.1.byte_size();
+    match self.1.in_mem_type() {
+      ValInMemType::U32 => p.exec(self.clone(), id_rw, VarU32RW { n_bytes }, |a: &u32, b: &u32| {
+        a.abs_diff(*b)
+      }),
+      ValInMemType::U64 => p.exec(self.clone(), id_rw, VarU64RW { n_bytes }, |a: &u64, b: &u64| {
+        a.abs_diff(*b)
+      }),
+      // Unsigned distances, not to overflow on e.g. `i32::MAX - i32::MIN`
+      ValInMemType::I32 => p.exec(self.clone(), id_rw, VarI32RW { n_bytes }, |a: &i32, b: &i32| {
+        a.abs_diff(*b)
+      }),
+      ValInMemType::I64 => p.exec(self.clone(), id_rw, VarI64RW { n_bytes }, |a: &i64, b: &i64| {
+        a.abs_diff(*b)
+      }),
+      // The difference of two finite floats may be infinite: it is saturated to MAX
+      ValInMemType::F32 => p.exec(
+        self.clone(),
+        id_rw,
+        F32RW,
+        |a: &FiniteFloat<f32>, b: &FiniteFloat<f32>| {
+          FiniteFloat::new((a.get() - b.get()).abs().min(f32::MAX)).unwrap()
+        },
+      ),
+      ValInMemType::F64 => p.exec(
+        self.clone(),
+        id_rw,
+        F64RW,
+        |a: &FiniteFloat<f64>, b: &FiniteFloat<f64>| {
+          FiniteFloat::new((a.get() - b.get()).abs().min(f64::MAX)).unwrap()
+        },
+      ),
+      ValInMemType::Str { n_chars } => p.exec(
+        self.clone(),
+        id_rw,
+        StrRW { n_bytes: n_chars },
+        dist::StrDist::distance(n_chars),
+      ),
     }
   }
+
+  /// Uses `exec` if the types are supported by the compiled code, else `exec_in_mem`.
+  pub fn exec_any<P>(&self, p: P) -> Result<P::Output, std::io::Error>
+  where
+    P: Process,
+  {
+    if self.is_supported() {
+      self.exec(p)
+    } else {
+      self.exec_in_mem(p)
+    }
+  }
+
+  /// Returns `true` if the (identifier, value) types are supported by `exec_in_mem` (i.e. if
+  /// they are not custom types).
+  pub fn is_in_mem_supported(&self) -> bool {
+    !matches!(self.0, IdType::Custom) && !matches!(self.1, ValType::Custom)
+  }
+
+  /// Returns `true` if the (identifier, value) types are supported by the compiled code
+  /// (see the crate features).
+  pub fn is_supported(&self) -> bool {
+    struct NoOp;
+    impl Process for NoOp {
+      type Output = ();
+
+      fn exec<I, V, U, D, IRW, VRW>(
+        self,
+        _types: IdVal,
+        _id_rw: IRW,
+        _val_rw: VRW,
+        _dist: D,
+      ) -> Result<Self::Output, std::io::Error> {
+        Ok(())
+      }
+    }
+    self.exec(NoOp).is_ok()
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -1172,6 +1327,54 @@ where
   }
 }
 
+/// Layout of the entries inside a page, i.e. inside an array of entries searched by bisection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PageLayout {
+  /// `id|val` records, one after the other
+  #[default]
+  Interleaved,
+  /// All the values stored contiguously, followed by all the identifiers (in the same order),
+  /// so that a search in the page reads only value bytes
+  Columnar,
+}
+
+impl PageLayout {
+  /// Names of the layouts accepted by `from_str`.
+  pub const NAMES: [&'static str; 2] = ["interleaved", "columnar"];
+}
+
+impl FromStr for PageLayout {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "interleaved" => Ok(PageLayout::Interleaved),
+      "columnar" => Ok(PageLayout::Columnar),
+      _ => Err(format!(
+        "Unknown page layout '{}'. Possible values: {}",
+        s,
+        PageLayout::NAMES.join(", ")
+      )),
+    }
+  }
+}
+
+impl Display for PageLayout {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      PageLayout::Interleaved => "interleaved",
+      PageLayout::Columnar => "columnar",
+    })
+  }
+}
+
+/// Value searched in raw entries, together with its bytes to be compared with `memcmp` to the
+/// raw bytes of the values, if the codec provides them (see `ReadWrite::memcmp_key`).
+struct SearchKey<'v, V> {
+  val: &'v V,
+  raw: Option<Vec<u8>>,
+}
+
 pub struct RawEntries<'a, I, V, IRW, VRW>
 where
   I: Id,
@@ -1179,9 +1382,10 @@ where
   IRW: ReadWrite<Type = I>,
   VRW: ReadWrite<Type = V>,
 {
-  raw: Cursor<&'a [u8]>,
+  raw: &'a [u8],
   id_rw: &'a IRW,
   val_rw: &'a VRW,
+  layout: PageLayout,
   entry_byte_size: usize,
   n_entries: usize,
 }
@@ -1194,13 +1398,24 @@ where
   VRW: ReadWrite<Type = V>,
 {
   pub fn new(raw: &'a [u8], id_rw: &'a IRW, val_rw: &'a VRW) -> RawEntries<'a, I, V, IRW, VRW> {
+    RawEntries::with_layout(raw, id_rw, val_rw, PageLayout::Interleaved)
+  }
+
+  /// Same as [new](#method.new), for entries stored with the given layout.
+  pub fn with_layout(
+    raw: &'a [u8],
+    id_rw: &'a IRW,
+    val_rw: &'a VRW,
+    layout: PageLayout,
+  ) -> RawEntries<'a, I, V, IRW, VRW> {
     assert!(!raw.is_empty());
     let entry_byte_size = id_rw.n_bytes() + val_rw.n_bytes();
     let n_entries = raw.len() / entry_byte_size;
     RawEntries {
-      raw: Cursor::new(raw),
+      raw,
       id_rw,
       val_rw,
+      layout,
       entry_byte_size,
       n_entries,
     }
@@ -1211,22 +1426,55 @@ where
     self.n_entries
   }
 
-  // For better performances, have a look at raw pointers!!
-  fn get_val(&mut self, index: usize) -> Result<V, std::io::Error> {
-    let pos = (self.entry_byte_size * index + self.id_rw.n_bytes()) as u64;
-    self.raw.set_position(pos);
-    self.val_rw.read(&mut self.raw)
+  /// Returns the raw bytes of the value of the entry at the given index.
+  fn raw_val(&self, index: usize) -> &'a [u8] {
+    let from = match self.layout {
+      PageLayout::Interleaved => self.entry_byte_size * index + self.id_rw.n_bytes(),
+      PageLayout::Columnar => self.val_rw.n_bytes() * index,
+    };
+    &self.raw[from..from + self.val_rw.n_bytes()]
+  }
+
+  /// Returns the raw bytes of the identifier of the entry at the given index.
+  fn raw_id(&self, index: usize) -> &'a [u8] {
+    let from = match self.layout {
+      PageLayout::Interleaved => self.entry_byte_size * index,
+      PageLayout::Columnar => {
+        self.val_rw.n_bytes() * self.n_entries + self.id_rw.n_bytes() * index
+      }
+    };
+    &self.raw[from..from + self.id_rw.n_bytes()]
   }
 
-  // For better performances, have a look at raw pointers!!
-  fn get_entry(&mut self, index: usize) -> Result<Entry<I, V>, std::io::Error> {
-    self.raw.set_position((self.entry_byte_size * index) as u64);
-    Entry::read(&mut self.raw, self.id_rw, self.val_rw)
+  fn get_val(&self, index: usize) -> Result<V, std::io::Error> {
+    self.val_rw.read(&mut self.raw_val(index))
   }
 
-  pub fn binary_search(&mut self, val: &V) -> Result<Result<usize, usize>, std::io::Error> {
+  /// Prepares the given value to be compared with the values of the entries.
+  fn search_key<'v>(&self, val: &'v V) -> Result<SearchKey<'v, V>, std::io::Error> {
+    self
+      .val_rw
+      .memcmp_key(val)
+      .map(|raw| SearchKey { val, raw })
+  }
+
+  /// Compares the value of the entry at the given index with the given value, without reading it.
+  fn cmp_val(&self, index: usize, key: &SearchKey<V>) -> Result<Ordering, std::io::Error> {
+    match &key.raw {
+      Some(raw) => Ok(self.raw_val(index).cmp(raw)),
+      None => self.val_rw.cmp_raw(self.raw_val(index), key.val),
+    }
+  }
+
+  fn get_entry(&self, index: usize) -> Result<Entry<I, V>, std::io::Error> {
+    let id = self.id_rw.read(&mut self.raw_id(index))?;
+    self.get_val(index).map(|val| Entry::new(id, val))
+  }
+
+  pub fn binary_search(&self, val: &V) -> Result<Result<usize, usize>, std::io::Error> {
     // Code taken from Rust slice binary_search:
     // https://doc.rust-lang.org/src/core/slice/mod.rs.html#1470-1474
+    let key = self.search_key(val)?;
     let mut size = self.n_entries();
     let mut base = 0_usize;
     while size > 1 {
@@ -1235,14 +1483,12 @@ where
       // mid is always in [0, size), that means mid is >= 0 and < size.
       // mid >= 0: by definition
       // mid < size: mid = size / 2 + size / 4 + size / 8 ...
-      let cur_val = self.get_val(mid)?;
-      let cmp = cur_val.cmp(val);
+      let cmp = self.cmp_val(mid, &key)?;
       base = if cmp == Greater { base } else { mid };
       size -= half;
     }
     // base is always in [0, size) because base <= mid.
-    self.get_val(base).map(|v| {
-      let cmp = v.cmp(val);
+    self.cmp_val(base, &key).map(|cmp| {
       if cmp == Equal {
         Ok(base)
       } else {
@@ -1250,9 +1496,197 @@ where
       }
     })
   }
+
+  /// Returns the index of the first entry for which the given predicate is `false`,
+  /// assuming the predicate is `true` for all entries before it and `false` for all entries
+  /// after it (like `slice::partition_point`).
+  pub fn partition_point<P>(&self, pred: P) -> Result<usize, std::io::Error>
+  where
+    P: Fn(&V) -> bool,
+  {
+    let mut size = self.n_entries();
+    let mut base = 0_usize;
+    while size > 0 {
+      let half = size >> 1;
+      let mid = base + half;
+      if pred(&self.get_val(mid)?) {
+        base = mid + 1;
+        size -= half + 1;
+      } else {
+        size = half;
+      }
+    }
+    Ok(base)
+  }
+}
+
+/// Writes, one after the other and in increasing index order, the entries of a page having
+/// the given layout (the counterpart of [RawEntries](struct.RawEntries.html)).
+pub struct RawEntriesWriter<'a, I, V, IRW, VRW>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  raw: &'a mut [u8],
+  id_rw: &'a IRW,
+  val_rw: &'a VRW,
+  layout: PageLayout,
+  n_entries: usize,
+  /// Index of the next entry to be written
+  index: usize,
+  _types: PhantomData<(I, V)>,
+}
+
+impl<'a, I, V, IRW, VRW> RawEntriesWriter<'a, I, V, IRW, VRW>
+where
+  I: Id,
+  V: Val,
+  IRW: ReadWrite<Type = I>,
+  VRW: ReadWrite<Type = V>,
+{
+  pub fn new(
+    raw: &'a mut [u8],
+    id_rw: &'a IRW,
+    val_rw: &'a VRW,
+    layout: PageLayout,
+  ) -> RawEntriesWriter<'a, I, V, IRW, VRW> {
+    let n_entries = raw.len() / (id_rw.n_bytes() + val_rw.n_bytes());
+    RawEntriesWriter {
+      raw,
+      id_rw,
+      val_rw,
+      layout,
+      n_entries,
+      index: 0,
+      _types: PhantomData,
+    }
+  }
+
+  /// Writes the given entry at the next index of the page.
+  pub fn push(&mut self, entry: Entry<I, V>) -> Result<(), std::io::Error> {
+    if self.is_full() {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "Page already full!",
+      ));
+    }
+    let (id_n_bytes, val_n_bytes) = (self.id_rw.n_bytes(), self.val_rw.n_bytes());
+    match self.layout {
+      PageLayout::Interleaved => {
+        let from = (id_n_bytes + val_n_bytes) * self.index;
+        entry.write(&mut &mut self.raw[from..], self.id_rw, self.val_rw)?;
+      }
+      PageLayout::Columnar => {
+        let val_from = val_n_bytes * self.index;
+        let id_from = val_n_bytes * self.n_entries + id_n_bytes * self.index;
+        self
+          .val_rw
+          .write(&mut &mut self.raw[val_from..val_from + val_n_bytes], &entry.val)?;
+        self
+          .id_rw
+          .write(&mut &mut self.raw[id_from..id_from + id_n_bytes], &entry.id)?;
+      }
+    }
+    self.index += 1;
+    Ok(())
+  }
+
+  /// Returns `true` if all the entries of the page have been written.
+  pub fn is_full(&self) -> bool {
+    self.index == self.n_entries
+  }
 }
 
 // datastruct:
 // - meta
-// - null values block (only identifiers, sequentially ordered by `id`)
+// - null values block (only identifiers, sequentially in the input order, i.e. ordered by `id` for recnos)
 // - values blocks key,val pairs (ordered by `val` blocks)
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn testok_exec_in_mem() {
+    // Same bytes as the codecs having a size known at compile time
+    fn assert_same<T, A: ReadWrite<Type = T>, B: ReadWrite<Type = T>>(a: &A, b: &B, val: T)
+    where
+      T: PartialEq + Debug,
+    {
+      let (mut buff_a, mut buff_b) = (Vec::new(), Vec::new());
+      a.write(&mut buff_a, &val).unwrap();
+      b.write(&mut buff_b, &val).unwrap();
+      assert_eq!(buff_a, buff_b);
+      assert_eq!(b.read(&mut buff_a.as_slice()).unwrap(), val);
+    }
+    assert_same(&U24RW, &VarU32RW { n_bytes: 3 }, (1 << 24) - 1);
+    assert_same(&U64RW, &VarU64RW { n_bytes: 8 }, u64::MAX);
+    assert_same(&I40RW, &VarI64RW { n_bytes: 5 }, -(1 << 39));
+    assert!(!VarU32RW { n_bytes: 3 }.fits(&(1 << 24)));
+    assert!(!VarI32RW { n_bytes: 3 }.fits(&(1 << 23)));
+    assert!(VarI32RW { n_bytes: 4 }.fits(&i32::MIN));
+    // All types but custom ones are supported, whatever the crate features
+    struct NBytes;
+    impl Process for NBytes {
+      type Output = (usize, usize);
+      fn exec<I, V, U, D, IRW, VRW>(
+        self,
+        _types: IdVal,
+        id_rw: IRW,
+        val_rw: VRW,
+        _dist: D,
+      ) -> Result<Self::Output, std::io::Error>
+      where
+        IRW: ReadWrite<Type = I>,
+        VRW: ReadWrite<Type = V>,
+      {
+        Ok((id_rw.n_bytes(), val_rw.n_bytes()))
+      }
+    }
+    let types = IdVal::new(IdType::U40, ValType::I24);
+    assert!(types.is_in_mem_supported());
+    assert_eq!(types.exec_in_mem(NBytes).unwrap(), (5, 3));
+    assert_eq!(types.exec_any(NBytes).unwrap(), (5, 3));
+    let types = IdVal::new(IdType::U32, ValType::Custom);
+    assert!(!types.is_in_mem_supported());
+    assert!(types.exec_in_mem(NBytes).is_err());
+    // The distance between the extreme values neither overflows nor panics
+    struct MaxDist(String, String);
+    impl Process for MaxDist {
+      type Output = String;
+      fn exec<I, V, U, D, IRW, VRW>(
+        self,
+        _types: IdVal,
+        _id_rw: IRW,
+        _val_rw: VRW,
+        dist: D,
+      ) -> Result<Self::Output, std::io::Error>
+      where
+        V: Val,
+        U: Dist,
+        D: Fn(&V, &V) -> U,
+      {
+        let parse = |s: &str| {
+          s.parse::<V>()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Wrong value"))
+        };
+        Ok(dist(&parse(&self.0)?, &parse(&self.1)?).to_string())
+      }
+    }
+    let max_dist = |val_type: ValType, min: String, max: String| {
+      IdVal::new(IdType::U32, val_type)
+        .exec_in_mem(MaxDist(min, max))
+        .unwrap()
+    };
+    let (min, max) = (i32::MIN.to_string(), i32::MAX.to_string());
+    assert_eq!(max_dist(ValType::I32, min, max), u32::MAX.to_string());
+    let (min, max) = (i64::MIN.to_string(), i64::MAX.to_string());
+    assert_eq!(max_dist(ValType::I64, min, max), u64::MAX.to_string());
+    let (min, max) = ((-f32::MAX).to_string(), f32::MAX.to_string());
+    assert_eq!(max_dist(ValType::F32, min, max), f32::MAX.to_string());
+    let (min, max) = ((-f64::MAX).to_string(), f64::MAX.to_string());
+    assert_eq!(max_dist(ValType::F64, min, max), f64::MAX.to_string());
+  }
+}
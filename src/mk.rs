@@ -1,23 +1,28 @@
 //! This module contains the main code able to build and store in a file a bs-tree.
 
 use std::{
+  borrow::Cow,
+  cell::Cell,
+  fmt::Display,
   io::{Error, ErrorKind, Read},
   str::FromStr,
 };
 
 use csv::{Reader, StringRecord};
 use itertools::Itertools;
-use log::{debug, info, warn};
+use log::{info, warn};
 
 use crate::{
   bstree,
   cliargs::{
-    colargs::ColIndices,
+    colargs::{ColArgs, ColIndices},
+    csvargs::FnUsingReader,
     memsize::MemSizeArgs,
-    mkargs::{MkAlgoArgs, TmpDir},
+    mkargs::{ChunkWriter, MkAlgoArgs, TmpDir, TmpNullsFile, Truncation},
   },
-  rw::ReadWrite,
-  Entry, EntryOpt, Id, IdVal, Process, Val,
+  norm::StrNorm,
+  rw::{truncate_str, ReadWrite},
+  Entry, EntryOpt, FromU64, Id, IdType, IdVal, Process, Val, ValType,
 };
 
 // See cds.index.general.impl.bstree.BSTreeFile
@@ -65,73 +70,124 @@ where
   ) -> Result<<Self as Process>::Output, std::io::Error>
   //Self::Output
   where
-    I: Id,                    // Identifier type (from u64 if recno option)
-    V: Val,                   // Value type (that is comparable)
-    IRW: ReadWrite<Type = I>, // Object able to read/write an identifier
-    VRW: ReadWrite<Type = V>, // Object able to read/write a value
+    I: 'static + Id,                    // Identifier type (from u64 if recno option)
+    V: 'static + Val,                   // Value type (that is comparable)
+    IRW: 'static + ReadWrite<Type = I>, // Object able to read/write an identifier
+    VRW: 'static + ReadWrite<Type = V>, // Object able to read/write a value
     P: Fn(usize, &StringRecord) -> Result<Entry<I, V>, Error>,
   {
     let to_io_err = From::from;
     let mut tmp_dir = self.args.get_tmp_dir()?;
+    let mut chunk_writer = ChunkWriter::new(self.args.threads, id_rw, val_rw);
     let mut count = 0_usize;
     // Create all tmp files
     for chunk in &self
       .reader
       .records()
       .enumerate()
-      .chunks(self.args.chunk_size)
+      .chunks(self.args.thread_chunk_size())
     {
-      let mut entries: Vec<Entry<I, V>> = chunk
+      let entries: Vec<Entry<I, V>> = chunk
         .map(|(i, rec_res)| {
           rec_res
             .map_err(to_io_err)
             .and_then(|rec| csv2entry(i, &rec))
         })
         .collect::<Result<_, Error>>()?;
-      entries.sort_unstable();
       count += entries.len();
-      tmp_dir.write_tmp_file(id_rw, val_rw, entries)?;
-      eprint!("\r\x1b[2K - n rows parsed and written: {}", &count);
+      chunk_writer.write(&mut tmp_dir, entries)?;
+      eprint!("\r\x1b[2K - n rows parsed: {}", &count);
     }
+    chunk_writer.finish()?;
     info!("Reduce to max {} files be merge sort...", self.args.kway);
-    tmp_dir = tmp_dir.reduce_to_k_files(id_rw, val_rw, self.args.kway)?;
+    tmp_dir = tmp_dir.par_reduce_to_k_files(id_rw, val_rw, self.args.kway, self.args.threads)?;
     info!("Re-read all tmp files to build the final bstree file...");
     let sorted_entry_iter = tmp_dir.to_sorted_iter(id_rw, val_rw);
     #[cfg(not(target_arch = "wasm32"))]
-    bstree::build(
+    bstree::build_with_options(
       self.args.get_output(),
       &self.mem_args,
       count,
       sorted_entry_iter,
+      std::iter::empty(),
       types,
       id_rw,
       val_rw,
+      &self.args.build_options(),
     )?;
     Ok(count)
   }
 
   fn mk_with_null<I, V, IRW, VRW, P>(
-    self,
-    _types: &IdVal,
-    _id_rw: &IRW,
-    _val_rw: &VRW,
-    _csv2entry: P,
+    mut self,
+    types: &IdVal,
+    id_rw: &IRW,
+    val_rw: &VRW,
+    csv2entry: P,
   ) -> Result<<Self as Process>::Output, Error>
   where
-    I: Id,
-    V: Val,
-    IRW: ReadWrite<Type = I>,
-    VRW: ReadWrite<Type = V>,
+    I: 'static + Id,
+    V: 'static + Val,
+    IRW: 'static + ReadWrite<Type = I>,
+    VRW: 'static + ReadWrite<Type = V>,
     P: Fn(usize, &StringRecord) -> Result<EntryOpt<I, V>, Error>,
   {
-    todo!()
+    let to_io_err = From::from;
+    let mut tmp_dir = self.args.get_tmp_dir()?;
+    // Declared after `tmp_dir` to be dropped (i.e. removed) before it
+    let mut nulls = TmpNullsFile::new(&self.args.temp)?;
+    let mut chunk_writer = ChunkWriter::new(self.args.threads, id_rw, val_rw);
+    let mut count = 0_usize;
+    // Create all tmp files, the NULL values being written in a separate file
+    for chunk in &self
+      .reader
+      .records()
+      .enumerate()
+      .chunks(self.args.thread_chunk_size())
+    {
+      let mut entries: Vec<Entry<I, V>> = Vec::with_capacity(self.args.thread_chunk_size());
+      for (i, rec_res) in chunk {
+        let EntryOpt { id, val } = rec_res
+          .map_err(to_io_err)
+          .and_then(|rec| csv2entry(i, &rec))?;
+        match val {
+          Some(val) => entries.push(Entry::new(id, val)),
+          None => nulls.append(id_rw, &id)?,
+        }
+      }
+      count += entries.len();
+      chunk_writer.write(&mut tmp_dir, entries)?;
+      eprint!(
+        "\r\x1b[2K - n rows parsed: {}; n NULL values: {}",
+        &count,
+        nulls.n_nulls()
+      );
+    }
+    chunk_writer.finish()?;
+    info!("Reduce to max {} files be merge sort...", self.args.kway);
+    tmp_dir = tmp_dir.par_reduce_to_k_files(id_rw, val_rw, self.args.kway, self.args.threads)?;
+    info!("Re-read all tmp files to build the final bstree file...");
+    let sorted_entry_iter = tmp_dir.to_sorted_iter(id_rw, val_rw);
+    #[cfg(not(target_arch = "wasm32"))]
+    bstree::build_with_options(
+      self.args.get_output(),
+      &self.mem_args,
+      count,
+      sorted_entry_iter,
+      nulls.to_iter(id_rw)?,
+      types,
+      id_rw,
+      val_rw,
+      &self.args.build_options(),
+    )?;
+    Ok(count)
   }
 }
 
 impl<R: Read> Process for MkIndex<R> {
   type Output = usize;
 
-  fn exec<I, V, D, IRW, VRW>(
+  fn exec<I, V, U, D, IRW, VRW>(
     self,
     types: IdVal,
     id_rw: IRW,
@@ -139,58 +195,496 @@ impl<R: Read> Process for MkIndex<R> {
     _dist: D,
   ) -> Result<Self::Output, Error>
   where
-    I: Id,
-    V: Val,
-    D: Fn(&V, &V) -> V,
-    IRW: ReadWrite<Type = I>,
-    VRW: ReadWrite<Type = V>,
+    I: 'static + Id,
+    V: 'static + Val,
+    D: Fn(&V, &V) -> U,
+    IRW: 'static + ReadWrite<Type = I>,
+    VRW: 'static + ReadWrite<Type = V>,
   {
+    check_norm(&self.args.normalize, &val_rw)?;
     info!("Parse CSV and write tmp files...");
-    let i_val = self.col_indices.val;
-    if self.supports_null {
-      match self.col_indices.id {
+    let truncation = self.args.truncation;
+    // Identifiers are never normalised
+    let id_col = self
+      .col_indices
+      .id
+      .map(|i_id| Col::new("id", i_id, StrNorm::default(), truncation, &id_rw));
+    let val_col = Col::new(
+      "value",
+      self.col_indices.val,
+      self.args.normalize,
+      truncation,
+      &val_rw,
+    );
+    let res = if self.supports_null {
+      match &id_col {
         None => self.mk_with_null(&types, &id_rw, &val_rw, |i, csv_row| {
           Ok(EntryOpt {
-            id: I::from_u64(i as u64),
-            val: get::<V>(csv_row, i_val, "value"),
+            id: recno(i, &id_rw)?,
+            val: get(csv_row, &val_col, &val_rw)?,
           })
         }),
-        Some(i_id) => self.mk_with_null(&types, &id_rw, &val_rw, |_, csv_row| {
+        Some(id_col) => self.mk_with_null(&types, &id_rw, &val_rw, |_, csv_row| {
           Ok(EntryOpt {
-            id: get_with_err::<I>(csv_row, i_id, "id")?,
-            val: get::<V>(csv_row, i_val, "value"),
+            id: get_with_err(csv_row, id_col, &id_rw)?,
+            val: get(csv_row, &val_col, &val_rw)?,
           })
         }),
       }
     } else {
-      match self.col_indices.id {
+      match &id_col {
         None => self.mk_no_null(&types, &id_rw, &val_rw, |i, csv_row| {
           Ok(Entry {
-            id: I::from_u64(i as u64),
-            val: get_with_err::<V>(csv_row, i_val, "value")?,
+            id: recno(i, &id_rw)?,
+            val: get_with_err(csv_row, &val_col, &val_rw)?,
           })
         }),
-        Some(i_id) => self.mk_no_null(&types, &id_rw, &val_rw, |_, csv_row| {
+        Some(id_col) => self.mk_no_null(&types, &id_rw, &val_rw, |_, csv_row| {
           Ok(Entry {
-            id: get_with_err::<I>(csv_row, i_id, "id")?,
-            val: get_with_err::<V>(csv_row, i_val, "value")?,
+            id: get_with_err(csv_row, id_col, &id_rw)?,
+            val: get_with_err(csv_row, &val_col, &val_rw)?,
           })
         }),
       }
+    };
+    if let Some(id_col) = &id_col {
+      id_col.report_truncated();
     }
+    val_col.report_truncated();
+    res
   }
 }
 
-fn get<F: FromStr>(record: &StringRecord, index: usize, col_name: &'static str) -> Option<F> {
-  let res = record.get(index);
-  match res {
+/// Pre-scan of a CSV input returning the number of bytes of the longest identifier and of the
+/// longest (normalised) value, to set the width of string columns.
+pub struct StrByteSizeScan<'a> {
+  col_args: &'a ColArgs,
+  norm: StrNorm,
+}
+
+impl<'a> StrByteSizeScan<'a> {
+  pub fn new(col_args: &'a ColArgs, norm: StrNorm) -> Self {
+    StrByteSizeScan { col_args, norm }
+  }
+}
+
+impl FnUsingReader for StrByteSizeScan<'_> {
+  /// Number of bytes of the longest identifier (0 if no identifier column) and of the longest value
+  type Output = (usize, usize);
+
+  fn call<R: Read>(self, mut reader: Reader<R>) -> Result<Self::Output, Error> {
+    let header = if reader.has_headers() {
+      Some(reader.headers()?.clone())
+    } else {
+      None
+    };
+    let col_indices = self.col_args.get_col_indices(&header.as_ref())?;
+    let (mut id_max, mut val_max) = (0, 0);
+    for record in reader.records() {
+      let record = record?;
+      if let Some(id) = col_indices.id.and_then(|i| record.get(i)) {
+        id_max = id_max.max(id.len());
+      }
+      if let Some(val) = record.get(col_indices.val) {
+        val_max = val_max.max(self.norm.apply(val).len());
+      }
+    }
+    Ok((id_max, val_max))
+  }
+}
+
+/// Pre-scan of a CSV input inferring the narrowest identifier and value types, the types given
+/// explicitly being kept.
+/// The inferred types may not be supported by the compiled code (see the crate features): they
+/// are then handled by `IdVal::exec_any`.
+pub struct TypeInference<'a> {
+  col_args: &'a ColArgs,
+  norm: StrNorm,
+  id_type: Option<IdType>,
+  val_type: Option<ValType>,
+}
+
+impl<'a> TypeInference<'a> {
+  pub fn new(
+    col_args: &'a ColArgs,
+    norm: StrNorm,
+    id_type: Option<IdType>,
+    val_type: Option<ValType>,
+  ) -> Self {
+    TypeInference {
+      col_args,
+      norm,
+      id_type,
+      val_type,
+    }
+  }
+}
+
+impl FnUsingReader for TypeInference<'_> {
+  type Output = IdVal;
+
+  fn call<R: Read>(self, mut reader: Reader<R>) -> Result<Self::Output, Error> {
+    let header = if reader.has_headers() {
+      Some(reader.headers()?.clone())
+    } else {
+      None
+    };
+    let col_indices = self.col_args.get_col_indices(&header.as_ref())?;
+    let mut n_rows = 0_u64;
+    let (mut id_stats, mut val_stats) = (ColStats::new(), ColStats::new());
+    for record in reader.records() {
+      let record = record?;
+      n_rows += 1;
+      if let Some(id) = col_indices.id.and_then(|i| record.get(i)) {
+        id_stats.add(id);
+      }
+      if let Some(val) = record.get(col_indices.val) {
+        val_stats.add(&self.norm.apply(val));
+      }
+    }
+    let id_types = match (self.id_type, col_indices.id) {
+      (Some(id_type), _) => vec![id_type],
+      // Record numbers, starting at 0
+      (None, None) => uint_id_types(n_rows.saturating_sub(1) as u128),
+      (None, Some(_)) => id_stats.id_types()?,
+    };
+    let val_types = match self.val_type {
+      Some(val_type) => vec![val_type],
+      None => val_stats.val_types()?,
+    };
+    Ok(IdVal::new(id_types[0].clone(), val_types[0].clone()))
+  }
+}
+
+/// Statistics on the non-empty fields of a CSV column, used to infer its datatype.
+struct ColStats {
+  n_fields: usize,
+  /// `true` while all fields are integers
+  is_int: bool,
+  /// `true` while all fields are finite floats (integers included)
+  is_float: bool,
+  /// `true` while all floats are represented exactly in single precision
+  is_f32: bool,
+  /// Range of the integer values
+  min: i128,
+  max: i128,
+  /// Number of bytes of the longest field
+  max_byte_size: usize,
+}
+
+impl ColStats {
+  fn new() -> Self {
+    ColStats {
+      n_fields: 0,
+      is_int: true,
+      is_float: true,
+      is_f32: true,
+      min: i128::MAX,
+      max: i128::MIN,
+      max_byte_size: 0,
+    }
+  }
+
+  fn add(&mut self, field: &str) {
+    // Empty fields are NULL values
+    if field.is_empty() {
+      return;
+    }
+    self.n_fields += 1;
+    self.max_byte_size = self.max_byte_size.max(field.len());
+    if self.is_int {
+      match field.parse::<i128>() {
+        Ok(v) => {
+          self.min = self.min.min(v);
+          self.max = self.max.max(v);
+        }
+        Err(_) => self.is_int = false,
+      }
+    }
+    if self.is_float {
+      match field.parse::<f64>() {
+        Ok(v) if v.is_finite() => {
+          // The shortest representation of the f32 must lead to the same f64
+          self.is_f32 &= field
+            .parse::<f32>()
+            .is_ok_and(|v32| v32.is_finite() && v32.to_string().parse::<f64>() == Ok(v))
+        }
+        _ => self.is_float = false,
+      }
+    }
+  }
+
+  fn check_not_empty(&self, name: &str) -> Result<(), Error> {
+    if self.n_fields == 0 {
+      Err(Error::new(
+        ErrorKind::Other,
+        format!("Unable to infer the {} type: no (non-empty) field", name),
+      ))
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Returns the possible identifier types, from the narrowest to the widest.
+  fn id_types(&self) -> Result<Vec<IdType>, Error> {
+    self.check_not_empty("identifier")?;
+    Ok(if self.is_int && self.min >= 0 && self.max <= u64::MAX as i128 {
+      uint_id_types(self.max as u128)
+    } else {
+      vec![IdType::Str {
+        n_chars: self.max_byte_size,
+      }]
+    })
+  }
+
+  /// Returns the possible value types, from the narrowest to the widest.
+  fn val_types(&self) -> Result<Vec<ValType>, Error> {
+    self.check_not_empty("value")?;
+    let mut types = Vec::new();
+    if self.is_int {
+      if self.min >= 0 {
+        types.extend(
+          [ValType::U24, ValType::U32, ValType::U40, ValType::U48, ValType::U56, ValType::U64]
+            .into_iter()
+            .filter(|t| (self.max as u128) < 1_u128 << (8 * t.byte_size())),
+        );
+      }
+      types.extend(
+        [ValType::I24, ValType::I32, ValType::I40, ValType::I48, ValType::I56, ValType::I64]
+          .into_iter()
+          .filter(|t| {
+            let half = 1_i128 << (8 * t.byte_size() - 1);
+            -half <= self.min && self.max < half
+          }),
+      );
+      // Integers exactly represented by a double
+      if -(1_i128 << 53) <= self.min && self.max <= 1_i128 << 53 {
+        types.push(ValType::F64);
+      }
+    } else if self.is_float {
+      if self.is_f32 {
+        types.push(ValType::F32);
+      }
+      types.push(ValType::F64);
+    }
+    if types.is_empty() {
+      types.push(ValType::Str {
+        n_chars: self.max_byte_size,
+      });
+    }
+    Ok(types)
+  }
+}
+
+/// Returns the unsigned identifier types able to store the given value, from the narrowest.
+fn uint_id_types(max: u128) -> Vec<IdType> {
+  [IdType::U24, IdType::U32, IdType::U40, IdType::U48, IdType::U56, IdType::U64]
+    .into_iter()
+    .filter(|t| max < 1_u128 << (8 * t.byte_size()))
+    .collect()
+}
+
+/// Returns an error if a normalisation is required on values which are not strings.
+fn check_norm<V: Val, VRW: ReadWrite<Type = V>>(norm: &StrNorm, val_rw: &VRW) -> Result<(), Error> {
+  if norm.is_identity() || val_rw.is_str() {
+    Ok(())
+  } else {
+    Err(Error::new(
+      ErrorKind::Other,
+      "Normalisation is only supported on string values",
+    ))
+  }
+}
+
+/// Maximum number of truncated fields reported individually, per column.
+const MAX_TRUNCATION_WARNINGS: usize = 10;
+
+/// Strings of a column truncated to the width of the column.
+#[derive(Default)]
+struct Truncated {
+  /// Number of strings truncated so far
+  count: Cell<usize>,
+  /// Number of bytes of the longest truncated string
+  longest: Cell<usize>,
+}
+
+impl Truncated {
+  /// Counts the given string, longer than `max_byte_size`, reporting it individually only for the
+  /// first `MAX_TRUNCATION_WARNINGS` strings of the column.
+  fn add(&self, col_name: &str, val: &str, position: &str, max_byte_size: usize) {
+    let count = self.count.get();
+    if count < MAX_TRUNCATION_WARNINGS {
+      warn!(
+        "Col '{}' value '{}' at {} longer than {} bytes, the value is truncated!",
+        col_name, val, position, max_byte_size
+      );
+    } else if count == MAX_TRUNCATION_WARNINGS {
+      warn!(
+        "Col '{}': more values longer than {} bytes, the next truncations are not reported \
+         individually.",
+        col_name, max_byte_size
+      );
+    }
+    self.count.set(count + 1);
+    self.longest.set(self.longest.get().max(val.len()));
+  }
+
+  /// Logs the number of truncated strings, if any.
+  fn report(&self, col_name: &str, max_byte_size: usize) {
+    if self.count.get() > 0 {
+      warn!(
+        "Number of '{}' fields truncated to {} bytes: {} (longest: {} bytes)",
+        col_name,
+        max_byte_size,
+        self.count.get(),
+        self.longest.get()
+      );
+    }
+  }
+}
+
+/// Error returned for a string longer than `max_byte_size` with the `error` truncation policy.
+fn too_long_err(col_name: &str, val: &str, position: &str, max_byte_size: usize) -> Error {
+  Error::new(
+    ErrorKind::Other,
+    format!(
+      "Col '{}' value '{}' at {} longer than {} bytes! Increase the width or use the 'warn' or \
+       'auto' truncation policy.",
+      col_name, val, position, max_byte_size
+    ),
+  )
+}
+
+/// Parameters used to parse the fields of a CSV column.
+struct Col {
+  name: &'static str,
+  index: usize,
+  norm: StrNorm,
+  truncation: Truncation,
+  /// Maximum number of bytes of a field, `None` if the column does not contain strings
+  max_byte_size: Option<usize>,
+  /// Fields truncated so far
+  truncated: Truncated,
+}
+
+impl Col {
+  fn new<T, RW: ReadWrite<Type = T>>(
+    name: &'static str,
+    index: usize,
+    norm: StrNorm,
+    truncation: Truncation,
+    rw: &RW,
+  ) -> Col {
+    Col {
+      name,
+      index,
+      norm,
+      truncation,
+      max_byte_size: if rw.is_str() { Some(rw.n_bytes()) } else { None },
+      truncated: Truncated::default(),
+    }
+  }
+
+  /// Returns the normalised field of the given record (if any), truncated if needed.
+  fn field<'a>(&self, record: &'a StringRecord) -> Result<Option<Cow<'a, str>>, Error> {
+    let field = match record.get(self.index) {
+      Some(field) => self.norm.apply(field),
+      None => return Ok(None),
+    };
+    match self.max_byte_size {
+      Some(max_byte_size) if field.len() > max_byte_size => match self.truncation {
+        Truncation::Warn => {
+          let position = get_position_str(record);
+          self.truncated.add(self.name, &field, &position, max_byte_size);
+          // Truncated here, and not when written, to sort the truncated strings
+          Ok(Some(Cow::Owned(
+            truncate_str(&field, max_byte_size).to_string(),
+          )))
+        }
+        // With 'auto', the width is set from a pre-scan: the field was not in the input file
+        Truncation::Error | Truncation::Auto => Err(too_long_err(
+          self.name,
+          &field,
+          &get_position_str(record),
+          max_byte_size,
+        )),
+      },
+      _ => Ok(Some(field)),
+    }
+  }
+
+  /// Logs the number of truncated fields, if any.
+  fn report_truncated(&self) {
+    self
+      .truncated
+      .report(self.name, self.max_byte_size.unwrap_or(0));
+  }
+}
+
+/// Returns the identifier of the given record number, if it fits in the identifier type.
+fn recno<RW: ReadWrite>(i: usize, rw: &RW) -> Result<RW::Type, Error>
+where
+  RW::Type: FromU64,
+{
+  RW::Type::try_from_u64(i as u64)
+    .filter(|id| rw.fits(id))
+    .ok_or_else(|| {
+      Error::new(
+        ErrorKind::Other,
+        format!(
+          "Record number {} does not fit in a {} bytes identifier! Use a wider --id-type.",
+          i,
+          rw.n_bytes()
+        ),
+      )
+    })
+}
+
+/// Returns an error if the given parsed value does not fit in the number of bytes of its type.
+fn check_fits<RW: ReadWrite>(
+  val: RW::Type,
+  rw: &RW,
+  col_name: &str,
+  record: &StringRecord,
+) -> Result<RW::Type, Error>
+where
+  RW::Type: Display,
+{
+  if rw.fits(&val) {
+    Ok(val)
+  } else {
+    Err(Error::new(
+      ErrorKind::Other,
+      format!(
+        "Col '{}' value '{}' at {} does not fit in {} bytes! Use a wider type.",
+        col_name,
+        val,
+        get_position_str(record),
+        rw.n_bytes()
+      ),
+    ))
+  }
+}
+
+fn get<RW: ReadWrite>(
+  record: &StringRecord,
+  col: &Col,
+  rw: &RW,
+) -> Result<Option<RW::Type>, Error>
+where
+  RW::Type: FromStr + Display,
+{
+  let col_name = col.name;
+  let res = col.field(record)?;
+  Ok(match res {
     Some(str_ref) => {
       if str_ref.is_empty() {
         warn!("Empty col '{}' at {}!", col_name, get_position_str(record));
         None
       } else {
-        match str_ref.parse::<F>() {
-          Ok(val) => Some(val),
+        match str_ref.parse::<RW::Type>() {
+          // Out of range values are errors, not NULLs, not to silently loose data
+          Ok(val) => Some(check_fits(val, rw, col_name, record)?),
           Err(_) => {
             warn!(
               "WARNING: error parsing col '{}' value '{}' at {}, the value is set to NULL!",
@@ -212,15 +706,19 @@ fn get<F: FromStr>(record: &StringRecord, index: usize, col_name: &'static str)
       );
       None
     }
-  }
+  })
 }
 
-fn get_with_err<F: FromStr>(
+fn get_with_err<RW: ReadWrite>(
   record: &StringRecord,
-  index: usize,
-  col_name: &'static str,
-) -> Result<F, Error> {
-  let res = record.get(index);
+  col: &Col,
+  rw: &RW,
+) -> Result<RW::Type, Error>
+where
+  RW::Type: FromStr + Display,
+{
+  let col_name = col.name;
+  let res = col.field(record)?;
   match res {
     Some(str_ref) => {
       if str_ref.is_empty() {
@@ -230,8 +728,8 @@ fn get_with_err<F: FromStr>(
           format!("Empty col '{}' at {}!", col_name, get_position_str(record)),
         ))
       } else {
-        match str_ref.parse::<F>() {
-          Ok(val) => Ok(val),
+        match str_ref.parse::<RW::Type>() {
+          Ok(val) => check_fits(val, rw, col_name, record),
           Err(_) => Err(Error::new(
             ErrorKind::Other,
             format!(
@@ -261,15 +759,19 @@ fn get_position_str(record: &StringRecord) -> String {
 /// Structure building a index element by element.
 pub struct BSTreeFileBuilder<I, V, IRW, VRW>
 where
-  I: Id,
-  V: Val,
-  IRW: ReadWrite<Type = I>,
-  VRW: ReadWrite<Type = V>,
+  I: 'static + Id,
+  V: 'static + Val,
+  IRW: 'static + ReadWrite<Type = I>,
+  VRW: 'static + ReadWrite<Type = V>,
 {
   /// Args
   args: MkAlgoArgs,
   /// Memory size args
   mem_args: MemSizeArgs,
+  /// Identifiers of the NULL values, if any (declared before `tmp_dir` to be dropped first)
+  nulls: Option<TmpNullsFile>,
+  /// Sort the chunks and write them in the temporary dir
+  chunk_writer: ChunkWriter<I, V, IRW, VRW>,
   /// Tmp dir obj
   tmp_dir: TmpDir,
   /// Id and value types (to write in the file)
@@ -281,14 +783,16 @@ where
   entries: Vec<Entry<I, V>>,
   /// Number of elements in the tree
   count: usize,
+  /// String values truncated so far
+  truncated: Truncated,
 }
 
 impl<I, V, IRW, VRW> BSTreeFileBuilder<I, V, IRW, VRW>
 where
-  I: Id,
-  V: Val,
-  IRW: ReadWrite<Type = I>,
-  VRW: ReadWrite<Type = V>,
+  I: 'static + Id,
+  V: 'static + Val,
+  IRW: 'static + ReadWrite<Type = I>,
+  VRW: 'static + ReadWrite<Type = V>,
 {
   pub fn new(
     args: MkAlgoArgs,
@@ -297,70 +801,212 @@ where
     id_rw: IRW,
     val_rw: VRW,
   ) -> Result<Self, Error> {
-    let chunk_size = args.chunk_size;
+    check_norm(&args.normalize, &val_rw)?;
+    let chunk_size = args.thread_chunk_size();
+    let chunk_writer = ChunkWriter::new(args.threads, &id_rw, &val_rw);
     args.get_tmp_dir().map(|tmp_dir| Self {
       args,
       mem_args,
+      nulls: None,
+      chunk_writer,
       tmp_dir,
       types,
       id_rw,
       val_rw,
       entries: Vec::with_capacity(chunk_size),
       count: 0,
+      truncated: Truncated::default(),
     })
   }
 
   /// We recall that the value is indexed (it is th key) and the identifier correspond, e.g., to
   /// a recno and is the information we want to retrieve from a query of the indexed values.
+  /// String values are normalised according to `args.normalize`, and those longer than the
+  /// width of the value type are handled according to `args.truncation`.
   pub fn append(&mut self, id: I, val: V) -> Result<(), Error> {
     if self.chunk_is_full() {
       self.sort_and_write_entries()?;
     }
+    let norm = &self.args.normalize;
+    let val = if norm.is_identity() {
+      val
+    } else {
+      norm
+        .apply(&val.to_string())
+        .parse::<V>()
+        .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))?
+    };
+    let val = self.truncate(val)?;
     self.entries.push(Entry::new(id, val));
     Ok(())
   }
 
+  /// Applies the truncation policy to a string value longer than the width of the value type.
+  fn truncate(&self, val: V) -> Result<V, Error> {
+    let max_byte_size = self.val_rw.n_bytes();
+    if !self.val_rw.is_str() {
+      return Ok(val);
+    }
+    let field = val.to_string();
+    if field.len() <= max_byte_size {
+      return Ok(val);
+    }
+    let position = format!("entry {}", self.count + self.entries.len());
+    match self.args.truncation {
+      Truncation::Warn => {
+        self.truncated.add("value", &field, &position, max_byte_size);
+        // Truncated here, and not when written, to sort the truncated strings
+        truncate_str(&field, max_byte_size)
+          .parse::<V>()
+          .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))
+      }
+      // No pre-scan of the appended values is possible: 'auto' behaves like 'error'
+      Truncation::Error | Truncation::Auto => {
+        Err(too_long_err("value", &field, &position, max_byte_size))
+      }
+    }
+  }
+
+  /// Register the identifier of a row having a NULL value (not stored in the tree itself).
+  pub fn append_null(&mut self, id: I) -> Result<(), Error> {
+    if self.nulls.is_none() {
+      self.nulls = Some(TmpNullsFile::new(&self.args.temp)?);
+    }
+    match &mut self.nulls {
+      Some(nulls) => nulls.append(&self.id_rw, &id),
+      None => unreachable!(),
+    }
+  }
+
   fn chunk_is_full(&self) -> bool {
-    self.entries.len() == self.args.chunk_size
+    self.entries.len() == self.args.thread_chunk_size()
   }
 
   fn sort_and_write_entries(&mut self) -> Result<(), Error> {
-    let mut chunk = std::mem::replace(&mut self.entries, Vec::with_capacity(self.args.chunk_size));
-    debug!("Sort chunk...");
-    chunk.sort();
+    let chunk = std::mem::replace(
+      &mut self.entries,
+      Vec::with_capacity(self.args.thread_chunk_size()),
+    );
     info!(
       "Write chunk {}..{} in temporary file...",
       self.count,
       self.count + chunk.len()
     );
     self.count += chunk.len();
-    self
-      .tmp_dir
-      .write_tmp_file(&self.id_rw, &self.val_rw, chunk)
+    self.chunk_writer.write(&mut self.tmp_dir, chunk)
   }
 
   /// Returns the number en entries in the BSTree.s
   pub fn build_index(mut self) -> Result<usize, Error> {
+    self.truncated.report("value", self.val_rw.n_bytes());
     // Write last elements in a temporray file
     if !self.entries.is_empty() {
       self.sort_and_write_entries()?;
     }
+    self.chunk_writer.finish()?;
     info!("Reduce to max {} files be merge sort...", self.args.kway);
     let mut tmp_dir = self
       .tmp_dir
-      .reduce_to_k_files(&self.id_rw, &self.val_rw, self.args.kway)?;
+      .par_reduce_to_k_files(
+        &self.id_rw,
+        &self.val_rw,
+        self.args.kway,
+        self.args.threads,
+      )?;
+    // Declared after `tmp_dir` to be dropped (i.e. removed) before it
+    let mut nulls = self.nulls.take();
     info!("Re-read all tmp files to build the final bstree file...");
     let sorted_entry_iter = tmp_dir.to_sorted_iter(&self.id_rw, &self.val_rw);
     #[cfg(not(target_arch = "wasm32"))]
-    bstree::build(
-      self.args.get_output(),
-      &self.mem_args,
-      self.count,
-      sorted_entry_iter,
-      &self.types,
-      &self.id_rw,
-      &self.val_rw,
-    )
+    match &mut nulls {
+      Some(nulls) => bstree::build_with_options(
+        self.args.get_output(),
+        &self.mem_args,
+        self.count,
+        sorted_entry_iter,
+        nulls.to_iter(&self.id_rw)?,
+        &self.types,
+        &self.id_rw,
+        &self.val_rw,
+        &self.args.build_options(),
+      ),
+      None => bstree::build_with_options(
+        self.args.get_output(),
+        &self.mem_args,
+        self.count,
+        sorted_entry_iter,
+        std::iter::empty(),
+        &self.types,
+        &self.id_rw,
+        &self.val_rw,
+        &self.args.build_options(),
+      ),
+    }
     .map(|()| self.count)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn testok_infer_types() {
+    use structopt::StructOpt;
+    let col_args = ColArgs::from_iter(["test"]);
+    let infer = |csv: &str, val_type: Option<ValType>| {
+      let reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(csv.as_bytes());
+      TypeInference::new(&col_args, StrNorm::default(), None, val_type)
+        .call(reader)
+        .unwrap()
+    };
+    // The narrowest types are chosen, whatever the crate features
+    let types = infer("1\n\n300\n", None);
+    assert_eq!(types.id_type().to_string(), "u3");
+    assert_eq!(types.val_type().to_string(), "u3");
+    assert_eq!(infer("-1\n300\n", None).val_type().to_string(), "i3");
+    assert_eq!(infer("1.5\n-3\n", None).val_type().to_string(), "f4");
+    assert_eq!(infer("0.123456789\n2\n", None).val_type().to_string(), "f8");
+    // An explicit type is kept
+    let types = infer("1\n2\n", Some(ValType::F64));
+    assert_eq!(types.val_type().to_string(), "f8");
+    for t in ["u3", "i5", "f8", "t12"] {
+      assert_eq!(t.parse::<ValType>().unwrap().to_string(), t);
+    }
+  }
+
+  #[test]
+  fn testok_builder_truncation() {
+    use crate::{
+      rw::{StrRW, U32RW},
+      testutils::TmpDir,
+    };
+    let dir = TmpDir::new("builder_truncation");
+    let builder = |truncation: Truncation| {
+      let mut args = MkAlgoArgs::new(None, None, Some(dir.join("tmp")), dir.join("test"));
+      args.truncation = truncation;
+      let types = IdVal::new(IdType::U32, ValType::Str { n_chars: 4 });
+      let mem_args = MemSizeArgs {
+        l1: 32,
+        disk: 8192,
+        fill_factor: 1.0,
+      };
+      BSTreeFileBuilder::new(args, mem_args, types, U32RW, StrRW { n_bytes: 4 }).unwrap()
+    };
+    // Too long values are truncated with the 'warn' policy...
+    let mut b = builder(Truncation::Warn);
+    b.append(0, String::from("abcd")).unwrap();
+    b.append(1, String::from("abcdefgh")).unwrap();
+    assert_eq!(b.truncated.count.get(), 1);
+    assert_eq!(b.truncated.longest.get(), 8);
+    assert!(b.entries.iter().all(|e| e.val == "abcd"));
+    // ... and rejected with the 'error' and 'auto' policies
+    for truncation in [Truncation::Error, Truncation::Auto] {
+      let mut b = builder(truncation);
+      b.append(0, String::from("abcd")).unwrap();
+      assert!(b.append(1, String::from("abcdefgh")).is_err());
+    }
+  }
+}
@@ -0,0 +1,146 @@
+//! Normalisation of string values, applied both when building a tree and when querying it.
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+  borrow::Cow,
+  fmt::{self, Display, Formatter},
+  io::{Error, ErrorKind},
+  str::FromStr,
+};
+
+/// Handling of the whitespaces inside a string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Spaces {
+  /// Whitespaces are left untouched
+  #[default]
+  Keep,
+  /// Each sequence of whitespaces is replaced by a single space
+  Collapse,
+  /// All whitespaces are removed
+  Remove,
+}
+
+/// Normalisation of string values, recorded in the tree metadata so that the same normalisation
+/// is applied to the query values (e.g. "HD  1234 " and "hd1234" both become "hd1234" with case
+/// folding and whitespace removal).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StrNorm {
+  /// Convert to lower case
+  pub case_fold: bool,
+  /// Remove leading and trailing whitespaces
+  pub trim: bool,
+  /// Handling of the other whitespaces
+  pub spaces: Spaces,
+}
+
+impl StrNorm {
+  /// Names of the options accepted (as a comma separated list) by `from_str`.
+  pub const NAMES: [&'static str; 5] = ["none", "case-fold", "trim", "collapse-spaces", "remove-spaces"];
+
+  /// Returns `true` if the normalisation leaves all strings untouched.
+  pub fn is_identity(&self) -> bool {
+    *self == StrNorm::default()
+  }
+
+  /// Returns the normalised version of the given string.
+  pub fn apply<'a>(&self, s: &'a str) -> Cow<'a, str> {
+    if self.is_identity() {
+      return Cow::Borrowed(s);
+    }
+    let s = if self.trim { s.trim() } else { s };
+    let s: String = match self.spaces {
+      Spaces::Keep => s.to_string(),
+      Spaces::Collapse => {
+        let mut res = String::with_capacity(s.len());
+        let mut prev_is_space = false;
+        for c in s.chars() {
+          if !c.is_whitespace() {
+            res.push(c);
+          } else if !prev_is_space {
+            res.push(' ');
+          }
+          prev_is_space = c.is_whitespace();
+        }
+        res
+      }
+      Spaces::Remove => s.chars().filter(|c| !c.is_whitespace()).collect(),
+    };
+    Cow::Owned(if self.case_fold { s.to_lowercase() } else { s })
+  }
+
+  /// Parses the normalised version of the given string, e.g. a query value.
+  pub fn parse<V: FromStr>(&self, s: &str) -> Result<V, Error> {
+    self
+      .apply(s)
+      .parse::<V>()
+      .map_err(|_| Error::new(ErrorKind::Other, "Wrong value type"))
+  }
+}
+
+impl FromStr for StrNorm {
+  type Err = Error;
+
+  /// Parses a comma separated list of options among `StrNorm::NAMES`.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut norm = StrNorm::default();
+    for opt in s.split(',').map(|opt| opt.trim()) {
+      match opt {
+        "none" => {}
+        "case-fold" => norm.case_fold = true,
+        "trim" => norm.trim = true,
+        "collapse-spaces" => norm.spaces = Spaces::Collapse,
+        "remove-spaces" => norm.spaces = Spaces::Remove,
+        _ => {
+          return Err(Error::new(
+            ErrorKind::Other,
+            format!(
+              "Unknown normalisation '{}'. Possible values: {}",
+              opt,
+              StrNorm::NAMES.join(", ")
+            ),
+          ))
+        }
+      }
+    }
+    Ok(norm)
+  }
+}
+
+impl Display for StrNorm {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let mut opts = Vec::new();
+    if self.case_fold {
+      opts.push("case-fold");
+    }
+    if self.trim {
+      opts.push("trim");
+    }
+    match self.spaces {
+      Spaces::Keep => {}
+      Spaces::Collapse => opts.push("collapse-spaces"),
+      Spaces::Remove => opts.push("remove-spaces"),
+    }
+    if opts.is_empty() {
+      f.write_str("none")
+    } else {
+      f.write_str(&opts.join(","))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn testok_parse_norm() {
+    let norm: StrNorm = "case-fold,trim,remove-spaces".parse().unwrap();
+    assert_eq!(norm.to_string(), "case-fold,trim,remove-spaces");
+    assert_eq!(norm.apply(" HD  1234 \t"), "hd1234");
+    let collapse: StrNorm = "trim, collapse-spaces".parse().unwrap();
+    assert_eq!(collapse.apply("  Gaia \t DR2  12 "), "Gaia DR2 12");
+    assert!("upper".parse::<StrNorm>().is_err());
+    assert!("none".parse::<StrNorm>().unwrap().is_identity());
+  }
+}
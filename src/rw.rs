@@ -1,9 +1,15 @@
 use crate::float::FiniteFloat;
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use std::io::{Error, ErrorKind, Read, Write};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use std::{
+  cmp::Ordering,
+  fmt::{self, Display, Formatter},
+  io::{Error, ErrorKind, Read, Write},
+  str::FromStr,
+};
 
 /// Trait used to read and write element of the associated type `Type`.
-pub trait ReadWrite: Clone + Send {
+pub trait ReadWrite: Clone + Send + Sync {
   type Type;
   /*fn id_type(&self) -> IdType; // Used to be written in the file
   fn val_type(&self) -> ValType;*/
@@ -13,6 +19,49 @@ pub trait ReadWrite: Clone + Send {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error>;
   /// Write an element of type `Type` to the given `Writer`
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error>;
+  /// Compares the element stored in the first `n_bytes` of `raw` with the given element, if
+  /// possible without building it (the default implementation reads it).
+  /// The result must be the one of `Ord::cmp` on the read element: the raw bytes themselves are
+  /// in general not ordered like the elements (e.g. little-endian or signed integers).
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error>
+  where
+    Self::Type: Ord,
+  {
+    self.read(&mut &raw[..]).map(|v| v.cmp(val))
+  }
+  /// Returns, if possible, the bytes of the given element that compared with `memcmp` to the
+  /// raw bytes of a stored element give the same result as `cmp_raw` (`None` by default).
+  /// It allows to encode once an element compared to many stored elements.
+  fn memcmp_key(&self, _val: &Self::Type) -> Result<Option<Vec<u8>>, Error> {
+    Ok(None)
+  }
+  /// Tells whether the elements are strings (`false` for numbers)
+  fn is_str(&self) -> bool {
+    false
+  }
+  /// Tells whether the given element can be written on `n_bytes` (always `true` except for
+  /// integers stored on less bytes than their in-memory type)
+  fn fits(&self, _val: &Self::Type) -> bool {
+    true
+  }
+  /// Transformation making the written bytes comparable with `memcmp` (`None` if not supported,
+  /// see [ValEncoding::Ordered](enum.ValEncoding.html#variant.Ordered))
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    None
+  }
+}
+
+/// Returns an error if the given element can't be written on `n_bytes` (instead of panicking or
+/// silently writing a wrong value).
+fn check_fits<T: Display, RW: ReadWrite<Type = T>>(rw: &RW, val: &T) -> Result<(), Error> {
+  if rw.fits(val) {
+    Ok(())
+  } else {
+    Err(Error::new(
+      ErrorKind::Other,
+      format!("Value {} does not fit in {} bytes", val, rw.n_bytes()),
+    ))
+  }
 }
 
 // Unsigned integers
@@ -28,9 +77,19 @@ impl ReadWrite for U24RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_u24::<LittleEndian>()
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_u24(raw).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
     writer.write_u24::<LittleEndian>(*val)
   }
+  fn fits(&self, val: &Self::Type) -> bool {
+    *val < 1 << 24
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Unsigned)
+  }
 }
 
 #[derive(Clone)]
@@ -44,9 +103,15 @@ impl ReadWrite for U32RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_u32::<LittleEndian>()
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_u32(raw).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
     writer.write_u32::<LittleEndian>(*val)
   }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Unsigned)
+  }
 }
 
 #[derive(Clone)]
@@ -60,9 +125,19 @@ impl ReadWrite for U40RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_uint::<LittleEndian>(5)
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_uint(raw, 5).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
     writer.write_uint::<LittleEndian>(*val, 5)
   }
+  fn fits(&self, val: &Self::Type) -> bool {
+    *val < 1 << 40
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Unsigned)
+  }
 }
 
 #[derive(Clone)]
@@ -76,9 +151,19 @@ impl ReadWrite for U48RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_u48::<LittleEndian>()
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_u48(raw).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
     writer.write_u48::<LittleEndian>(*val)
   }
+  fn fits(&self, val: &Self::Type) -> bool {
+    *val < 1 << 48
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Unsigned)
+  }
 }
 
 #[derive(Clone)]
@@ -92,9 +177,19 @@ impl ReadWrite for U56RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_uint::<LittleEndian>(7)
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_uint(raw, 7).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
     writer.write_uint::<LittleEndian>(*val, 7)
   }
+  fn fits(&self, val: &Self::Type) -> bool {
+    *val < 1 << 56
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Unsigned)
+  }
 }
 
 #[derive(Clone)]
@@ -108,9 +203,15 @@ impl ReadWrite for U64RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_u64::<LittleEndian>()
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_u64(raw).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
     writer.write_u64::<LittleEndian>(*val)
   }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Unsigned)
+  }
 }
 
 // Signed integers
@@ -126,9 +227,19 @@ impl ReadWrite for I24RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_i24::<LittleEndian>()
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_i24(raw).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
     writer.write_i24::<LittleEndian>(*val)
   }
+  fn fits(&self, val: &Self::Type) -> bool {
+    (-(1 << 23)..1 << 23).contains(val)
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Signed)
+  }
 }
 
 #[derive(Clone)]
@@ -142,9 +253,15 @@ impl ReadWrite for I32RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_i32::<LittleEndian>()
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_i32(raw).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
     writer.write_i32::<LittleEndian>(*val)
   }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Signed)
+  }
 }
 
 #[derive(Clone)]
@@ -158,9 +275,19 @@ impl ReadWrite for I40RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_int::<LittleEndian>(5)
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_int(raw, 5).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
     writer.write_int::<LittleEndian>(*val, 5)
   }
+  fn fits(&self, val: &Self::Type) -> bool {
+    (-(1 << 39)..1 << 39).contains(val)
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Signed)
+  }
 }
 
 #[derive(Clone)]
@@ -174,9 +301,19 @@ impl ReadWrite for I48RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_i48::<LittleEndian>()
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_i48(raw).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
     writer.write_i48::<LittleEndian>(*val)
   }
+  fn fits(&self, val: &Self::Type) -> bool {
+    (-(1 << 47)..1 << 47).contains(val)
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Signed)
+  }
 }
 
 #[derive(Clone)]
@@ -190,9 +327,19 @@ impl ReadWrite for I56RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_int::<LittleEndian>(7)
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_int(raw, 7).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
     writer.write_int::<LittleEndian>(*val, 7)
   }
+  fn fits(&self, val: &Self::Type) -> bool {
+    (-(1 << 55)..1 << 55).contains(val)
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Signed)
+  }
 }
 
 #[derive(Clone)]
@@ -206,9 +353,143 @@ impl ReadWrite for I64RW {
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     reader.read_i64::<LittleEndian>()
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_i64(raw).cmp(val))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
     writer.write_i64::<LittleEndian>(*val)
   }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Signed)
+  }
+}
+
+// Integers whose number of bytes is known at runtime only (see `IdVal::exec_in_mem`)
+
+/// Unsigned integer stored on `n_bytes` in `[1, 4]`.
+#[derive(Clone)]
+pub struct VarU32RW {
+  pub n_bytes: usize,
+}
+
+impl ReadWrite for VarU32RW {
+  type Type = u32;
+  fn n_bytes(&self) -> usize {
+    self.n_bytes
+  }
+  fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
+    reader
+      .read_uint::<LittleEndian>(self.n_bytes)
+      .map(|v| v as u32)
+  }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok((LittleEndian::read_uint(raw, self.n_bytes) as u32).cmp(val))
+  }
+  fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
+    writer.write_uint::<LittleEndian>(*val as u64, self.n_bytes)
+  }
+  fn fits(&self, val: &Self::Type) -> bool {
+    self.n_bytes >= 4 || *val >> (8 * self.n_bytes) == 0
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Unsigned)
+  }
+}
+
+/// Unsigned integer stored on `n_bytes` in `[1, 8]`.
+#[derive(Clone)]
+pub struct VarU64RW {
+  pub n_bytes: usize,
+}
+
+impl ReadWrite for VarU64RW {
+  type Type = u64;
+  fn n_bytes(&self) -> usize {
+    self.n_bytes
+  }
+  fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
+    reader.read_uint::<LittleEndian>(self.n_bytes)
+  }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_uint(raw, self.n_bytes).cmp(val))
+  }
+  fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
+    writer.write_uint::<LittleEndian>(*val, self.n_bytes)
+  }
+  fn fits(&self, val: &Self::Type) -> bool {
+    self.n_bytes >= 8 || *val >> (8 * self.n_bytes) == 0
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Unsigned)
+  }
+}
+
+/// Signed integer stored on `n_bytes` in `[1, 4]`.
+#[derive(Clone)]
+pub struct VarI32RW {
+  pub n_bytes: usize,
+}
+
+impl ReadWrite for VarI32RW {
+  type Type = i32;
+  fn n_bytes(&self) -> usize {
+    self.n_bytes
+  }
+  fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
+    reader
+      .read_int::<LittleEndian>(self.n_bytes)
+      .map(|v| v as i32)
+  }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok((LittleEndian::read_int(raw, self.n_bytes) as i32).cmp(val))
+  }
+  fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
+    writer.write_int::<LittleEndian>(*val as i64, self.n_bytes)
+  }
+  fn fits(&self, val: &Self::Type) -> bool {
+    self.n_bytes >= 4 || {
+      let half = 1_i32 << (8 * self.n_bytes - 1);
+      (-half..half).contains(val)
+    }
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Signed)
+  }
+}
+
+/// Signed integer stored on `n_bytes` in `[1, 8]`.
+#[derive(Clone)]
+pub struct VarI64RW {
+  pub n_bytes: usize,
+}
+
+impl ReadWrite for VarI64RW {
+  type Type = i64;
+  fn n_bytes(&self) -> usize {
+    self.n_bytes
+  }
+  fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
+    reader.read_int::<LittleEndian>(self.n_bytes)
+  }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    Ok(LittleEndian::read_int(raw, self.n_bytes).cmp(val))
+  }
+  fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    check_fits(self, val)?;
+    writer.write_int::<LittleEndian>(*val, self.n_bytes)
+  }
+  fn fits(&self, val: &Self::Type) -> bool {
+    self.n_bytes >= 8 || {
+      let half = 1_i64 << (8 * self.n_bytes - 1);
+      (-half..half).contains(val)
+    }
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Signed)
+  }
 }
 
 // Float
@@ -227,9 +508,17 @@ impl ReadWrite for F32RW {
         .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Read a not finite f32!"))
     })
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    FiniteFloat::<f32>::new(LittleEndian::read_f32(raw))
+      .map(|v| v.cmp(val))
+      .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Read a not finite f32!"))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
     writer.write_f32::<LittleEndian>(val.get())
   }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Float)
+  }
 }
 
 #[derive(Clone)]
@@ -246,9 +535,17 @@ impl ReadWrite for F64RW {
         .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Read a not finite f64!"))
     })
   }
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    FiniteFloat::<f64>::new(LittleEndian::read_f64(raw))
+      .map(|v| v.cmp(val))
+      .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Read a not finite f64!"))
+  }
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
     writer.write_f64::<LittleEndian>(val.get())
   }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Float)
+  }
 }
 
 // String
@@ -258,11 +555,31 @@ pub struct StrRW {
   pub n_bytes: usize,
 }
 
+/// Returns the longest prefix of the given string fitting in `n_bytes` bytes, without cutting
+/// a UTF-8 code point (so that the truncated string can be read back).
+pub fn truncate_str(s: &str, n_bytes: usize) -> &str {
+  if s.len() <= n_bytes {
+    s
+  } else {
+    let mut i = n_bytes;
+    while !s.is_char_boundary(i) {
+      i -= 1;
+    }
+    &s[..i]
+  }
+}
+
 impl ReadWrite for StrRW {
   type Type = String;
   fn n_bytes(&self) -> usize {
     self.n_bytes
   }
+  fn is_str(&self) -> bool {
+    true
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    Some(OrderedKind::Bytes)
+  }
   fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
     let mut buf = vec![0u8; self.n_bytes];
     reader
@@ -273,11 +590,19 @@ impl ReadWrite for StrRW {
       )
     )
   }
+  /// Compares the bytes without building a `String` (the UTF-8 validity is not checked).
+  /// The order is the same as the `String` one, since it is also based on the bytes.
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error> {
+    let raw = &raw[..self.n_bytes];
+    let len = raw.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    Ok(raw[..len].cmp(val.as_bytes()))
+  }
+  /// Strings longer than `n_bytes` are truncated (see [truncate_str](fn.truncate_str.html)).
   fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
-    let buf = val.as_bytes();
+    let buf = truncate_str(val, self.n_bytes).as_bytes();
     let l = buf.len();
-    if l >= self.n_bytes {
-      writer.write_all(&buf[0..self.n_bytes])
+    if l == self.n_bytes {
+      writer.write_all(buf)
     } else {
       // 0u8 = '\0' = null character
       writer
@@ -286,3 +611,305 @@ impl ReadWrite for StrRW {
     }
   }
 }
+
+// Encoding
+
+/// Encoding of the values in a tree file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValEncoding {
+  /// Bytes written by the value codec (little-endian numbers)
+  #[default]
+  LittleEndian,
+  /// Bytes transformed so that their lexicographic order (i.e. `memcmp`) is the order of the
+  /// values (see [OrderedKind](enum.OrderedKind.html))
+  Ordered,
+}
+
+impl ValEncoding {
+  /// Names of the encodings accepted by `from_str`.
+  pub const NAMES: [&'static str; 2] = ["little-endian", "ordered"];
+}
+
+impl FromStr for ValEncoding {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "little-endian" => Ok(ValEncoding::LittleEndian),
+      "ordered" => Ok(ValEncoding::Ordered),
+      _ => Err(format!(
+        "Unknown encoding '{}'. Possible values: {}",
+        s,
+        ValEncoding::NAMES.join(", ")
+      )),
+    }
+  }
+}
+
+impl Display for ValEncoding {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      ValEncoding::LittleEndian => "little-endian",
+      ValEncoding::Ordered => "ordered",
+    })
+  }
+}
+
+/// Transformation of the (little-endian) bytes written by a codec into bytes comparable with
+/// `memcmp`, i.e. ordered the same way as the values they encode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderedKind {
+  /// Unsigned integers: big-endian
+  Unsigned,
+  /// Two's complement integers: big-endian, with the sign bit flipped
+  Signed,
+  /// IEEE floats: big-endian, with the sign bit flipped for positive values and all bits
+  /// flipped for negative ones (`-0.0` being written as `0.0`)
+  Float,
+  /// Bytes already comparable with `memcmp` (e.g. null padded UTF-8 strings)
+  Bytes,
+}
+
+impl OrderedKind {
+  /// Transforms in place the bytes written by a codec.
+  pub fn encode(&self, bytes: &mut [u8]) {
+    match self {
+      OrderedKind::Unsigned => bytes.reverse(),
+      OrderedKind::Signed => {
+        bytes.reverse();
+        bytes[0] ^= 0x80;
+      }
+      OrderedKind::Float => {
+        bytes.reverse();
+        if bytes[0] == 0x80 && bytes[1..].iter().all(|b| *b == 0) {
+          // -0.0 == 0.0
+          bytes[0] = 0;
+        }
+        if bytes[0] & 0x80 == 0 {
+          bytes[0] ^= 0x80;
+        } else {
+          bytes.iter_mut().for_each(|b| *b = !*b);
+        }
+      }
+      OrderedKind::Bytes => {}
+    }
+  }
+
+  /// Inverse of `encode`.
+  pub fn decode(&self, bytes: &mut [u8]) {
+    match self {
+      OrderedKind::Unsigned => bytes.reverse(),
+      OrderedKind::Signed => {
+        bytes[0] ^= 0x80;
+        bytes.reverse();
+      }
+      OrderedKind::Float => {
+        if bytes[0] & 0x80 != 0 {
+          bytes[0] ^= 0x80;
+        } else {
+          bytes.iter_mut().for_each(|b| *b = !*b);
+        }
+        bytes.reverse();
+      }
+      OrderedKind::Bytes => {}
+    }
+  }
+}
+
+/// Value codec applying the encoding of a tree file to the bytes of the given codec.
+/// Both the tree building and the queries must use it.
+#[derive(Clone)]
+pub struct EncodedRW<RW: ReadWrite> {
+  rw: RW,
+  /// `None` for the little-endian encoding, or if the bytes are already ordered
+  ordered: Option<OrderedKind>,
+}
+
+/// Maximum number of bytes of the elements whose bytes have to be transformed (i.e. numbers)
+const MAX_ORDERED_BYTE_SIZE: usize = 8;
+
+impl<RW: ReadWrite> EncodedRW<RW> {
+  pub fn new(rw: RW, encoding: ValEncoding) -> Result<Self, Error> {
+    let ordered = match encoding {
+      ValEncoding::LittleEndian => None,
+      ValEncoding::Ordered => match rw.ordered_kind() {
+        Some(OrderedKind::Bytes) => None,
+        Some(kind) if rw.n_bytes() <= MAX_ORDERED_BYTE_SIZE => Some(kind),
+        _ => {
+          return Err(Error::new(
+            ErrorKind::Other,
+            "The ordered encoding is not supported by the value type",
+          ))
+        }
+      },
+    };
+    Ok(EncodedRW { rw, ordered })
+  }
+
+  /// Writes the given value in the given buffer, and encodes it.
+  fn encode<'a>(
+    &self,
+    kind: &OrderedKind,
+    val: &RW::Type,
+    buf: &'a mut [u8; MAX_ORDERED_BYTE_SIZE],
+  ) -> Result<&'a [u8], Error> {
+    let buf = &mut buf[..self.rw.n_bytes()];
+    self.rw.write(&mut &mut buf[..], val)?;
+    kind.encode(buf);
+    Ok(buf)
+  }
+}
+
+impl<RW: ReadWrite> ReadWrite for EncodedRW<RW> {
+  type Type = RW::Type;
+  fn n_bytes(&self) -> usize {
+    self.rw.n_bytes()
+  }
+  fn read<R: Read>(&self, reader: &mut R) -> Result<Self::Type, Error> {
+    match &self.ordered {
+      None => self.rw.read(reader),
+      Some(kind) => {
+        let mut buf = [0_u8; MAX_ORDERED_BYTE_SIZE];
+        let buf = &mut buf[..self.rw.n_bytes()];
+        reader.read_exact(buf)?;
+        kind.decode(buf);
+        self.rw.read(&mut &buf[..])
+      }
+    }
+  }
+  fn write<W: Write>(&self, writer: &mut W, val: &Self::Type) -> Result<(), Error> {
+    match &self.ordered {
+      None => self.rw.write(writer, val),
+      Some(kind) => {
+        let mut buf = [0_u8; MAX_ORDERED_BYTE_SIZE];
+        writer.write_all(self.encode(kind, val, &mut buf)?)
+      }
+    }
+  }
+  /// With the ordered encoding, compares the raw bytes with `memcmp`.
+  /// A value which can't be encoded (e.g. a query value out of the range of a narrow integer
+  /// type) is compared to the decoded raw bytes.
+  fn cmp_raw(&self, raw: &[u8], val: &Self::Type) -> Result<Ordering, Error>
+  where
+    Self::Type: Ord,
+  {
+    match &self.ordered {
+      None => self.rw.cmp_raw(raw, val),
+      Some(kind) if self.rw.fits(val) => {
+        let mut buf = [0_u8; MAX_ORDERED_BYTE_SIZE];
+        let encoded = self.encode(kind, val, &mut buf)?;
+        Ok(raw[..encoded.len()].cmp(encoded))
+      }
+      Some(kind) => {
+        let mut buf = [0_u8; MAX_ORDERED_BYTE_SIZE];
+        let buf = &mut buf[..self.rw.n_bytes()];
+        buf.copy_from_slice(&raw[..buf.len()]);
+        kind.decode(buf);
+        self.rw.cmp_raw(buf, val)
+      }
+    }
+  }
+  /// `None` for a value which can't be encoded, see `cmp_raw`.
+  fn memcmp_key(&self, val: &Self::Type) -> Result<Option<Vec<u8>>, Error> {
+    match &self.ordered {
+      None => self.rw.memcmp_key(val),
+      Some(_) if !self.rw.fits(val) => Ok(None),
+      Some(kind) => {
+        let mut buf = [0_u8; MAX_ORDERED_BYTE_SIZE];
+        self.encode(kind, val, &mut buf).map(|encoded| Some(encoded.to_vec()))
+      }
+    }
+  }
+  fn is_str(&self) -> bool {
+    self.rw.is_str()
+  }
+  fn fits(&self, val: &Self::Type) -> bool {
+    self.rw.fits(val)
+  }
+  fn ordered_kind(&self) -> Option<OrderedKind> {
+    match &self.ordered {
+      None => self.rw.ordered_kind(),
+      Some(_) => Some(OrderedKind::Bytes),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn testok_truncate_str() {
+    assert_eq!(truncate_str("abc", 4), "abc");
+    assert_eq!(truncate_str("abcdef", 4), "abcd");
+    // 'é' is encoded on 2 bytes: it is not cut in the middle
+    assert_eq!(truncate_str("abcé", 4), "abc");
+    assert_eq!(truncate_str("é", 1), "");
+    let str_rw = StrRW { n_bytes: 4 };
+    let mut buff = Vec::new();
+    str_rw.write(&mut buff, &String::from("abcé")).unwrap();
+    assert_eq!(buff, b"abc\0");
+    assert_eq!(str_rw.read(&mut buff.as_slice()).unwrap(), "abc");
+  }
+
+  #[test]
+  fn testerr_narrow_int_overflow() {
+    use crate::FromU64;
+    assert!(U24RW.fits(&((1 << 24) - 1)));
+    assert!(!U24RW.fits(&(1 << 24)));
+    assert!(I24RW.fits(&-(1 << 23)));
+    assert!(!I24RW.fits(&-((1 << 23) + 1)));
+    assert!(!I24RW.fits(&(1 << 23)));
+    assert!(!U40RW.fits(&(1 << 40)));
+    // An error instead of a panic
+    let mut buff = Vec::new();
+    assert!(U24RW.write(&mut buff, &(1 << 24)).is_err());
+    assert!(I24RW.write(&mut buff, &(1 << 23)).is_err());
+    assert!(buff.is_empty());
+    // Record numbers do not wrap
+    assert_eq!(u32::try_from_u64(u32::MAX as u64), Some(u32::MAX));
+    assert_eq!(u32::try_from_u64(u32::MAX as u64 + 1), None);
+  }
+
+  #[test]
+  fn testok_cmp_raw() {
+    use std::fmt::Debug;
+    // `cmp_raw`, and `memcmp_key` if supported, must give the same result as reading the
+    // value and comparing it
+    fn assert_cmp<T: Ord + Debug, RW: ReadWrite<Type = T>>(rw: &RW, stored: &[T], vals: &[T]) {
+      for s in stored {
+        let mut raw = Vec::new();
+        rw.write(&mut raw, s).unwrap();
+        for v in vals {
+          assert_eq!(rw.cmp_raw(&raw, v).unwrap(), s.cmp(v), "{:?} vs {:?}", s, v);
+          if let Some(key) = rw.memcmp_key(v).unwrap() {
+            assert_eq!(raw.as_slice().cmp(&key), s.cmp(v), "{:?} vs {:?}", s, v);
+          }
+        }
+      }
+    }
+    let ints = [-(1 << 23), -1, 0, 1, (1 << 23) - 1];
+    assert_cmp(&I24RW, &ints, &ints);
+    let uints = [0, 1, 255, 256, (1 << 40) - 1];
+    assert_cmp(&U40RW, &uints, &uints);
+    let ints = [-(1 << 39), -256, -1, 0, 1, 256];
+    assert_cmp(&VarI64RW { n_bytes: 5 }, &ints, &ints);
+    let floats: Vec<FiniteFloat<f32>> = [-1.5, -0.0, 0.0, 1e-3, 2.5]
+      .into_iter()
+      .map(|f| FiniteFloat::new(f).unwrap())
+      .collect();
+    assert_cmp(&F32RW, &floats, &floats);
+    // Ordered encoding
+    let ordered = |rw| EncodedRW::new(rw, ValEncoding::Ordered).unwrap();
+    assert_cmp(&ordered(VarI64RW { n_bytes: 5 }), &ints, &ints);
+    assert!(ordered(VarI64RW { n_bytes: 5 }).memcmp_key(&1).unwrap().is_some());
+    assert_cmp(&EncodedRW::new(F32RW, ValEncoding::Ordered).unwrap(), &floats, &floats);
+    let strs: Vec<String> = ["", "a", "ab", "abc", "abcd", "b", "é"]
+      .into_iter()
+      .map(String::from)
+      .collect();
+    // "abcd" is longer than the 3 bytes, it is stored truncated
+    assert_cmp(&StrRW { n_bytes: 3 }, &strs[..4], &strs);
+    assert_cmp(&StrRW { n_bytes: 3 }, &strs[5..], &strs);
+  }
+}
@@ -0,0 +1,318 @@
+//! Sources of the raw bytes of a tree file.
+//!
+//! The read-only traversal of a tree (see `SubTreeR` and `SubTreeRank`) works on blocks of
+//! bytes of the file.
+//! A block is only a (lightweight) handle on a range of bytes: it is split into sub-blocks
+//! while going down the tree, and its content is actually read only when needed, i.e. when
+//! reading the entries of a L1 page (or of the `ld` part of a LD page).
+//! Two sources are provided:
+//! * `MmapSource`: the file is memory mapped, a block is a simple slice (no copy);
+//! * `PreadSource`: blocks are read with positioned reads (`pread`), the last read blocks being
+//!   kept in a small cache. It is useful when memory mapping a (possibly very large) file is not
+//!   possible or not desirable (network filesystems, 32 bit environments, ...).
+
+use std::{
+  collections::VecDeque,
+  fs::File,
+  io::Error,
+  ops::{Bound, Deref, RangeBounds},
+  path::Path,
+  sync::{Arc, Mutex},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use memmap::{Mmap, MmapOptions};
+
+/// Default size, in bytes, of the cache of a `PreadSource` (1 MB).
+pub const DEFAULT_CACHE_BYTE_SIZE: usize = 1 << 20;
+
+/// Source of the bytes of a tree file.
+/// A source is shared (read-only) by the threads querying a same tree.
+pub trait BlockSource: Sync {
+  /// Type of the handle on a range of bytes of the source.
+  type Block<'a>: RawBlock
+  where
+    Self: 'a;
+
+  /// Total number of bytes in the source.
+  fn byte_size(&self) -> usize;
+
+  /// Returns a handle on the `len` bytes starting at `offset`.
+  fn block(&self, offset: usize, len: usize) -> Self::Block<'_>;
+}
+
+/// Handle on a range of bytes of a `BlockSource`.
+pub trait RawBlock: Copy {
+  /// Content of the block.
+  type Bytes: Deref<Target = [u8]>;
+
+  /// Number of bytes in the block.
+  fn len(&self) -> usize;
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Divides the block into two at the given index (same as `slice::split_at`).
+  fn split_at(self, mid: usize) -> (Self, Self);
+
+  /// Returns the sub-block corresponding to the given range, relative to the start of this block.
+  fn slice<R: RangeBounds<usize>>(self, range: R) -> Self;
+
+  /// Reads the content of the block.
+  fn bytes(self) -> Result<Self::Bytes, Error>;
+}
+
+impl<'a> RawBlock for &'a [u8] {
+  type Bytes = &'a [u8];
+
+  fn len(&self) -> usize {
+    <[u8]>::len(self)
+  }
+
+  fn split_at(self, mid: usize) -> (Self, Self) {
+    <[u8]>::split_at(self, mid)
+  }
+
+  fn slice<R: RangeBounds<usize>>(self, range: R) -> Self {
+    &self[(range.start_bound().cloned(), range.end_bound().cloned())]
+  }
+
+  fn bytes(self) -> Result<Self::Bytes, Error> {
+    Ok(self)
+  }
+}
+
+/// Memory mapped file.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct MmapSource(Mmap);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MmapSource {
+  /// Memory map the given (non-empty) file.
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<MmapSource, Error> {
+    let file = File::open(path)?;
+    unsafe { MmapOptions::new().map(&file) }.map(MmapSource)
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Deref for MmapSource {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BlockSource for MmapSource {
+  type Block<'a> = &'a [u8];
+
+  fn byte_size(&self) -> usize {
+    self.0.len()
+  }
+
+  fn block(&self, offset: usize, len: usize) -> Self::Block<'_> {
+    &self.0[offset..offset + len]
+  }
+}
+
+/// File read using positioned reads, the last read blocks being kept in a LRU cache.
+#[cfg(any(unix, windows))]
+pub struct PreadSource {
+  file: File,
+  byte_size: usize,
+  cache: Mutex<BlockCache>,
+}
+
+#[cfg(any(unix, windows))]
+impl PreadSource {
+  /// Open the given file.
+  /// # Args
+  /// * `cache_byte_size`: maximum number of bytes kept in the cache of the last read blocks
+  pub fn open<P: AsRef<Path>>(path: P, cache_byte_size: usize) -> Result<PreadSource, Error> {
+    let file = File::open(path)?;
+    let byte_size = file.metadata()?.len() as usize;
+    Ok(PreadSource {
+      file,
+      byte_size,
+      cache: Mutex::new(BlockCache::new(cache_byte_size)),
+    })
+  }
+
+  fn read(&self, offset: usize, len: usize) -> Result<Arc<[u8]>, Error> {
+    if let Some(bytes) = self.cache.lock().unwrap().get(offset, len) {
+      return Ok(bytes);
+    }
+    let mut buff = vec![0_u8; len];
+    read_exact_at(&self.file, &mut buff, offset as u64)?;
+    let bytes: Arc<[u8]> = buff.into();
+    self
+      .cache
+      .lock()
+      .unwrap()
+      .insert(offset, len, bytes.clone());
+    Ok(bytes)
+  }
+}
+
+#[cfg(any(unix, windows))]
+impl BlockSource for PreadSource {
+  type Block<'a> = PreadBlock<'a>;
+
+  fn byte_size(&self) -> usize {
+    self.byte_size
+  }
+
+  fn block(&self, offset: usize, len: usize) -> Self::Block<'_> {
+    assert!(offset + len <= self.byte_size);
+    PreadBlock {
+      source: self,
+      offset,
+      len,
+    }
+  }
+}
+
+/// Range of bytes of a `PreadSource`, read only when its content is required.
+#[cfg(any(unix, windows))]
+#[derive(Clone, Copy)]
+pub struct PreadBlock<'a> {
+  source: &'a PreadSource,
+  offset: usize,
+  len: usize,
+}
+
+#[cfg(any(unix, windows))]
+impl<'a> RawBlock for PreadBlock<'a> {
+  type Bytes = Arc<[u8]>;
+
+  fn len(&self) -> usize {
+    self.len
+  }
+
+  fn split_at(self, mid: usize) -> (Self, Self) {
+    assert!(mid <= self.len);
+    (self.slice(..mid), self.slice(mid..))
+  }
+
+  fn slice<R: RangeBounds<usize>>(self, range: R) -> Self {
+    let from = match range.start_bound() {
+      Bound::Included(&i) => i,
+      Bound::Excluded(&i) => i + 1,
+      Bound::Unbounded => 0,
+    };
+    let to = match range.end_bound() {
+      Bound::Included(&i) => i + 1,
+      Bound::Excluded(&i) => i,
+      Bound::Unbounded => self.len,
+    };
+    assert!(from <= to && to <= self.len);
+    PreadBlock {
+      source: self.source,
+      offset: self.offset + from,
+      len: to - from,
+    }
+  }
+
+  fn bytes(self) -> Result<Self::Bytes, Error> {
+    self.source.read(self.offset, self.len)
+  }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buff: &mut [u8], offset: u64) -> Result<(), Error> {
+  use std::os::unix::fs::FileExt;
+  file.read_exact_at(buff, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buff: &mut [u8], mut offset: u64) -> Result<(), Error> {
+  use std::{io::ErrorKind, os::windows::fs::FileExt};
+  while !buff.is_empty() {
+    match file.seek_read(buff, offset) {
+      Ok(0) => return Err(Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer")),
+      Ok(n) => {
+        buff = &mut buff[n..];
+        offset += n as u64;
+      }
+      Err(e) if e.kind() == ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+  Ok(())
+}
+
+/// Least recently used blocks, identified by their offset and length.
+/// The number of blocks is small, so a linear search is enough.
+struct BlockCache {
+  /// Maximum number of bytes in the cache
+  capacity: usize,
+  /// Current number of bytes in the cache
+  byte_size: usize,
+  /// Blocks, from the most to the least recently used
+  blocks: VecDeque<(usize, usize, Arc<[u8]>)>,
+}
+
+impl BlockCache {
+  fn new(capacity: usize) -> BlockCache {
+    BlockCache {
+      capacity,
+      byte_size: 0,
+      blocks: VecDeque::new(),
+    }
+  }
+
+  fn get(&mut self, offset: usize, len: usize) -> Option<Arc<[u8]>> {
+    let i = self
+      .blocks
+      .iter()
+      .position(|(o, l, _)| *o == offset && *l == len)?;
+    let block = self.blocks.remove(i)?;
+    let bytes = block.2.clone();
+    self.blocks.push_front(block);
+    Some(bytes)
+  }
+
+  fn insert(&mut self, offset: usize, len: usize, bytes: Arc<[u8]>) {
+    // The block may have been inserted by a concurrent reader since our call to `get`
+    if len > self.capacity || self.get(offset, len).is_some() {
+      return;
+    }
+    while self.byte_size + len > self.capacity {
+      match self.blocks.pop_back() {
+        Some((_, l, _)) => self.byte_size -= l,
+        None => break,
+      }
+    }
+    self.byte_size += len;
+    self.blocks.push_front((offset, len, bytes));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn testok_block_cache_insert() {
+    let mut cache = BlockCache::new(10);
+    let block = |len: usize| -> Arc<[u8]> { vec![0_u8; len].into() };
+    cache.insert(0, 4, block(4));
+    cache.insert(4, 4, block(4));
+    // Same block inserted twice (e.g. read concurrently by two threads)
+    cache.insert(0, 4, block(4));
+    assert_eq!(cache.byte_size, 8);
+    assert_eq!(cache.blocks.len(), 2);
+    // The block (0, 4) is the most recently used, so (4, 4) is evicted
+    cache.insert(8, 2, block(2));
+    cache.insert(10, 2, block(2));
+    assert_eq!(cache.byte_size, 8);
+    assert!(cache.get(0, 4).is_some());
+    assert!(cache.get(4, 4).is_none());
+    // Blocks larger than the cache are not kept
+    cache.insert(12, 11, block(11));
+    assert!(cache.get(12, 11).is_none());
+  }
+}
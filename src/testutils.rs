@@ -0,0 +1,28 @@
+//! Helpers shared by the unit tests of the different modules.
+
+use std::path::{Path, PathBuf};
+
+/// Directory created in the system temporary directory and removed, with its content, when
+/// dropped, i.e. also when the test using it panics.
+pub struct TmpDir(PathBuf);
+
+impl TmpDir {
+  /// The name must be unique among the tests, since they run in parallel in the same process.
+  pub fn new(name: &str) -> Self {
+    let dir = std::env::temp_dir().join(format!("bstree_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    Self(dir)
+  }
+
+  /// Returns the path of the given file in this directory.
+  pub fn join<P: AsRef<Path>>(&self, file_name: P) -> PathBuf {
+    self.0.join(file_name)
+  }
+}
+
+impl Drop for TmpDir {
+  fn drop(&mut self) {
+    // Errors ignored, not to hide the panic of a failed test
+    let _ = std::fs::remove_dir_all(&self.0);
+  }
+}
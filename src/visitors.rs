@@ -11,7 +11,7 @@ pub trait Visitor {
 
   fn center(&self) -> &Self::V;
 
-  /// Returns `true` if the visitor intersects the given range.
+  // Returns `true` if the visitor intersects the given range.
   // fn intersects(&self, from: Self::V, to: Self::V) -> bool;
 
   /// Visit the given entry
@@ -45,7 +45,7 @@ where
   U: Ord,
 {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-    self.distance.partial_cmp(&other.distance)
+    Some(self.cmp(other))
   }
 }
 